@@ -9,6 +9,6 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn hello(req: Request) -> Response {
+async fn hello(_req: Request) -> Response {
     Response::default()
 }
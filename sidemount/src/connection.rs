@@ -0,0 +1,19 @@
+use std::net::SocketAddr;
+
+/// Information about the connection a request was received on.
+///
+/// The TLS details are only populated for connections accepted by a TLS
+/// listener and are `None` for plain TCP connections.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionInfo {
+    /// The address of the remote peer.
+    pub remote_addr: Option<SocketAddr>,
+    /// The local address the connection was accepted on.
+    pub local_addr: Option<SocketAddr>,
+    /// The SNI server name sent by the client during the TLS handshake.
+    pub server_name: Option<String>,
+    /// The ALPN protocol negotiated during the TLS handshake, e.g. `h2`.
+    pub alpn_protocol: Option<Vec<u8>>,
+    /// The negotiated TLS protocol version, e.g. `TLSv1_3`.
+    pub tls_version: Option<&'static str>,
+}
@@ -0,0 +1,104 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+pub use cookie::SameSite;
+use hyper::header::{self, HeaderMap};
+
+/// Parsed request cookies cached in the request extensions.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Cookies(pub HashMap<String, String>);
+
+/// Parses every `Cookie` header into a map of cookie names to values.
+///
+/// Names and values are percent-decoded, as [Cookie] encodes them, and values
+/// wrapped in double quotes are unquoted per RFC 6265. When the same cookie
+/// name is sent more than once the first occurrence wins.
+pub(crate) fn parse(headers: &HeaderMap) -> Cookies {
+    let mut cookies = HashMap::new();
+    for value in headers.get_all(header::COOKIE) {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        for cookie in cookie::Cookie::split_parse_encoded(value).flatten() {
+            cookies
+                .entry(cookie.name().to_owned())
+                .or_insert_with(|| cookie.value_trimmed().to_owned());
+        }
+    }
+    Cookies(cookies)
+}
+
+/// A cookie to set on the client with [crate::Response::set_cookie], encoded
+/// by the `cookie` crate.
+///
+/// ## Examples
+/// ```rust
+/// use std::time::Duration;
+/// use sidemount::{Cookie, SameSite};
+///
+/// let cookie = Cookie::new("theme", "dark")
+///     .path("/")
+///     .http_only(true)
+///     .secure(true)
+///     .same_site(SameSite::Lax)
+///     .max_age(Duration::from_secs(3600));
+/// assert_eq!(
+///     cookie.to_string(),
+///     "theme=dark; HttpOnly; SameSite=Lax; Secure; Path=/; Max-Age=3600"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cookie(cookie::Cookie<'static>);
+
+impl Cookie {
+    /// Creates a session cookie with the name and value and no attributes.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Cookie(cookie::Cookie::new(name.into(), value.into()))
+    }
+
+    /// Sets the `Path` the cookie is sent for.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.0.set_path(path.into());
+        self
+    }
+
+    /// Sets the `Domain` the cookie is sent to.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.0.set_domain(domain.into());
+        self
+    }
+
+    /// Sets whether the cookie is hidden from scripts with `HttpOnly`.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.0.set_http_only(http_only);
+        self
+    }
+
+    /// Sets whether the cookie is only sent over HTTPS with `Secure`.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.0.set_secure(secure);
+        self
+    }
+
+    /// Sets the `SameSite` policy of the cookie.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.0.set_same_site(same_site);
+        self
+    }
+
+    /// Sets the `Max-Age` after which the client discards the cookie.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        let max_age =
+            cookie::time::Duration::try_from(max_age).unwrap_or(cookie::time::Duration::MAX);
+        self.0.set_max_age(max_age);
+        self
+    }
+}
+
+impl fmt::Display for Cookie {
+    /// Encodes the cookie as the value of a `Set-Cookie` header, with its
+    /// name and value percent-encoded so they cannot add attributes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.encoded().fmt(f)
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use hyper::header;
+use hyper::StatusCode;
+
+use crate::{Handler, Request, Response};
+
+/// Handler serving the files of a directory, mapping the catch-all param of
+/// the route onto a path under the root.
+///
+/// Files are streamed with the `Content-Type` guessed from their extension
+/// along with `Content-Length` and `Last-Modified`, and a request with an
+/// `If-Modified-Since` date no older than the file gets a `304 Not Modified`.
+/// Missing files, directories and paths resolving outside of the root, such as
+/// through `../` or a symlink, all get a `404 Not Found`.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::fs::ServeDir;
+///
+/// let mut app = sidemount::new();
+/// app.at("/static/{*path}").get(ServeDir::new("public"));
+/// app.at("/assets/{*file}").get(ServeDir::new("assets").param("file"));
+/// ```
+pub struct ServeDir {
+    root: PathBuf,
+    param: String,
+}
+
+impl ServeDir {
+    /// Creates a handler serving the files under the root from the `path`
+    /// param of the route.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        ServeDir {
+            root: root.into(),
+            param: String::from("path"),
+        }
+    }
+
+    /// Sets the name of the param holding the path of the file to serve.
+    pub fn param(mut self, name: &str) -> Self {
+        self.param = String::from(name);
+        self
+    }
+
+    /// Resolves the requested path to a file under the root, following any
+    /// `..` and symlinks before checking it did not escape the root.
+    async fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let root = tokio::fs::canonicalize(&self.root).await.ok()?;
+        let path = tokio::fs::canonicalize(root.join(path.trim_start_matches('/')))
+            .await
+            .ok()?;
+        path.starts_with(&root).then_some(path)
+    }
+}
+
+#[async_trait]
+impl Handler for ServeDir {
+    async fn call(&self, req: Request) -> Response {
+        let path = match req.param(&self.param) {
+            Some(path) => self.resolve(path).await,
+            None => None,
+        };
+        let path = match path {
+            Some(path) => path,
+            None => return StatusCode::NOT_FOUND.into(),
+        };
+
+        let metadata = match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => return StatusCode::NOT_FOUND.into(),
+        };
+        if let Ok(modified) = metadata.modified() {
+            if not_modified_since(&req, modified) {
+                let mut res = Response::from(StatusCode::NOT_MODIFIED);
+                if let Ok(value) = httpdate::fmt_http_date(modified).parse() {
+                    res.headers_mut().insert(header::LAST_MODIFIED, value);
+                }
+                return res;
+            }
+        }
+
+        match Response::file(&path).await {
+            Ok(res) => res,
+            Err(_) => StatusCode::NOT_FOUND.into(),
+        }
+    }
+}
+
+/// Determines if the file was not modified since the `If-Modified-Since` date
+/// of the request, comparing whole seconds as HTTP dates have no fractions.
+fn not_modified_since(req: &Request, modified: SystemTime) -> bool {
+    let since = match req
+        .header(header::IF_MODIFIED_SINCE)
+        .and_then(|since| httpdate::parse_http_date(since).ok())
+    {
+        Some(since) => since,
+        None => return false,
+    };
+    let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).map(|d| d.as_secs());
+    match (secs(modified), secs(since)) {
+        (Ok(modified), Ok(since)) => modified <= since,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::http;
+
+    async fn get(dir: &ServeDir, path: &str, headers: &[(&str, &str)]) -> http::Response {
+        let mut req = hyper::Request::get("/");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let params = HashMap::from([(String::from("path"), String::from(path))]);
+        let req = Request::new(req.body(hyper::Body::empty()).unwrap(), params);
+        dir.call(req).await.into()
+    }
+
+    #[tokio::test]
+    async fn test_serve_dir() {
+        let base = std::env::temp_dir().join(format!("sidemount-fs-{}", std::process::id()));
+        let root = base.join("public");
+        std::fs::create_dir_all(root.join("css")).unwrap();
+        std::fs::write(root.join("css/site.css"), "body {}").unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+        let dir = ServeDir::new(&root);
+
+        let res = get(&dir, "css/site.css", &[]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/css");
+        let modified = res.headers()[header::LAST_MODIFIED]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"body {}");
+
+        let res = get(&dir, "css/site.css", &[("if-modified-since", &modified)]).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        let stale = "Thu, 01 Jan 1970 00:00:00 GMT";
+        let res = get(&dir, "css/site.css", &[("if-modified-since", stale)]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = get(&dir, "css/missing.css", &[]).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = get(&dir, "css", &[]).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = get(&dir, "../secret.txt", &[]).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = get(&dir, "css/../../secret.txt", &[]).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
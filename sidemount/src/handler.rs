@@ -1,50 +1,146 @@
-use std::future::Future;
+use std::{future::Future, marker::PhantomData, sync::Arc};
 
 use async_trait::async_trait;
 
-use crate::{Request, Response};
+use crate::{IntoResponse, Request, Response};
 
+/// Handles a request, implemented for async functions and closures taking a
+/// [Request] and returning an [IntoResponse].
+///
+/// Routes call handlers through `Arc<dyn Handler>`, which needs the future of
+/// each call boxed: a request costs one allocation for the handler and one per
+/// [crate::Middleware] layer it passes through.
 #[async_trait]
 pub trait Handler: Send + Sync + 'static {
     async fn call(&self, req: Request) -> Response;
 }
 
 #[async_trait]
-impl<F, Fut> Handler for F
+impl<F, Fut, R> Handler for F
 where
     F: Send + Sync + 'static + Fn(Request) -> Fut,
-    Fut: Future<Output = Response> + Send,
+    Fut: Future<Output = R> + Send,
+    R: IntoResponse,
 {
     async fn call(&self, req: Request) -> Response {
-        (self)(req).await
+        (self)(req).await.into_response()
+    }
+}
+
+/// Adapts a synchronous function returning a response into a [Handler], for
+/// handlers that do not await anything. The function runs on the async
+/// runtime, so it must not block.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::{Request, Response, SyncHandler};
+///
+/// fn hello(req: Request) -> Response {
+///     Response::text("hello")
+/// }
+///
+/// let mut app = sidemount::new();
+/// app.at("/hello").get(SyncHandler(hello));
+/// ```
+pub struct SyncHandler<F>(pub F);
+
+#[async_trait]
+impl<F, R> Handler for SyncHandler<F>
+where
+    F: Send + Sync + 'static + Fn(Request) -> R,
+    R: IntoResponse,
+{
+    async fn call(&self, req: Request) -> Response {
+        (self.0)(req).into_response()
+    }
+}
+
+/// A handler given the shared state of type `S` along with the request, see
+/// [crate::Server::route_with_state].
+#[async_trait]
+pub trait StatefulHandler<S>: Send + Sync + 'static {
+    async fn call(&self, req: Request, state: Arc<S>) -> Response;
+}
+
+#[async_trait]
+impl<F, S, Fut, R> StatefulHandler<S> for F
+where
+    F: Send + Sync + 'static + Fn(Request, Arc<S>) -> Fut,
+    S: Send + Sync + 'static,
+    Fut: Future<Output = R> + Send,
+    R: IntoResponse,
+{
+    async fn call(&self, req: Request, state: Arc<S>) -> Response {
+        (self)(req, state).await.into_response()
+    }
+}
+
+/// Adapts a [StatefulHandler] into a [Handler], looking up the state
+/// registered with [crate::Server::with_state] on each call. A request without
+/// the state gets a `500 Internal Server Error`.
+pub struct WithState<H, S> {
+    handler: H,
+    state: PhantomData<fn() -> S>,
+}
+
+impl<H, S> WithState<H, S>
+where
+    H: StatefulHandler<S>,
+    S: Send + Sync + 'static,
+{
+    /// Wraps the stateful handler.
+    pub fn new(handler: H) -> Self {
+        WithState {
+            handler,
+            state: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<H, S> Handler for WithState<H, S>
+where
+    H: StatefulHandler<S>,
+    S: Send + Sync + 'static,
+{
+    async fn call(&self, req: Request) -> Response {
+        match req.state::<S>() {
+            Some(state) => self.handler.call(req, state).await,
+            None => Response::from_error(format!(
+                "No state of type {} registered",
+                std::any::type_name::<S>()
+            )),
+        }
     }
 }
 
 #[async_trait]
-impl<A, B, T, Fut, Fut2> Handler for (A, B)
+impl<A, B, T, Fut, Fut2, R> Handler for (A, B)
 where
     A: Send + Sync + 'static + Fn(Request) -> Fut,
     B: Send + Sync + 'static + Fn(T) -> Fut2,
     Fut: Future<Output = T> + Send,
-    Fut2: Future<Output = Response> + Send,
+    Fut2: Future<Output = R> + Send,
+    R: IntoResponse,
     T: Send,
 {
     async fn call(&self, req: Request) -> Response {
         let (a, b) = self;
         let res = (a)(req).await;
-        (b)(res).await
+        (b)(res).await.into_response()
     }
 }
 
 #[async_trait]
-impl<A, B, C, T, T2, Fut, Fut2, Fut3> Handler for (A, B, C)
+impl<A, B, C, T, T2, Fut, Fut2, Fut3, R> Handler for (A, B, C)
 where
     A: Send + Sync + 'static + Fn(Request) -> Fut,
     B: Send + Sync + 'static + Fn(T) -> Fut2,
     C: Send + Sync + 'static + Fn(T2) -> Fut3,
     Fut: Future<Output = T> + Send,
     Fut2: Future<Output = T2> + Send,
-    Fut3: Future<Output = Response> + Send,
+    Fut3: Future<Output = R> + Send,
+    R: IntoResponse,
     T: Send,
     T2: Send,
 {
@@ -52,12 +148,12 @@ where
         let (a, b, c) = self;
         let res = (a)(req).await;
         let res = (b)(res).await;
-        (c)(res).await
+        (c)(res).await.into_response()
     }
 }
 
 #[async_trait]
-impl<A, B, C, D, T, T2, T3, Fut, Fut2, Fut3, Fut4> Handler for (A, B, C, D)
+impl<A, B, C, D, T, T2, T3, Fut, Fut2, Fut3, Fut4, R> Handler for (A, B, C, D)
 where
     A: Send + Sync + 'static + Fn(Request) -> Fut,
     B: Send + Sync + 'static + Fn(T) -> Fut2,
@@ -66,7 +162,8 @@ where
     Fut: Future<Output = T> + Send,
     Fut2: Future<Output = T2> + Send,
     Fut3: Future<Output = T3> + Send,
-    Fut4: Future<Output = Response> + Send,
+    Fut4: Future<Output = R> + Send,
+    R: IntoResponse,
     T: Send,
     T2: Send,
     T3: Send,
@@ -76,12 +173,12 @@ where
         let res = (a)(req).await;
         let res = (b)(res).await;
         let res = (c)(res).await;
-        (d)(res).await
+        (d)(res).await.into_response()
     }
 }
 
 #[async_trait]
-impl<A, B, C, D, E, T, T2, T3, T4, Fut, Fut2, Fut3, Fut4, Fut5> Handler for (A, B, C, D, E)
+impl<A, B, C, D, E, T, T2, T3, T4, Fut, Fut2, Fut3, Fut4, Fut5, R> Handler for (A, B, C, D, E)
 where
     A: Send + Sync + 'static + Fn(Request) -> Fut,
     B: Send + Sync + 'static + Fn(T) -> Fut2,
@@ -92,7 +189,8 @@ where
     Fut2: Future<Output = T2> + Send,
     Fut3: Future<Output = T3> + Send,
     Fut4: Future<Output = T4> + Send,
-    Fut5: Future<Output = Response> + Send,
+    Fut5: Future<Output = R> + Send,
+    R: IntoResponse,
     T: Send,
     T2: Send,
     T3: Send,
@@ -104,12 +202,12 @@ where
         let res = (b)(res).await;
         let res = (c)(res).await;
         let res = (d)(res).await;
-        (e)(res).await
+        (e)(res).await.into_response()
     }
 }
 
 #[async_trait]
-impl<A, B, C, D, E, F, T, T2, T3, T4, T5, Fut, Fut2, Fut3, Fut4, Fut5, Fut6> Handler
+impl<A, B, C, D, E, F, T, T2, T3, T4, T5, Fut, Fut2, Fut3, Fut4, Fut5, Fut6, R> Handler
     for (A, B, C, D, E, F)
 where
     A: Send + Sync + 'static + Fn(Request) -> Fut,
@@ -123,7 +221,8 @@ where
     Fut3: Future<Output = T3> + Send,
     Fut4: Future<Output = T4> + Send,
     Fut5: Future<Output = T5> + Send,
-    Fut6: Future<Output = Response> + Send,
+    Fut6: Future<Output = R> + Send,
+    R: IntoResponse,
     T: Send,
     T2: Send,
     T3: Send,
@@ -137,6 +236,6 @@ where
         let res = (c)(res).await;
         let res = (d)(res).await;
         let res = (e)(res).await;
-        (f)(res).await
+        (f)(res).await.into_response()
     }
 }
@@ -1,28 +1,55 @@
 #![feature(unboxed_closures)]
-#![feature(fn_traits)]
-#![feature(trait_alias)]
-#![feature(try_trait_v2)]
 
+mod connection;
+#[cfg(feature = "cookies")]
+mod cookies;
+pub mod fs;
 mod handler;
-mod middleware;
+#[cfg(feature = "metrics")]
+mod metrics;
+pub mod middleware;
 mod node;
+mod proxy;
 mod request;
 mod response;
 mod router;
 mod server;
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(feature = "session")]
+pub mod session;
+pub mod sse;
+mod state;
+#[cfg(feature = "tls")]
+mod tls;
 
-pub use handler::Handler;
+pub use connection::ConnectionInfo;
+#[cfg(feature = "cookies")]
+pub use cookies::{Cookie, SameSite};
+pub use handler::{Handler, StatefulHandler, SyncHandler, WithState};
+#[cfg(feature = "metrics")]
+pub use metrics::TaskMonitors;
 pub use middleware::{Middleware, Next};
-pub use node::Node;
-pub use request::Request;
-pub use response::Response;
-pub use router::{Route, RouteResult, Router};
-pub use server::Server;
+pub use node::{Branch, Node, TraceStep};
+#[cfg(feature = "serde")]
+pub use request::QueryError;
+pub use request::{BodyError, ParamError, Request, UnreadBody};
+pub use response::{BodyStats, IntoResponse, Response, StreamBody};
+pub use router::{
+    MatchExplanation, Params, Route, RouteMatch, RouteResult, RouteTable, Router, TrailingSlash,
+};
+pub use server::{BoundServer, Server, ShutdownHandle};
+#[cfg(feature = "tower")]
+pub use service::SidemountService;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
 
 pub mod http {
     pub type Request = hyper::Request<hyper::Body>;
     pub type Response = hyper::Response<hyper::Body>;
     pub type Method = hyper::Method;
+    pub type HeaderMap = hyper::HeaderMap;
+    pub type RequestParts = hyper::http::request::Parts;
 }
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 pub type Method = http::Method;
@@ -31,8 +58,11 @@ pub type Method = http::Method;
 ///
 /// ## Examples
 /// ```ignore
-/// fn index() {}
-/// fn authorize() {}
+/// use sidemount::{Request, Response};
+///
+/// async fn index(req: Request) -> Response {
+///     Response::default()
+/// }
 ///
 /// #[tokio::main]
 /// async fn main() {
@@ -51,7 +81,11 @@ pub fn new() -> Server {
 ///
 /// ## Examples
 /// ```rust
-/// fn index() {}
+/// use sidemount::{Request, Response};
+///
+/// async fn index(req: Request) -> Response {
+///     Response::default()
+/// }
 ///
 /// let mut router = sidemount::router();
 /// router.at("/foo").get(index);
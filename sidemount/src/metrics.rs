@@ -0,0 +1,54 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio_metrics::TaskMonitor;
+
+/// The [tokio_metrics] task monitors of a server, see
+/// [crate::Server::with_task_monitors].
+///
+/// Connection tasks share one monitor, so its scheduling delays show whether
+/// the runtime is starved. Requests are monitored per route pattern, such as
+/// `/users/{id}`, so their poll times show which handlers are slow. Requests
+/// run inside their connection task rather than a task of their own, so their
+/// scheduling delays are reported by the connection monitor.
+#[derive(Default)]
+pub struct TaskMonitors {
+    connections: TaskMonitor,
+    routes: Mutex<HashMap<Arc<str>, TaskMonitor>>,
+}
+
+impl TaskMonitors {
+    /// Gets the monitor of the connection tasks spawned by
+    /// [crate::Server::listen].
+    pub fn connections(&self) -> &TaskMonitor {
+        &self.connections
+    }
+
+    /// Gets the monitor of the requests that matched the route pattern, once
+    /// one has been served.
+    pub fn route(&self, pattern: &str) -> Option<TaskMonitor> {
+        self.routes.lock().unwrap().get(pattern).cloned()
+    }
+
+    /// Gets the monitors of every route that has served a request, by pattern.
+    pub fn routes(&self) -> Vec<(String, TaskMonitor)> {
+        self.routes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(pattern, monitor)| (String::from(&**pattern), monitor.clone()))
+            .collect()
+    }
+
+    /// Gets the monitor of the route pattern, creating it on first use.
+    pub(crate) fn route_monitor(&self, pattern: &Arc<str>) -> TaskMonitor {
+        self.routes
+            .lock()
+            .unwrap()
+            .entry(pattern.clone())
+            .or_default()
+            .clone()
+    }
+}
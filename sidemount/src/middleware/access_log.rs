@@ -0,0 +1,571 @@
+use std::{
+    fmt::Write,
+    net::IpAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use hyper::header::HeaderMap;
+use hyper::{StatusCode, Version};
+
+use crate::{Method, Middleware, Next, Request, Response};
+
+/// The format of the lines written by [AccessLog].
+#[derive(Clone, Copy)]
+pub enum LogFormat {
+    /// The Common Log Format used by most web servers:
+    /// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET / HTTP/1.1" 200 2326`
+    Common,
+    /// The Common Log Format followed by the quoted referer and user agent.
+    Combined,
+    /// The method, path, status and time taken, the default of [Logger]:
+    /// `GET /users/42 200 1.500ms`
+    Short,
+    /// One JSON object per line with the fields selected by
+    /// [AccessLog::fields], for log aggregators.
+    Json,
+    /// A custom function rendering each record into a line.
+    Custom(fn(&AccessRecord) -> String),
+}
+
+/// A field of an [AccessRecord], as written by [LogFormat::Json].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogField {
+    Time,
+    Method,
+    Path,
+    MatchedPattern,
+    Status,
+    LatencyMs,
+    RemoteAddr,
+    UserAgent,
+    Referer,
+    RequestId,
+    Bytes,
+}
+
+/// Everything recorded about a request by [AccessLog].
+#[derive(Clone, Debug)]
+pub struct AccessRecord {
+    /// When the request was received.
+    pub time: SystemTime,
+    pub method: Method,
+    pub path: String,
+    pub version: Version,
+    /// The pattern of the route that handled the request, if any.
+    pub matched_pattern: Option<String>,
+    pub status: StatusCode,
+    /// The time taken to produce the response, not including sending its body.
+    pub latency: Duration,
+    /// The client address, see [Request::remote_addr].
+    pub remote_addr: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    pub referer: Option<String>,
+    /// The `X-Request-Id` of the request, or of the response if the request
+    /// did not have one.
+    pub request_id: Option<String>,
+    /// The `Content-Length` of the response, if known.
+    pub bytes: Option<u64>,
+}
+
+/// A response extension that keeps [AccessLog] from logging the request, such
+/// as for health checks.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::middleware::SkipAccessLog;
+/// use sidemount::{Request, Response};
+///
+/// async fn health(req: Request) -> Response {
+///     let mut res = Response::text("ok");
+///     res.extensions_mut().insert(SkipAccessLog);
+///     res
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct SkipAccessLog;
+
+/// Middleware that logs a line for every request through the [log] crate at
+/// info level, under the `sidemount::access` target, unless the response
+/// carries [SkipAccessLog]. This is the request logger of the crate: the line
+/// holds the method, path, status and, depending on the format, the latency.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::middleware::{AccessLog, LogField, LogFormat};
+///
+/// let mut app = sidemount::new();
+/// app.mount_with_priority(
+///     AccessLog::new(LogFormat::Json).fields(&[LogField::Method, LogField::Path, LogField::Status]),
+///     -100,
+/// );
+/// ```
+pub struct AccessLog {
+    format: LogFormat,
+    fields: Vec<LogField>,
+    sink: Option<Box<dyn Fn(String) + Send + Sync>>,
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        AccessLog::new(LogFormat::Common)
+    }
+}
+
+impl AccessLog {
+    /// Creates new access log middleware writing lines in the given format.
+    pub fn new(format: LogFormat) -> Self {
+        AccessLog {
+            format,
+            fields: vec![
+                LogField::Method,
+                LogField::Path,
+                LogField::MatchedPattern,
+                LogField::Status,
+                LogField::LatencyMs,
+                LogField::RemoteAddr,
+                LogField::UserAgent,
+                LogField::RequestId,
+            ],
+            sink: None,
+        }
+    }
+
+    /// Sets the format of the lines written.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::middleware::{AccessLog, AccessRecord, LogFormat};
+    ///
+    /// fn short(record: &AccessRecord) -> String {
+    ///     format!("{} {} {} {:?}", record.method, record.path, record.status, record.latency)
+    /// }
+    ///
+    /// let mut app = sidemount::new();
+    /// app.mount(AccessLog::default().with_format(LogFormat::Custom(short)));
+    /// ```
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the fields written by [LogFormat::Json], in order.
+    pub fn fields(mut self, fields: &[LogField]) -> Self {
+        self.fields = fields.to_vec();
+        self
+    }
+
+    /// Sets a function receiving each line instead of the [log] crate, such as
+    /// to write the lines to a dedicated file or collect them in tests.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::middleware::AccessLog;
+    ///
+    /// let mut app = sidemount::new();
+    /// app.mount(AccessLog::default().sink(|line| println!("{}", line)));
+    /// ```
+    pub fn sink(mut self, sink: impl Fn(String) + Send + Sync + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Renders the record into a line in the configured format.
+    fn render(&self, record: &AccessRecord) -> String {
+        match self.format {
+            LogFormat::Common => common(record),
+            LogFormat::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                common(record),
+                escape(record.referer.as_deref().unwrap_or("-")),
+                escape(record.user_agent.as_deref().unwrap_or("-"))
+            ),
+            LogFormat::Short => format!(
+                "{} {} {} {:.3}ms",
+                record.method,
+                escape(&record.path),
+                record.status.as_u16(),
+                record.latency.as_secs_f64() * 1000.0
+            ),
+            LogFormat::Json => json(record, &self.fields),
+            LogFormat::Custom(render) => render(record),
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for AccessLog {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        let time = SystemTime::now();
+        let start = Instant::now();
+        let user_agent = header_value(req.headers(), "user-agent");
+        let referer = header_value(req.headers(), "referer");
+        let request_id = header_value(req.headers(), "x-request-id");
+        let method = req.method().clone();
+        let path = String::from(req.path());
+        let version = req.version();
+        let matched_pattern = req.matched_pattern().map(String::from);
+        let remote_addr = req.remote_addr();
+
+        let res = next.run(req).await;
+        if res.extensions().get::<SkipAccessLog>().is_some() {
+            return res;
+        }
+        let record = AccessRecord {
+            time,
+            method,
+            path,
+            version,
+            matched_pattern,
+            status: res.status_code(),
+            latency: start.elapsed(),
+            remote_addr,
+            user_agent,
+            referer,
+            request_id: request_id.or_else(|| header_value(res.headers(), "x-request-id")),
+            bytes: header_value(res.headers(), "content-length").and_then(|len| len.parse().ok()),
+        };
+        let line = self.render(&record);
+        match &self.sink {
+            Some(sink) => sink(line),
+            None => log::info!(target: "sidemount::access", "{}", line),
+        }
+        res
+    }
+}
+
+/// Middleware logging the method, path, status and time taken of every
+/// request, such as `GET /users/42 200 1.500ms`. This is [AccessLog] with
+/// [LogFormat::Short] by default, for apps that want request logging without
+/// choosing a format.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::middleware::{LogFormat, Logger};
+///
+/// let mut app = sidemount::new();
+/// app.mount_with_priority(Logger::new(), -100);
+///
+/// let mut app = sidemount::new();
+/// app.mount(Logger::new().with_format(LogFormat::Combined));
+/// ```
+pub struct Logger(AccessLog);
+
+impl Default for Logger {
+    fn default() -> Self {
+        Logger::new()
+    }
+}
+
+impl Logger {
+    /// Creates a new logger writing lines in [LogFormat::Short].
+    pub fn new() -> Self {
+        Logger(AccessLog::new(LogFormat::Short))
+    }
+
+    /// Sets the format of the lines written, see [AccessLog::with_format].
+    pub fn with_format(self, format: LogFormat) -> Self {
+        Logger(self.0.with_format(format))
+    }
+
+    /// Sets a function receiving each line instead of the [log] crate, see
+    /// [AccessLog::sink].
+    pub fn sink(self, sink: impl Fn(String) + Send + Sync + 'static) -> Self {
+        Logger(self.0.sink(sink))
+    }
+}
+
+#[async_trait]
+impl Middleware for Logger {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        self.0.handle(req, next).await
+    }
+}
+
+/// Gets the header as a string, if present and valid.
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+/// Renders the record in the Common Log Format.
+fn common(record: &AccessRecord) -> String {
+    format!(
+        "{} - - [{}] \"{} {} {:?}\" {} {}",
+        record
+            .remote_addr
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|| String::from("-")),
+        clf_date(record.time),
+        record.method,
+        escape(&record.path),
+        record.version,
+        record.status.as_u16(),
+        record
+            .bytes
+            .map(|bytes| bytes.to_string())
+            .unwrap_or_else(|| String::from("-"))
+    )
+}
+
+/// Escapes a value written between quotes the way Apache does, so it cannot
+/// end the quotes or the line early: `"` and `\` are escaped with a backslash,
+/// and control characters and other bytes outside printable ASCII as `\xNN`.
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_ascii_control() || !c.is_ascii() => {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    let _ = write!(out, "\\x{:02x}", byte);
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Renders the selected fields of the record as a JSON object.
+fn json(record: &AccessRecord, fields: &[LogField]) -> String {
+    let mut line = String::from("{");
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        let (name, value) = match field {
+            LogField::Time => (
+                "time",
+                json_string(Some(&httpdate::fmt_http_date(record.time))),
+            ),
+            LogField::Method => ("method", json_string(Some(record.method.as_str()))),
+            LogField::Path => ("path", json_string(Some(&record.path))),
+            LogField::MatchedPattern => (
+                "matched_pattern",
+                json_string(record.matched_pattern.as_deref()),
+            ),
+            LogField::Status => ("status", record.status.as_u16().to_string()),
+            LogField::LatencyMs => (
+                "latency_ms",
+                format!("{:.3}", record.latency.as_secs_f64() * 1000.0),
+            ),
+            LogField::RemoteAddr => (
+                "remote_addr",
+                json_string(record.remote_addr.map(|addr| addr.to_string()).as_deref()),
+            ),
+            LogField::UserAgent => ("user_agent", json_string(record.user_agent.as_deref())),
+            LogField::Referer => ("referer", json_string(record.referer.as_deref())),
+            LogField::RequestId => ("request_id", json_string(record.request_id.as_deref())),
+            LogField::Bytes => (
+                "bytes",
+                record
+                    .bytes
+                    .map(|bytes| bytes.to_string())
+                    .unwrap_or_else(|| String::from("null")),
+            ),
+        };
+        let _ = write!(line, "\"{}\":{}", name, value);
+    }
+    line.push('}');
+    line
+}
+
+/// Renders the value as a JSON string, or `null` when missing.
+fn json_string(value: Option<&str>) -> String {
+    let value = match value {
+        Some(value) => value,
+        None => return String::from("null"),
+    };
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Formats the time as a Common Log Format date in UTC, such as
+/// `10/Oct/2000:13:55:36 +0000`.
+fn clf_date(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    fn record() -> AccessRecord {
+        AccessRecord {
+            time: UNIX_EPOCH + Duration::from_secs(971_186_136),
+            method: Method::GET,
+            path: String::from("/users/42"),
+            version: Version::HTTP_11,
+            matched_pattern: Some(String::from("/users/{id}")),
+            status: StatusCode::OK,
+            latency: Duration::from_micros(1500),
+            remote_addr: Some([127, 0, 0, 1].into()),
+            user_agent: Some(String::from("curl/8.0 \"test\"")),
+            referer: None,
+            request_id: None,
+            bytes: Some(2326),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let record = record();
+        let common =
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /users/42 HTTP/1.1\" 200 2326";
+        assert_eq!(AccessLog::new(LogFormat::Common).render(&record), common);
+        assert_eq!(
+            AccessLog::new(LogFormat::Combined).render(&record),
+            format!("{} \"-\" \"curl/8.0 \\\"test\\\"\"", common)
+        );
+
+        let forged = AccessRecord {
+            path: String::from("/a\" 200 0\n127.0.0.2"),
+            referer: Some(String::from("caf\u{e9}\\")),
+            ..record.clone()
+        };
+        assert_eq!(
+            AccessLog::new(LogFormat::Combined).render(&forged),
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /a\\\" 200 0\\x0a127.0.0.2 HTTP/1.1\" \
+             200 2326 \"caf\\xc3\\xa9\\\\\" \"curl/8.0 \\\"test\\\"\""
+        );
+        assert_eq!(
+            AccessLog::new(LogFormat::Json).render(&record),
+            "{\"method\":\"GET\",\"path\":\"/users/42\",\"matched_pattern\":\"/users/{id}\",\
+             \"status\":200,\"latency_ms\":1.500,\"remote_addr\":\"127.0.0.1\",\
+             \"user_agent\":\"curl/8.0 \\\"test\\\"\",\"request_id\":null}"
+        );
+        assert_eq!(
+            AccessLog::new(LogFormat::Json)
+                .fields(&[LogField::Status, LogField::Bytes])
+                .render(&record),
+            "{\"status\":200,\"bytes\":2326}"
+        );
+
+        assert_eq!(
+            AccessLog::new(LogFormat::Short).render(&record),
+            "GET /users/42 200 1.500ms"
+        );
+
+        fn custom(record: &AccessRecord) -> String {
+            format!("{} {}", record.status.as_u16(), record.path)
+        }
+        assert_eq!(
+            AccessLog::new(LogFormat::Custom(custom)).render(&record),
+            "200 /users/42"
+        );
+    }
+
+    async fn ok(_req: Request) -> Response {
+        Response::text("ok")
+    }
+
+    /// Runs a request for each path through the middleware.
+    async fn run(log: impl Middleware, paths: &[&str]) {
+        let middleware: Arc<Vec<Arc<dyn Middleware>>> = Arc::new(vec![Arc::new(log)]);
+        for path in paths {
+            let req = hyper::Request::get(*path)
+                .body(hyper::Body::empty())
+                .unwrap();
+            let req = Request::new(req, HashMap::new());
+            Next::new(middleware.clone(), Arc::new(ok)).run(req).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logs_each_request() {
+        fn short(record: &AccessRecord) -> String {
+            format!(
+                "{} {} {}",
+                record.method,
+                record.path,
+                record.status.as_u16()
+            )
+        }
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let log = AccessLog::default()
+            .with_format(LogFormat::Custom(short))
+            .sink({
+                let lines = lines.clone();
+                move |line| lines.lock().unwrap().push(line)
+            });
+        run(log, &["/a", "/b"]).await;
+        assert_eq!(*lines.lock().unwrap(), ["GET /a 200", "GET /b 200"]);
+    }
+
+    #[tokio::test]
+    async fn test_logger() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let logger = Logger::new().sink({
+            let lines = lines.clone();
+            move |line| lines.lock().unwrap().push(line)
+        });
+        run(logger, &["/users/42"]).await;
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("GET /users/42 200 "), "{}", lines[0]);
+        assert!(lines[0].ends_with("ms"), "{}", lines[0]);
+    }
+
+    #[test]
+    fn test_clf_date() {
+        assert_eq!(clf_date(UNIX_EPOCH), "01/Jan/1970:00:00:00 +0000");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(clf_date(leap_day), "29/Feb/2000:00:00:00 +0000");
+    }
+}
@@ -0,0 +1,102 @@
+use std::panic::AssertUnwindSafe;
+
+use async_trait::async_trait;
+use futures::FutureExt;
+use hyper::StatusCode;
+
+use crate::{Middleware, Next, Request, Response};
+
+/// Middleware turning a panic in the middleware and handlers after it into a
+/// `500 Internal Server Error`, so the middleware before it, such as an access
+/// log, still see a response.
+///
+/// The server already answers a panicking request with a 500 rather than
+/// dropping the connection, but only once the panic has unwound through every
+/// middleware. Mounting this with a low priority keeps the rest of the chain
+/// running.
+///
+/// The rest of the chain is wrapped in [AssertUnwindSafe] to catch the panic,
+/// as a [Request] is not [std::panic::UnwindSafe]. State shared across
+/// requests that was being modified when the panic happened, such as data
+/// behind a lock, may be left inconsistent (a [std::sync::Mutex] is poisoned
+/// instead), and a panic with `panic = "abort"` cannot be caught at all.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::middleware::CatchPanic;
+///
+/// let mut app = sidemount::new();
+/// app.mount_with_priority(CatchPanic::new().body("something went wrong"), -10);
+/// ```
+pub struct CatchPanic {
+    body: Option<String>,
+}
+
+impl Default for CatchPanic {
+    fn default() -> Self {
+        CatchPanic::new()
+    }
+}
+
+impl CatchPanic {
+    /// Creates new middleware answering a panic with an empty 500.
+    pub fn new() -> Self {
+        CatchPanic { body: None }
+    }
+
+    /// Sets the plain text body of the 500 response.
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+#[async_trait]
+impl Middleware for CatchPanic {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        if let Ok(res) = AssertUnwindSafe(next.run(req)).catch_unwind().await {
+            return res;
+        }
+        let res = match &self.body {
+            Some(body) => Response::text(body.as_str()),
+            None => Response::default(),
+        };
+        res.status(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn name(&self) -> &'static str {
+        "catch_panic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run;
+
+    async fn panics(_req: Request) -> Response {
+        panic!("something went wrong");
+    }
+
+    async fn ok(_req: Request) -> Response {
+        Response::text("ok")
+    }
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        let res = run(CatchPanic::new(), panics).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        let res = run(CatchPanic::new().body("oops"), panics).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"oops");
+
+        let res = run(CatchPanic::new().body("oops"), ok).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"ok");
+    }
+}
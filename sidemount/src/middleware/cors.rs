@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hyper::header::{self, HeaderValue};
+use hyper::StatusCode;
+
+use crate::{Method, Middleware, Next, Request, Response};
+
+/// The origins allowed by [Cors].
+enum Origins {
+    Any,
+    List(Vec<String>),
+}
+
+/// Middleware answering cross-origin requests from browsers.
+///
+/// A preflight request (an `OPTIONS` request with
+/// `Access-Control-Request-Method`) from an allowed origin is answered with a
+/// `204 No Content` and the `Access-Control-Allow-*` headers, without running
+/// the handler. Other requests from an allowed origin run normally and their
+/// response gets `Access-Control-Allow-Origin`. Requests from other origins,
+/// or without an `Origin` header, pass through untouched, so the browser
+/// blocks the response.
+///
+/// Any origin is allowed by default with `*`. With an allowlist, the origin
+/// of the request is reflected instead, and every response varies on
+/// `Origin`. Credentials are only allowed along with an allowlist: browsers
+/// refuse them with `*`, and reflecting any origin would let every site make
+/// credentialed requests.
+///
+/// ## Examples
+/// ```rust
+/// use std::time::Duration;
+/// use sidemount::middleware::Cors;
+/// use sidemount::Method;
+///
+/// let mut app = sidemount::new();
+/// app.mount_with_priority(
+///     Cors::new()
+///         .allow_origins(&["https://app.example.com"])
+///         .allow_methods(&[Method::GET, Method::POST])
+///         .allow_headers(&["content-type", "authorization"])
+///         .allow_credentials(true)
+///         .max_age(Duration::from_secs(3600)),
+///     -50,
+/// );
+/// ```
+pub struct Cors {
+    origins: Origins,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors::new()
+    }
+}
+
+impl Cors {
+    /// Creates new CORS middleware allowing any origin to make `GET`, `HEAD`
+    /// and `POST` requests without credentials or extra headers.
+    pub fn new() -> Self {
+        Cors {
+            origins: Origins::Any,
+            methods: vec![Method::GET, Method::HEAD, Method::POST],
+            headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Sets the origins allowed, such as `https://app.example.com`, or `*` to
+    /// allow any origin.
+    pub fn allow_origins(mut self, origins: &[&str]) -> Self {
+        self.origins = if origins.contains(&"*") {
+            Origins::Any
+        } else {
+            Origins::List(origins.iter().map(|origin| String::from(*origin)).collect())
+        };
+        self
+    }
+
+    /// Sets the methods allowed in preflight responses.
+    pub fn allow_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    /// Sets the request headers allowed in preflight responses.
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers
+            .iter()
+            .map(|name| name.to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Sets whether requests may carry credentials such as cookies. This has
+    /// no effect unless the allowed origins are set with
+    /// [allow_origins](Cors::allow_origins), without `*`.
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Sets how long browsers may cache preflight responses.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Determines if the origin is allowed.
+    fn allows(&self, origin: &str) -> bool {
+        match &self.origins {
+            Origins::Any => true,
+            Origins::List(origins) => origins.iter().any(|allowed| allowed == origin),
+        }
+    }
+
+    /// Adds the headers shared by preflight and actual responses.
+    fn allow_origin(&self, res: &mut Response, origin: &HeaderValue) {
+        let headers = res.headers_mut();
+        match self.origins {
+            Origins::Any => {
+                headers.insert(
+                    header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                    HeaderValue::from_static("*"),
+                );
+            }
+            Origins::List(_) => {
+                headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+                if self.credentials {
+                    headers.insert(
+                        header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                        HeaderValue::from_static("true"),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Adds `Vary: Origin` when the response depends on the origin, so caches
+    /// do not serve it to other origins, whether or not this one was allowed.
+    fn vary(&self, mut res: Response) -> Response {
+        if matches!(self.origins, Origins::List(_)) {
+            res.add_vary("origin");
+        }
+        res
+    }
+
+    /// Answers a preflight request.
+    fn preflight(&self, origin: &HeaderValue) -> Response {
+        let mut res = Response::from(StatusCode::NO_CONTENT);
+        self.allow_origin(&mut res, origin);
+        let headers = res.headers_mut();
+        let methods = self
+            .methods
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        if let Ok(methods) = HeaderValue::from_str(&methods) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        if !self.headers.is_empty() {
+            if let Ok(allowed) = HeaderValue::from_str(&self.headers.join(", ")) {
+                headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed);
+            }
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+        res
+    }
+}
+
+#[async_trait]
+impl Middleware for Cors {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        let origin = match req.headers().get(header::ORIGIN) {
+            Some(origin) if origin.to_str().is_ok_and(|origin| self.allows(origin)) => {
+                origin.clone()
+            }
+            _ => return self.vary(next.run(req).await),
+        };
+        let preflight = req.method() == Method::OPTIONS
+            && req
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+        if preflight {
+            return self.vary(self.preflight(&origin));
+        }
+
+        let mut res = next.run(req).await;
+        self.allow_origin(&mut res, &origin);
+        self.vary(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::service::Service;
+    use hyper::Body;
+
+    use super::*;
+    use crate::{http, Server};
+
+    async fn users(_req: Request) -> Response {
+        Response::text("users")
+    }
+
+    fn server(cors: Cors) -> Server {
+        let mut server = Server::new();
+        server.mount(cors);
+        server.at("/users").get(users);
+        server
+    }
+
+    fn request(method: Method, origin: Option<&str>) -> http::Request {
+        let mut req = hyper::Request::builder().method(method).uri("/users");
+        if let Some(origin) = origin {
+            req = req.header(header::ORIGIN, origin);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let cors = Cors::new()
+            .allow_origins(&["https://app.example.com"])
+            .allow_methods(&[Method::GET, Method::DELETE])
+            .allow_headers(&["Content-Type"])
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(600));
+        let mut server = server(cors);
+
+        let mut req = request(Method::OPTIONS, Some("https://app.example.com"));
+        req.headers_mut().insert(
+            header::ACCESS_CONTROL_REQUEST_METHOD,
+            HeaderValue::from_static("DELETE"),
+        );
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let headers = res.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, DELETE");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        assert_eq!(headers[header::VARY], "origin");
+
+        let mut req = request(Method::OPTIONS, Some("https://evil.example.com"));
+        req.headers_mut().insert(
+            header::ACCESS_CONTROL_REQUEST_METHOD,
+            HeaderValue::from_static("DELETE"),
+        );
+        let res = server.call(req).await.unwrap();
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(res.headers()[header::VARY], "origin");
+
+        let res = server.call(request(Method::GET, None)).await.unwrap();
+        assert_eq!(res.headers()[header::VARY], "origin");
+    }
+
+    #[tokio::test]
+    async fn test_simple_request() {
+        let mut server = server(Cors::new());
+
+        let res = server
+            .call(request(Method::GET, Some("https://any.example.com")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"users");
+
+        let res = server.call(request(Method::GET, None)).await.unwrap();
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn test_any_origin_refuses_credentials() {
+        let mut server = server(Cors::new().allow_credentials(true));
+
+        let res = server
+            .call(request(Method::GET, Some("https://evil.example.com")))
+            .await
+            .unwrap();
+        let headers = res.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+        assert!(!headers.contains_key(header::VARY));
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use hyper::body::{Bytes, HttpBody};
+use hyper::{Body, HeaderMap, StatusCode};
+
+use crate::{http, Method, Middleware, Next, Request, Response, Result};
+
+/// A response produced for an idempotency key, buffered so it can be replayed.
+#[derive(Clone, Debug)]
+pub struct StoredResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+/// The state of an idempotency key when a request with it begins.
+#[derive(Debug)]
+pub enum Begin {
+    /// The key is new and the request should run, then be completed or aborted.
+    Started,
+    /// A request with the key is still running.
+    InFlight,
+    /// A request with the key already completed with this response.
+    Completed(StoredResponse),
+}
+
+/// A backing store used by [Idempotency] to track requests by key.
+///
+/// Keys are given along with a fingerprint of the request (its method and
+/// path), so a key reused for a different request can be rejected.
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// Begins a request with the key, atomically marking it in flight if new.
+    /// Returns `None` if the key was used for a request with another
+    /// fingerprint.
+    async fn begin(&self, key: &str, fingerprint: &str) -> Result<Option<Begin>>;
+
+    /// Stores the response of the request with the key.
+    async fn complete(&self, key: &str, res: StoredResponse) -> Result<()>;
+
+    /// Forgets the key so the request can be retried.
+    async fn abort(&self, key: &str) -> Result<()>;
+}
+
+enum Entry {
+    InFlight,
+    Completed(StoredResponse, Instant),
+}
+
+/// An in-memory [IdempotencyStore] keeping responses for a time to live.
+///
+/// Keys are lost when the process restarts and are not shared across
+/// processes, so this is best suited to single-instance apps.
+pub struct MemoryIdempotencyStore {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (String, Entry)>>,
+}
+
+impl MemoryIdempotencyStore {
+    /// Creates a new empty in-memory store keeping responses for the time to
+    /// live.
+    pub fn new(ttl: Duration) -> Self {
+        MemoryIdempotencyStore {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn begin(&self, key: &str, fingerprint: &str) -> Result<Option<Begin>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, entry)| match entry {
+            Entry::InFlight => true,
+            Entry::Completed(_, at) => at.elapsed() < self.ttl,
+        });
+        match entries.get(key) {
+            Some((existing, _)) if existing != fingerprint => Ok(None),
+            Some((_, Entry::InFlight)) => Ok(Some(Begin::InFlight)),
+            Some((_, Entry::Completed(res, _))) => Ok(Some(Begin::Completed(res.clone()))),
+            None => {
+                entries.insert(
+                    String::from(key),
+                    (String::from(fingerprint), Entry::InFlight),
+                );
+                Ok(Some(Begin::Started))
+            }
+        }
+    }
+
+    async fn complete(&self, key: &str, res: StoredResponse) -> Result<()> {
+        if let Some((_, entry)) = self.entries.lock().unwrap().get_mut(key) {
+            *entry = Entry::Completed(res, Instant::now());
+        }
+        Ok(())
+    }
+
+    async fn abort(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Aborts the key of a request that never finished, such as one whose handler
+/// panicked or whose future was dropped on a timeout, so it can be retried
+/// instead of being answered with a `409 Conflict` forever.
+struct InFlight {
+    store: Arc<dyn IdempotencyStore>,
+    key: Option<String>,
+}
+
+impl InFlight {
+    /// Marks the request as finished, leaving the key to the store.
+    fn finish(mut self) {
+        self.key = None;
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let key = match self.key.take() {
+            Some(key) => key,
+            None => return,
+        };
+        let store = self.store.clone();
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                if let Err(err) = store.abort(&key).await {
+                    log::error!("Failed to abort idempotent request: {:?}", err);
+                }
+            });
+        }
+    }
+}
+
+/// Middleware that makes requests carrying an `Idempotency-Key` header safe to
+/// retry, such as payment submissions.
+///
+/// The first request with a key runs normally and its response is buffered
+/// and stored; later requests with the same key get the stored response
+/// without running the handler. While the first request is still running,
+/// requests with its key get a `409 Conflict`, and a key reused for another
+/// method or path gets a `422 Unprocessable Entity`. Server errors (`5xx`) are
+/// not stored, so the request can be retried, and neither are requests that
+/// never finish because their handler panicked or timed out.
+///
+/// Responses larger than [Idempotency::max_body_size] (1 MiB by default) and
+/// server-sent event streams are passed through without being stored, so a
+/// retry with their key runs the handler again. Requests without the header,
+/// or with a method in [Idempotency::skip_methods] (`GET`, `HEAD` and
+/// `OPTIONS` by default), pass through untouched.
+///
+/// ## Examples
+/// ```rust
+/// use std::time::Duration;
+/// use sidemount::middleware::{Idempotency, MemoryIdempotencyStore};
+///
+/// let mut app = sidemount::new();
+/// let store = MemoryIdempotencyStore::new(Duration::from_secs(86400));
+/// app.mount_with_priority(Idempotency::new(store), 100);
+/// ```
+pub struct Idempotency {
+    store: Arc<dyn IdempotencyStore>,
+    skip_methods: Vec<Method>,
+    max_body_size: usize,
+}
+
+/// The largest response body [Idempotency] buffers and stores by default, see
+/// [Idempotency::max_body_size].
+const MAX_STORED_BODY: usize = 1024 * 1024;
+
+impl Idempotency {
+    /// Creates new idempotency middleware backed by the given store.
+    pub fn new(store: impl IdempotencyStore) -> Self {
+        Idempotency {
+            store: Arc::new(store),
+            skip_methods: vec![Method::GET, Method::HEAD, Method::OPTIONS],
+            max_body_size: MAX_STORED_BODY,
+        }
+    }
+
+    /// Sets the methods that are never deduplicated, which are already
+    /// idempotent.
+    pub fn skip_methods(mut self, methods: &[Method]) -> Self {
+        self.skip_methods = methods.to_vec();
+        self
+    }
+
+    /// Sets the largest response body, in bytes, that is buffered and stored.
+    /// Larger responses are passed through and their key is released.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+}
+
+#[async_trait]
+impl Middleware for Idempotency {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        let key = match req.headers().get("idempotency-key") {
+            Some(key) if !self.skip_methods.contains(req.method()) => match key.to_str() {
+                Ok(key) => String::from(key),
+                Err(_) => return StatusCode::BAD_REQUEST.into(),
+            },
+            _ => return next.run(req).await,
+        };
+        let fingerprint = format!("{} {}", req.method(), req.path());
+
+        match self.store.begin(&key, &fingerprint).await {
+            Ok(Some(Begin::Started)) => {}
+            Ok(Some(Begin::InFlight)) => return StatusCode::CONFLICT.into(),
+            Ok(Some(Begin::Completed(stored))) => {
+                let mut res = hyper::Response::new(Body::from(stored.body));
+                *res.status_mut() = stored.status;
+                *res.headers_mut() = stored.headers;
+                return res.into();
+            }
+            Ok(None) => return StatusCode::UNPROCESSABLE_ENTITY.into(),
+            Err(err) => {
+                log::error!("Failed to begin idempotent request: {:?}", err);
+                return next.run(req).await;
+            }
+        }
+        let in_flight = InFlight {
+            store: self.store.clone(),
+            key: Some(key.clone()),
+        };
+
+        let res: http::Response = next.run(req).await.into();
+        let (parts, mut body) = res.into_parts();
+        let event_stream = parts
+            .headers
+            .get(hyper::header::CONTENT_TYPE)
+            .is_some_and(|value| value.as_bytes().starts_with(b"text/event-stream"));
+        let mut buffered = Vec::new();
+        let mut oversized = event_stream || body.size_hint().lower() > self.max_body_size as u64;
+        while !oversized {
+            match body.data().await {
+                Some(Ok(chunk)) => {
+                    oversized = buffered.len() + chunk.len() > self.max_body_size;
+                    buffered.extend_from_slice(&chunk);
+                }
+                Some(Err(err)) => {
+                    log::error!("Failed to buffer idempotent response: {:?}", err);
+                    if let Err(err) = self.store.abort(&key).await {
+                        log::error!("Failed to abort idempotent request: {:?}", err);
+                    }
+                    in_flight.finish();
+                    return StatusCode::INTERNAL_SERVER_ERROR.into();
+                }
+                None => break,
+            }
+        }
+
+        if oversized {
+            if let Err(err) = self.store.abort(&key).await {
+                log::error!("Failed to abort idempotent request: {:?}", err);
+            }
+            in_flight.finish();
+            let head = futures::stream::iter(Some(Ok(Bytes::from(buffered))));
+            let body = Body::wrap_stream(head.chain(body));
+            return http::Response::from_parts(parts, body).into();
+        }
+        let body = Bytes::from(buffered);
+
+        let result = if parts.status.is_server_error() {
+            self.store.abort(&key).await
+        } else {
+            let stored = StoredResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+            };
+            self.store.complete(&key, stored).await
+        };
+        if let Err(err) = result {
+            log::error!("Failed to store idempotent response: {:?}", err);
+        }
+        in_flight.finish();
+        http::Response::from_parts(parts, Body::from(body)).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    static CHARGES: AtomicUsize = AtomicUsize::new(0);
+
+    async fn charge(req: Request) -> Response {
+        if let Some(notify) = req.extensions().get::<Arc<Notify>>() {
+            notify.notified().await;
+        }
+        let charges = CHARGES.fetch_add(1, Ordering::SeqCst) + 1;
+        Response::text(format!("charge {}", charges))
+    }
+
+    fn request(method: Method, path: &str, key: &str) -> Request {
+        let req = hyper::Request::builder()
+            .method(method)
+            .uri(path)
+            .header("idempotency-key", key)
+            .body(Body::empty())
+            .unwrap();
+        Request::new(req, HashMap::new())
+    }
+
+    async fn run(middleware: &Arc<Vec<Arc<dyn Middleware>>>, req: Request) -> (StatusCode, String) {
+        let res: http::Response = Next::new(middleware.clone(), Arc::new(charge))
+            .run(req)
+            .await
+            .into();
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_idempotency() {
+        let store = MemoryIdempotencyStore::new(Duration::from_secs(60));
+        let middleware: Arc<Vec<Arc<dyn Middleware>>> =
+            Arc::new(vec![Arc::new(Idempotency::new(store))]);
+
+        let first = run(&middleware, request(Method::POST, "/charges", "a")).await;
+        assert_eq!(first, (StatusCode::OK, String::from("charge 1")));
+        let replay = run(&middleware, request(Method::POST, "/charges", "a")).await;
+        assert_eq!(replay, first);
+        let other = run(&middleware, request(Method::POST, "/charges", "b")).await;
+        assert_eq!(other.1, "charge 2");
+
+        let reused = run(&middleware, request(Method::POST, "/refunds", "a")).await;
+        assert_eq!(reused.0, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let notify = Arc::new(Notify::new());
+        let mut slow = request(Method::POST, "/charges", "c");
+        slow.extensions_mut().insert(notify.clone());
+        let slow = tokio::spawn({
+            let middleware = middleware.clone();
+            async move { run(&middleware, slow).await }
+        });
+        tokio::task::yield_now().await;
+        let conflict = run(&middleware, request(Method::POST, "/charges", "c")).await;
+        assert_eq!(conflict.0, StatusCode::CONFLICT);
+        notify.notify_one();
+        assert_eq!(slow.await.unwrap().1, "charge 3");
+    }
+
+    async fn stall(req: Request) -> Response {
+        if let Some(notify) = req.extensions().get::<Arc<Notify>>() {
+            notify.notified().await;
+        }
+        Response::text("done")
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_not_stored() {
+        let store = MemoryIdempotencyStore::new(Duration::from_secs(60));
+        let middleware: Arc<Vec<Arc<dyn Middleware>>> =
+            Arc::new(vec![Arc::new(Idempotency::new(store).max_body_size(4))]);
+
+        let (status, first) = run(&middleware, request(Method::POST, "/large", "a")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(first.starts_with("charge "));
+        let (status, retry) = run(&middleware, request(Method::POST, "/large", "a")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(retry, first);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_is_aborted() {
+        let store = MemoryIdempotencyStore::new(Duration::from_secs(60));
+        let middleware: Arc<Vec<Arc<dyn Middleware>>> =
+            Arc::new(vec![Arc::new(Idempotency::new(store))]);
+        let run = |req| Next::new(middleware.clone(), Arc::new(stall)).run(req);
+
+        let mut stalled = request(Method::POST, "/charges", "a");
+        stalled.extensions_mut().insert(Arc::new(Notify::new()));
+        let timed_out = tokio::time::timeout(Duration::from_millis(10), run(stalled)).await;
+        assert!(timed_out.is_err());
+        tokio::task::yield_now().await;
+
+        let retry: http::Response = run(request(Method::POST, "/charges", "a")).await.into();
+        assert_eq!(retry.status(), StatusCode::OK);
+    }
+}
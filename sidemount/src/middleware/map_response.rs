@@ -0,0 +1,79 @@
+use async_trait::async_trait;
+
+use crate::{Middleware, Next, Request, Response};
+
+/// Middleware that rewrites every response of the handlers after it, see
+/// [map_response].
+struct MapResponse<F>(F);
+
+#[async_trait]
+impl<F> Middleware for MapResponse<F>
+where
+    F: Fn(Response) -> Response + Send + Sync + 'static,
+{
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        let res = next.run(req).await;
+        (self.0)(res)
+    }
+
+    fn name(&self) -> &'static str {
+        "map_response"
+    }
+}
+
+/// Creates middleware that passes every response of the middleware and
+/// handlers after it through the function, such as to add a header.
+///
+/// Any middleware can do the same by rewriting the response returned by
+/// [Next::run]; this saves declaring a type for the common case.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::middleware::map_response;
+///
+/// let mut app = sidemount::new();
+/// app.mount(map_response(|mut res| {
+///     res.headers_mut()
+///         .insert("x-powered-by", "sidemount".parse().unwrap());
+///     res
+/// }));
+/// ```
+pub fn map_response<F>(f: F) -> impl Middleware
+where
+    F: Fn(Response) -> Response + Send + Sync + 'static,
+{
+    MapResponse(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::StatusCode;
+
+    use super::*;
+    use crate::middleware::run;
+
+    async fn ok(_req: Request) -> Response {
+        Response::text("ok")
+    }
+
+    async fn missing(_req: Request) -> Response {
+        StatusCode::NOT_FOUND.into()
+    }
+
+    #[tokio::test]
+    async fn test_map_response() {
+        let powered_by = || {
+            map_response(|mut res| {
+                res.headers_mut()
+                    .insert("x-powered-by", "sidemount".parse().unwrap());
+                res
+            })
+        };
+
+        let res = run(powered_by(), ok).await;
+        assert_eq!(res.headers()["x-powered-by"], "sidemount");
+        let res = run(powered_by(), missing).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.headers()["x-powered-by"], "sidemount");
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::{Handler, Method, Request, Response};
+
+mod access_log;
+mod catch_panic;
+mod cors;
+mod idempotency;
+mod map_response;
+mod timeout;
+
+pub use access_log::{AccessLog, AccessRecord, LogField, LogFormat, Logger, SkipAccessLog};
+pub use catch_panic::CatchPanic;
+pub use cors::Cors;
+pub use idempotency::{
+    Begin, Idempotency, IdempotencyStore, MemoryIdempotencyStore, StoredResponse,
+};
+pub use map_response::map_response;
+pub use timeout::Timeout;
+
+#[async_trait]
+pub trait Middleware: Send + Sync + 'static {
+    async fn handle(&self, req: Request, next: Next) -> Response;
+
+    /// Gets the name of the middleware used when profiling, which defaults to
+    /// its type name.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// The time spent in each layer of the middleware chain for one request,
+/// see [crate::Server::with_profiler].
+#[derive(Clone, Debug)]
+pub struct MiddlewareProfile {
+    pub method: Method,
+    pub path: String,
+    /// The layers in the order they ran, ending with the handler. Layers after
+    /// one that responded without calling `next.run` are absent.
+    pub layers: Vec<LayerTiming>,
+}
+
+/// The time spent in one layer of the middleware chain.
+#[derive(Clone, Debug)]
+pub struct LayerTiming {
+    /// The name of the middleware, or `handler` for the handler.
+    pub name: &'static str,
+    /// The time spent in the layer including all inner layers.
+    pub total: Duration,
+    /// The time spent in the layer itself, before calling `next.run` and
+    /// after it returned.
+    pub own: Duration,
+}
+
+/// The total time of each layer of a profiled chain, indexed by cursor.
+pub(crate) struct Timings(Mutex<Vec<Option<Duration>>>);
+
+impl Timings {
+    pub fn new(layers: usize) -> Self {
+        Timings(Mutex::new(vec![None; layers]))
+    }
+
+    /// Attributes the recorded times to the middleware and handler, taking
+    /// the time of each inner layer out of the layer around it.
+    pub fn profile(
+        &self,
+        method: Method,
+        path: String,
+        middleware: &[Arc<dyn Middleware>],
+    ) -> MiddlewareProfile {
+        let totals = self.0.lock().unwrap();
+        let layers = totals
+            .iter()
+            .enumerate()
+            .map_while(|(i, total)| {
+                let total = (*total)?;
+                let inner = totals.get(i + 1).copied().flatten().unwrap_or_default();
+                Some(LayerTiming {
+                    name: middleware.get(i).map_or("handler", |mid| mid.name()),
+                    total,
+                    own: total.saturating_sub(inner),
+                })
+            })
+            .collect();
+        MiddlewareProfile {
+            method,
+            path,
+            layers,
+        }
+    }
+}
+
+pub struct Next {
+    middleware: Arc<Vec<Arc<dyn Middleware>>>,
+    handler: Arc<dyn Handler>,
+    cursor: usize,
+    timings: Option<Arc<Timings>>,
+}
+
+impl Next {
+    pub fn new(middleware: Arc<Vec<Arc<dyn Middleware>>>, handler: Arc<dyn Handler>) -> Self {
+        Self {
+            middleware,
+            handler,
+            cursor: 0,
+            timings: None,
+        }
+    }
+
+    /// Records the time spent in each layer of the chain into the timings.
+    pub(crate) fn with_timings(mut self, timings: Arc<Timings>) -> Self {
+        self.timings = Some(timings);
+        self
+    }
+
+    pub async fn run(mut self, req: Request) -> Response {
+        let cursor = self.cursor;
+        let timings = self.timings.clone();
+        let start = timings.as_ref().map(|_| Instant::now());
+        let res = if let Some(mid) = (*self.middleware).get(self.cursor) {
+            self.cursor += 1;
+            mid.to_owned().handle(req, self).await
+        } else {
+            self.handler.call(req).await
+        };
+        if let (Some(timings), Some(start)) = (timings, start) {
+            timings.0.lock().unwrap()[cursor] = Some(start.elapsed());
+        }
+        res
+    }
+}
+
+/// Runs a `GET /` request through the middleware and handler, for testing a
+/// middleware on its own.
+#[cfg(test)]
+pub(crate) async fn run(mid: impl Middleware, handler: impl Handler) -> crate::http::Response {
+    let middleware: Arc<Vec<Arc<dyn Middleware>>> = Arc::new(vec![Arc::new(mid)]);
+    let req = hyper::Request::get("/").body(hyper::Body::empty()).unwrap();
+    let req = Request::new(req, std::collections::HashMap::new());
+    Next::new(middleware, Arc::new(handler))
+        .run(req)
+        .await
+        .into()
+}
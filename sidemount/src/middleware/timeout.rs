@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hyper::StatusCode;
+
+use crate::{Middleware, Next, Request, Response};
+
+/// Middleware bounding the time the middleware and handlers after it may take
+/// to respond, answering with an empty `503 Service Unavailable` once the
+/// deadline passes.
+///
+/// Unlike [crate::Server::with_request_timeout], which applies to every route,
+/// this can be mounted on a single route or router with its own deadline and
+/// status code. The rest of the chain is cancelled the same way: its future is
+/// dropped at its current `.await` point.
+///
+/// ## Examples
+/// ```rust
+/// use std::time::Duration;
+/// use hyper::StatusCode;
+/// use sidemount::middleware::Timeout;
+/// use sidemount::{Request, Response};
+///
+/// async fn report(req: Request) -> Response {
+///     Response::default()
+/// }
+///
+/// let mut app = sidemount::new();
+/// app.mount_with_priority(Timeout::new(Duration::from_secs(30)), -10);
+/// app.at("/report")
+///     .with(Timeout::new(Duration::from_secs(5)).status(StatusCode::GATEWAY_TIMEOUT))
+///     .get(report);
+/// ```
+pub struct Timeout {
+    duration: Duration,
+    status: StatusCode,
+}
+
+impl Timeout {
+    /// Creates new middleware answering requests taking longer than the
+    /// duration with a `503 Service Unavailable`.
+    pub fn new(duration: Duration) -> Self {
+        Timeout {
+            duration,
+            status: StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// Sets the status code of the response sent when the deadline passes,
+    /// such as `504 Gateway Timeout`.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+#[async_trait]
+impl Middleware for Timeout {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        tokio::time::timeout(self.duration, next.run(req))
+            .await
+            .unwrap_or_else(|_| self.status.into())
+    }
+
+    fn name(&self) -> &'static str {
+        "timeout"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run;
+
+    async fn slow(_req: Request) -> Response {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Response::text("slow")
+    }
+
+    async fn fast(_req: Request) -> Response {
+        Response::text("fast")
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        tokio::time::pause();
+        let timeout = || Timeout::new(Duration::from_millis(50));
+
+        let res = run(timeout(), slow).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        let res = run(timeout().status(StatusCode::GATEWAY_TIMEOUT), slow).await;
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let res = run(timeout(), fast).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"fast");
+    }
+}
@@ -1,11 +1,44 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
+/// A radix tree of path segments, each optionally holding a value of `T`.
+///
+/// The tree backs the [Router](crate::Router) but is generic over its payload
+/// so it can be reused as a standalone data structure, such as a permission
+/// tree keyed by path. Segments may be static (`users`), a `{name}` capture of
+/// the whole segment, or a mix of literals and captures (`{name}.{ext}`). The
+/// last segment may also be a `{*name}` (or `*name`) catch-all capturing the
+/// rest of the path, slashes included, which is only used when no other path
+/// matches.
+///
+/// Static segments are tried before patterns, patterns before captures of a
+/// whole segment, and when a path does not lead to a value through one
+/// matching segment the next one is tried.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::Node;
+///
+/// let mut permissions = Node::new("");
+/// permissions.insert("/admin", "admin");
+/// permissions.insert("/users/{id}/settings", "owner");
+///
+/// let (role, params) = permissions.find("/users/42/settings").unwrap();
+/// assert_eq!(*role, "owner");
+/// assert_eq!(params["id"], "42");
+///
+/// permissions.insert("/files/{*path}", "reader");
+/// let (role, params) = permissions.find("/files/docs/report.pdf").unwrap();
+/// assert_eq!(*role, "reader");
+/// assert_eq!(params["path"], "docs/report.pdf");
+/// ```
 #[derive(Debug)]
 pub struct Node<T> {
     pub nodes: Vec<Node<T>>,
     pub key: String,
     pub handler: Option<T>,
+    pub fallback: Option<T>,
     pub wildcard: bool,
+    segment: Segment,
 }
 
 /// Default implementation for node with a "/" at the root path
@@ -15,54 +48,272 @@ impl<T> Default for Node<T> {
     }
 }
 
-/// Determines if the given path key is a simple wildcard capture
-fn is_wildcard<T: AsRef<str>>(key: T) -> bool {
-    let key = key.as_ref();
-    key.starts_with("{") && key.ends_with("}")
+/// A step taken while matching one path segment, see [Node::trace].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceStep {
+    /// The path segment being matched.
+    pub segment: String,
+    /// The keys of the nodes that were tried and did not match, or did not
+    /// lead to a value, in order.
+    pub tried: Vec<String>,
+    /// The key of the node that matched and the kind of branch it is, or
+    /// `None` when no node matched and the traversal stopped.
+    pub matched: Option<(String, Branch)>,
+}
+
+/// The kind of branch taken by a [TraceStep].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Branch {
+    /// A static segment such as `users`.
+    Static,
+    /// A segment mixing literals and captures such as `{name}.{ext}`.
+    Pattern,
+    /// A capture of the whole segment such as `{id}`.
+    Wildcard,
+    /// A capture of the rest of the path such as `{*path}`.
+    CatchAll,
+}
+
+/// A piece of a compiled path segment pattern
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Param(String),
+}
+
+/// A path segment key compiled for matching against a request path segment.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// A purely static segment, matched by simple equality.
+    Static,
+    /// A segment made up of literals and one or more `{name}` captures.
+    Pattern(Vec<Part>),
+    /// A `{*name}` capture of the rest of the path.
+    CatchAll(String),
+}
+
+impl Segment {
+    /// Compiles the given path key into a segment matcher.
+    fn compile(key: &str) -> Self {
+        let catch_all = key
+            .strip_prefix("{*")
+            .and_then(|key| key.strip_suffix('}'))
+            .or_else(|| key.strip_prefix('*'));
+        if let Some(name) = catch_all.filter(|name| !name.is_empty()) {
+            return Segment::CatchAll(String::from(name));
+        }
+        if !key.contains('{') {
+            return Segment::Static;
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = key;
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(0) => match rest.find('}') {
+                    Some(end) if end > 1 => {
+                        parts.push(Part::Param(String::from(&rest[1..end])));
+                        rest = &rest[end + 1..];
+                    }
+                    _ => return Segment::Static,
+                },
+                Some(start) => {
+                    parts.push(Part::Literal(String::from(&rest[..start])));
+                    rest = &rest[start..];
+                }
+                None => {
+                    parts.push(Part::Literal(String::from(rest)));
+                    rest = "";
+                }
+            }
+        }
+        Segment::Pattern(parts)
+    }
+
+    /// Determines if the segment is a single capture of the entire path segment
+    fn is_wildcard(&self) -> bool {
+        matches!(self, Segment::Pattern(parts) if matches!(parts.as_slice(), [Part::Param(_)]))
+    }
+}
+
+/// Matches the parts of a pattern against the value, pushing any captures along the way.
+///
+/// Captures are greedy and must be non-empty, backtracking until the remaining
+/// parts are satisfied, so `{name}.{ext}` against `archive.tar.gz` captures
+/// `name=archive.tar` and `ext=gz`.
+///
+/// Captures are only recorded when `out` is given, so matching alone does not
+/// allocate.
+fn match_parts<'a, 'b>(
+    parts: &'a [Part],
+    value: &'b str,
+    mut out: Option<&mut Vec<(&'a str, &'b str)>>,
+) -> bool {
+    match parts.split_first() {
+        None => value.is_empty(),
+        Some((Part::Literal(lit), rest)) => match value.strip_prefix(lit.as_str()) {
+            Some(value) => match_parts(rest, value, out),
+            None => false,
+        },
+        Some((Part::Param(name), [])) => {
+            if value.is_empty() {
+                return false;
+            }
+            if let Some(out) = out {
+                out.push((name, value));
+            }
+            true
+        }
+        Some((Part::Param(name), rest)) => {
+            for end in (1..value.len()).rev() {
+                if !value.is_char_boundary(end) {
+                    continue;
+                }
+                if let Some(out) = out.as_deref_mut() {
+                    out.push((name, &value[..end]));
+                }
+                if match_parts(rest, &value[end..], out.as_deref_mut()) {
+                    return true;
+                }
+                if let Some(out) = out.as_deref_mut() {
+                    out.pop();
+                }
+            }
+            false
+        }
+    }
+}
+
+/// Params captured along a path, borrowing their names from the tree and their
+/// values from the path as sent.
+type Captures<'a, 'p> = Vec<(&'a str, &'p str)>;
+
+/// Percent-decodes the captured params into a map of names to values.
+fn decode_all(captures: Captures<'_, '_>) -> HashMap<String, String> {
+    captures
+        .into_iter()
+        .map(|(name, value)| (String::from(name), decode(value).into_owned()))
+        .collect()
+}
+
+/// Percent-decodes a captured param, such as `my%20doc` into `my doc`, keeping
+/// the raw value when it does not decode to valid UTF-8.
+pub(crate) fn decode(value: &str) -> Cow<'_, str> {
+    percent_encoding::percent_decode_str(value)
+        .decode_utf8()
+        .unwrap_or(Cow::Borrowed(value))
 }
 
 impl<T> Node<T> {
     /// Creates a new node with the given path argument.
     pub fn new(key: &str) -> Self {
+        let segment = Segment::compile(key);
         Node {
             nodes: Vec::new(),
             key: String::from(key),
             handler: None,
-            wildcard: is_wildcard(key),
+            fallback: None,
+            wildcard: segment.is_wildcard(),
+            segment,
         }
     }
 
+    /// Sets the path key of the node, recompiling its segment matcher.
+    fn set_key(&mut self, key: &str) {
+        self.segment = Segment::compile(key);
+        self.wildcard = self.segment.is_wildcard();
+        self.key = String::from(key);
+    }
+
+    /// Determines if the node matches the given path segment.
+    fn matches(&self, value: &str) -> bool {
+        match &self.segment {
+            Segment::Static => value == self.key,
+            _ if self.wildcard => true,
+            Segment::Pattern(parts) => match_parts(parts, value, None),
+            Segment::CatchAll(_) => false,
+        }
+    }
+
+    /// Captures the params of the node from the given path segment, returning
+    /// false without capturing anything if the segment does not match.
+    fn capture<'a, 'p>(&'a self, value: &'p str, captures: &mut Captures<'a, 'p>) -> bool {
+        match &self.segment {
+            Segment::Static => value == self.key,
+            Segment::Pattern(parts) => match_parts(parts, value, Some(captures)),
+            Segment::CatchAll(_) => false,
+        }
+    }
+
+    /// Gets the catch-all child of the node, such as `{*path}`.
+    fn catch_all(&self) -> Option<&Node<T>> {
+        self.nodes
+            .iter()
+            .find(|m| matches!(m.segment, Segment::CatchAll(_)))
+    }
+
     /// Inserts a new path and associated handler along the node tree.
     pub fn insert(&mut self, path: &str, f: T) {
+        self.entry(path).handler = Some(f);
+    }
+
+    /// Inserts a fallback along the node tree, used for any path under it that
+    /// has no more specific match.
+    pub fn insert_fallback(&mut self, path: &str, f: T) {
+        self.entry(path).fallback = Some(f);
+    }
+
+    /// Gets a mutable reference to the node at the path, creating any missing
+    /// nodes along the way.
+    fn entry(&mut self, path: &str) -> &mut Node<T> {
         match path.split_once('/') {
             Some((root, "")) => {
-                self.key = String::from(root);
-                self.handler = Some(f);
-                self.wildcard = is_wildcard(root);
+                self.set_key(root);
+                self
             }
-            Some(("", path)) => self.insert(path, f),
+            Some(("", path)) => self.entry(path),
             Some((root, path)) => {
-                let node = self.nodes.iter_mut().find(|m| root == &m.key || m.wildcard);
-                match node {
-                    Some(n) => n.insert(path, f),
-                    None => {
-                        let mut node = Node::new(root);
-                        node.insert(path, f);
-                        self.nodes.push(node);
-                    }
-                }
-            }
-            None => {
-                let node = self.nodes.iter_mut().find(|m| path == &m.key || m.wildcard);
-                match node {
-                    Some(n) => n.handler = Some(f),
-                    None => {
-                        let mut node = Node::new(path);
-                        node.handler = Some(f);
-                        self.nodes.push(node);
-                    }
+                let child = self.child(root);
+                if matches!(child.segment, Segment::CatchAll(_)) {
+                    panic!("Catch-all segment {} must be the last segment", root);
                 }
+                child.entry(path)
             }
+            None => self.child(path),
+        }
+    }
+
+    /// Gets a mutable reference to the child node with the key, creating it
+    /// if it does not exist.
+    fn child(&mut self, key: &str) -> &mut Node<T> {
+        let i = match self.nodes.iter().position(|m| key == m.key) {
+            Some(i) => i,
+            None => self.push_child(Node::new(key)),
+        };
+        &mut self.nodes[i]
+    }
+
+    /// Adds a child node, returning its index. Static children are kept before
+    /// patterns, patterns before captures of a whole segment and those before
+    /// catch-alls, so the most specific child is tried first.
+    pub(crate) fn push_child(&mut self, node: Node<T>) -> usize {
+        let rank = node.rank();
+        let i = self
+            .nodes
+            .iter()
+            .position(|m| m.rank() > rank)
+            .unwrap_or(self.nodes.len());
+        self.nodes.insert(i, node);
+        i
+    }
+
+    /// Gets the order of the node among its siblings, see [Node::push_child].
+    fn rank(&self) -> u8 {
+        match self.segment {
+            Segment::Static => 0,
+            _ if self.wildcard => 2,
+            Segment::Pattern(_) => 1,
+            Segment::CatchAll(_) => 3,
         }
     }
 
@@ -71,29 +322,34 @@ impl<T> Node<T> {
         match path.split_once('/') {
             Some((root, "")) => {
                 *self = node;
-                self.key = String::from(root);
-                self.wildcard = is_wildcard(root);
+                self.set_key(root);
             }
             Some(("", path)) => self.insert_node(path, node),
             Some((root, path)) => {
-                let parent = self.nodes.iter_mut().find(|m| root == &m.key || m.wildcard);
+                let parent = self.nodes.iter_mut().find(|m| root == m.key);
                 match parent {
                     Some(n) => n.insert_node(path, node),
                     None => {
                         let mut parent = Node::new(root);
                         parent.insert_node(path, node);
-                        self.nodes.push(parent);
+                        self.push_child(parent);
                     }
                 }
             }
             None => {
-                let parent = self.nodes.iter_mut().find(|m| path == &m.key || m.wildcard);
+                let parent = self.nodes.iter_mut().find(|m| path == m.key);
                 match parent {
-                    Some(n) => n.nodes = node.nodes,
+                    Some(n) => {
+                        n.nodes = node.nodes;
+                        n.handler = node.handler.or(n.handler.take());
+                        n.fallback = node.fallback.or(n.fallback.take());
+                    }
                     None => {
                         let mut parent = Node::new(path);
                         parent.nodes = node.nodes;
-                        self.nodes.push(parent);
+                        parent.handler = node.handler;
+                        parent.fallback = node.fallback;
+                        self.push_child(parent);
                     }
                 }
             }
@@ -102,78 +358,365 @@ impl<T> Node<T> {
 
     /// Gets a borrowed reference to the handler along the path
     pub fn get(&self, path: &str) -> Option<&T> {
+        self.get_exact(path)
+            .or_else(|| self.find_catch_all(path, &mut Vec::new())?.handler.as_ref())
+    }
+
+    /// Gets the handler along the path without considering catch-alls.
+    fn get_exact(&self, path: &str) -> Option<&T> {
         match path.split_once('/') {
             Some((root, "")) => {
-                if root == &self.key || self.wildcard {
+                if self.matches(root) {
                     self.handler.as_ref()
                 } else {
                     None
                 }
             }
-            Some(("", path)) => self.get(path),
-            Some((root, path)) => {
-                let node = self.nodes.iter().find(|m| root == &m.key || m.wildcard);
-                if let Some(node) = node {
-                    node.get(path)
-                } else {
-                    None
-                }
+            Some(("", path)) => self.get_exact(path),
+            Some((root, path)) => self
+                .nodes
+                .iter()
+                .filter(|m| m.matches(root))
+                .find_map(|node| node.get_exact(path)),
+            None => self
+                .nodes
+                .iter()
+                .filter(|m| m.matches(path))
+                .find_map(|node| node.handler.as_ref()),
+        }
+    }
+
+    /// Finds the value along the path, returning it with the params captured
+    /// from any `{name}` segments along the way.
+    pub fn find(&self, path: &str) -> Option<(&T, HashMap<String, String>)> {
+        let mut params = HashMap::new();
+        let value = self.get_params(path, &mut params)?;
+        Some((value, params))
+    }
+
+    /// Finds the fallback of the deepest node along the path that has one,
+    /// returning it with the params captured along the way.
+    pub fn find_fallback(&self, path: &str) -> Option<(&T, HashMap<String, String>)> {
+        let mut captures = Vec::new();
+        let value = self.find_fallback_captures(path, &mut captures)?;
+        Some((value, decode_all(captures)))
+    }
+
+    /// Gets the fallback of the deepest node along the path that has one,
+    /// without decoding params.
+    pub fn get_fallback_ref(&self, path: &str) -> Option<&T> {
+        self.find_fallback_captures(path, &mut Vec::new())
+    }
+
+    /// Finds the fallback for the path, leaving only the captures of the
+    /// segments leading to it.
+    fn find_fallback_captures<'a, 'p>(
+        &'a self,
+        path: &'p str,
+        captures: &mut Captures<'a, 'p>,
+    ) -> Option<&'a T> {
+        let (value, _) = self.get_fallback(path, captures, 0)?;
+        Some(value)
+    }
+
+    /// Gets the fallback of the deepest node that has one along any branch
+    /// matching the path, with the depth of that node. Siblings are tried in
+    /// order and the first of equally deep fallbacks wins.
+    ///
+    /// The captures of the segments leading to the fallback are appended, and
+    /// rolled back when no fallback is found.
+    fn get_fallback<'a, 'p>(
+        &'a self,
+        path: &'p str,
+        captures: &mut Captures<'a, 'p>,
+        depth: usize,
+    ) -> Option<(&'a T, usize)> {
+        let (root, rest) = match path.split_once('/') {
+            Some(("", path)) => return self.get_fallback(path, captures, depth),
+            Some((root, rest)) => (root, Some(rest)),
+            None => (path, None),
+        };
+        let len = captures.len();
+        let mut deepest: Option<(&'a T, usize, Captures<'a, 'p>)> = None;
+        for node in &self.nodes {
+            if !node.capture(root, captures) {
+                continue;
             }
-            None => {
-                let node = self.nodes.iter().find(|m| path == &m.key || m.wildcard);
-                if let Some(node) = node {
-                    node.handler.as_ref()
-                } else {
-                    None
+            let found = match rest {
+                Some(rest) => node.get_fallback(rest, captures, depth + 1),
+                None => node.fallback.as_ref().map(|value| (value, depth + 1)),
+            };
+            if let Some((value, found_depth)) = found {
+                if deepest.as_ref().is_none_or(|(_, d, _)| found_depth > *d) {
+                    deepest = Some((value, found_depth, captures[len..].to_vec()));
                 }
             }
+            captures.truncate(len);
+        }
+        match deepest {
+            Some((value, depth, found)) => {
+                captures.extend(found);
+                Some((value, depth))
+            }
+            None => self.fallback.as_ref().map(|value| (value, depth)),
         }
     }
 
-    /// Gets a borrowed reference to the handler along the path
-    pub fn get_params(&self, path: &str, params: &mut HashMap<String, String>) -> Option<&T> {
+    /// Finds the catch-all for the path, leaving only the captures of the
+    /// segments leading to it followed by the rest of the path it captures.
+    fn find_catch_all<'a, 'p>(
+        &'a self,
+        path: &'p str,
+        captures: &mut Captures<'a, 'p>,
+    ) -> Option<&'a Node<T>> {
+        let (node, rest, len) = self.get_catch_all(path, captures, None)?;
+        captures.truncate(len);
+        if let Segment::CatchAll(name) = &node.segment {
+            captures.push((name, rest));
+        }
+        Some(node)
+    }
+
+    /// Gets the catch-all of the deepest node along the path that has one, with
+    /// the rest of the path it captures and the number of captures made up to
+    /// that node.
+    fn get_catch_all<'a, 'p>(
+        &'a self,
+        path: &'p str,
+        captures: &mut Captures<'a, 'p>,
+        nearest: Option<(&'a Node<T>, &'p str, usize)>,
+    ) -> Option<(&'a Node<T>, &'p str, usize)> {
+        let nearest = self
+            .catch_all()
+            .map(|node| (node, path, captures.len()))
+            .or(nearest);
         match path.split_once('/') {
+            Some(("", path)) => self.get_catch_all(path, captures, nearest),
+            Some((root, path)) => match self.nodes.iter().find(|m| m.capture(root, captures)) {
+                Some(node) => node.get_catch_all(path, captures, nearest),
+                None => nearest,
+            },
+            None => nearest,
+        }
+    }
+
+    /// Gets the value of a single param along the path, such as `id` for
+    /// `/users/{id}`, following the same decisions as [Node::find] and then
+    /// [Node::find_fallback]. The value is percent-decoded like those of
+    /// [Node::find], borrowing from the path when it has nothing to decode,
+    /// and no map of params is built.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::Node;
+    ///
+    /// let mut root = Node::new("");
+    /// root.insert("/users/{id}/files/{name}.{ext}", ());
+    ///
+    /// assert_eq!(root.param("/users/42/files/a.txt", "ext").as_deref(), Some("txt"));
+    /// assert_eq!(root.param("/users/42/files/a.txt", "missing").as_deref(), None);
+    /// ```
+    pub fn param<'p>(&self, path: &'p str, name: &str) -> Option<Cow<'p, str>> {
+        let mut captures = Vec::new();
+        if self.get_params_exact(path, &mut captures).is_none() {
+            captures.clear();
+            if self.find_catch_all(path, &mut captures).is_none() {
+                captures.clear();
+                self.find_fallback_captures(path, &mut captures)?;
+            }
+        }
+        captures
+            .into_iter()
+            .rev()
+            .find(|(param, _)| *param == name)
+            .map(|(_, value)| decode(value))
+    }
+
+    /// Gets a borrowed reference to the handler along the path, capturing any
+    /// `{name}` segment params into the given map.
+    ///
+    /// Segments are matched as sent and the captured values are then
+    /// percent-decoded, so `/files/{name}` captures `a/b` from `/files/a%2Fb`.
+    ///
+    /// Params are only added to the map once a value is found, so a path that
+    /// does not match leaves no captures behind.
+    pub fn get_params(&self, path: &str, params: &mut HashMap<String, String>) -> Option<&T> {
+        let mut captures = Vec::new();
+        let value = match self.get_params_exact(path, &mut captures) {
+            Some(value) => value,
+            None => {
+                captures.clear();
+                self.find_catch_all(path, &mut captures)?.handler.as_ref()?
+            }
+        };
+        params.extend(decode_all(captures));
+        Some(value)
+    }
+
+    /// Gets the handler along the path capturing params, without considering
+    /// catch-alls. The captures are rolled back when no handler is found.
+    fn get_params_exact<'a, 'p>(
+        &'a self,
+        path: &'p str,
+        captures: &mut Captures<'a, 'p>,
+    ) -> Option<&'a T> {
+        let len = captures.len();
+        let value = match path.split_once('/') {
             Some((root, "")) => {
-                if root == &self.key || self.wildcard {
-                    if self.wildcard {
-                        params.insert(self.key.clone(), root.to_owned());
-                    }
+                if self.capture(root, captures) {
                     self.handler.as_ref()
                 } else {
                     None
                 }
             }
-            Some(("", path)) => self.get(path),
-            Some((root, path)) => {
-                let node = self.nodes.iter().find(|m| root == &m.key || m.wildcard);
-                if let Some(node) = node {
-                    if node.wildcard {
-                        params.insert(node.key.clone(), root.to_owned());
-                    }
-                    node.get_params(path, params)
+            Some(("", path)) => self.get_params_exact(path, captures),
+            Some((root, path)) => self.nodes.iter().find_map(|node| {
+                let len = captures.len();
+                let value = if node.capture(root, captures) {
+                    node.get_params_exact(path, captures)
                 } else {
                     None
+                };
+                if value.is_none() {
+                    captures.truncate(len);
                 }
-            }
-            None => {
-                let node = self.nodes.iter().find(|m| path == &m.key || m.wildcard);
-                if let Some(node) = node {
-                    if node.wildcard {
-                        params.insert(node.key.clone(), path.to_owned());
-                    }
+                value
+            }),
+            None => self.nodes.iter().find_map(|node| {
+                let len = captures.len();
+                let value = if node.capture(path, captures) {
                     node.handler.as_ref()
                 } else {
                     None
+                };
+                if value.is_none() {
+                    captures.truncate(len);
                 }
+                value
+            }),
+        };
+        if value.is_none() {
+            captures.truncate(len);
+        }
+        value
+    }
+
+    /// Traces the traversal of the path through the tree, recording the nodes
+    /// tried and matched at each segment. This follows the same decisions as
+    /// [Node::find] and is meant for debugging which route a path resolves to.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Branch, Node};
+    ///
+    /// let mut root = Node::new("");
+    /// root.insert("/users/me", "me");
+    /// root.insert("/users/{id}", "user");
+    ///
+    /// let steps = root.trace("/users/42");
+    /// assert_eq!(steps[1].tried, vec!["me"]);
+    /// assert_eq!(steps[1].matched, Some((String::from("{id}"), Branch::Wildcard)));
+    /// ```
+    pub fn trace(&self, path: &str) -> Vec<TraceStep> {
+        let mut steps = Vec::new();
+        if !self.trace_into(path, &mut steps, false) {
+            let mut catch_all = Vec::new();
+            if self.trace_into(path, &mut catch_all, true) {
+                return catch_all;
             }
         }
+        steps
     }
 
-    /// Gets a mutable reference to the handler along the path
+    /// Traces the path into the steps, returning whether a value was found.
+    /// With `catch_all`, a node whose children lead to no value ends the
+    /// traversal at its catch-all instead, as [Node::find] does once no path
+    /// matches exactly.
+    fn trace_into(&self, path: &str, steps: &mut Vec<TraceStep>, catch_all: bool) -> bool {
+        let len = steps.len();
+        let found = match path.split_once('/') {
+            Some((root, "")) => {
+                return Node::visit(root, std::iter::once(self), steps, |node, _| {
+                    node.handler.is_some()
+                })
+            }
+            Some(("", path)) => return self.trace_into(path, steps, catch_all),
+            Some((root, rest)) => Node::visit(root, self.nodes.iter(), steps, |node, steps| {
+                node.trace_into(rest, steps, catch_all)
+            }),
+            None => Node::visit(path, self.nodes.iter(), steps, |node, _| {
+                node.handler.is_some()
+            }),
+        };
+        if found || !catch_all {
+            return found;
+        }
+        match self.catch_all().filter(|node| node.handler.is_some()) {
+            Some(node) => {
+                steps.truncate(len);
+                steps.push(TraceStep {
+                    segment: String::from(path),
+                    tried: Vec::new(),
+                    matched: Some((node.key.clone(), Branch::CatchAll)),
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Tries the candidate nodes against the segment in order, following each
+    /// that matches with `rest` until one leads to a value. When none does, the
+    /// steps of the first that matched are kept, or a step without a match.
+    fn visit<'a>(
+        segment: &str,
+        candidates: impl Iterator<Item = &'a Node<T>>,
+        steps: &mut Vec<TraceStep>,
+        rest: impl Fn(&'a Node<T>, &mut Vec<TraceStep>) -> bool,
+    ) -> bool
+    where
+        T: 'a,
+    {
+        let mut step = TraceStep {
+            segment: String::from(segment),
+            tried: Vec::new(),
+            matched: None,
+        };
+        let mut missed = None;
+        for node in candidates {
+            if node.matches(segment) {
+                let branch = match node.segment {
+                    Segment::Static => Branch::Static,
+                    _ if node.wildcard => Branch::Wildcard,
+                    Segment::Pattern(_) => Branch::Pattern,
+                    Segment::CatchAll(_) => Branch::CatchAll,
+                };
+                let mut attempt = vec![TraceStep {
+                    matched: Some((node.key.clone(), branch)),
+                    ..step.clone()
+                }];
+                if rest(node, &mut attempt) {
+                    steps.extend(attempt);
+                    return true;
+                }
+                missed.get_or_insert(attempt);
+            }
+            step.tried.push(node.key.clone());
+        }
+        match missed {
+            Some(missed) => steps.extend(missed),
+            None => steps.push(step),
+        }
+        false
+    }
+
+    /// Gets a mutable reference to the handler inserted at the path, comparing
+    /// keys rather than matching them, so `/users/{id}` does not get the
+    /// handler of `/users/{name}`.
     pub fn get_mut(&mut self, path: &str) -> Option<&mut T> {
         match path.split_once('/') {
             Some((root, "")) => {
-                if root == &self.key || self.wildcard {
+                if self.matches(root) {
                     self.handler.as_mut()
                 } else {
                     None
@@ -181,7 +724,7 @@ impl<T> Node<T> {
             }
             Some(("", path)) => self.get_mut(path),
             Some((root, path)) => {
-                let node = self.nodes.iter_mut().find(|m| root == &m.key || m.wildcard);
+                let node = self.nodes.iter_mut().find(|m| root == m.key);
                 if let Some(node) = node {
                     node.get_mut(path)
                 } else {
@@ -189,7 +732,7 @@ impl<T> Node<T> {
                 }
             }
             None => {
-                let node = self.nodes.iter_mut().find(|m| path == &m.key || m.wildcard);
+                let node = self.nodes.iter_mut().find(|m| path == m.key);
                 if let Some(node) = node {
                     node.handler.as_mut()
                 } else {
@@ -241,4 +784,275 @@ mod tests {
         assert!(root.get("/companies/1234/users").is_none());
         assert!(root.get("/companies/1234/users/foo").is_some());
     }
+
+    #[test]
+    fn test_get_params() {
+        let mut root = Node::<HandlerFn>::new("");
+        root.insert("/users/{id}/profile", |_| Ok(()));
+
+        let mut params = HashMap::new();
+        assert!(root.get_params("/users/42/profile", &mut params).is_some());
+        assert_eq!(params.get("id").map(String::as_str), Some("42"));
+
+        root.insert("/companies/{cid}/users/{uid}", |_| Ok(()));
+        let mut params = HashMap::new();
+        assert!(root
+            .get_params("/companies/acme/users/7", &mut params)
+            .is_some());
+        assert_eq!(params.get("cid").map(String::as_str), Some("acme"));
+        assert_eq!(params.get("uid").map(String::as_str), Some("7"));
+    }
+
+    #[test]
+    fn test_get_params_percent_decoded() {
+        let mut root = Node::<HandlerFn>::new("");
+        root.insert("/files/{name}", |_| Ok(()));
+        root.insert("/docs/{name}.{ext}", |_| Ok(()));
+        root.insert("/static/{*path}", |_| Ok(()));
+
+        let decoded = |path: &str, name: &str| {
+            let mut params = HashMap::new();
+            assert!(root.get_params(path, &mut params).is_some(), "{}", path);
+            params.remove(name).unwrap()
+        };
+        assert_eq!(decoded("/files/my%20doc", "name"), "my doc");
+        assert_eq!(decoded("/files/a%2Fb", "name"), "a/b");
+        assert_eq!(decoded("/files/caf%C3%A9", "name"), "café");
+        assert_eq!(decoded("/files/bad%FF", "name"), "bad%FF");
+        assert_eq!(decoded("/files/100%", "name"), "100%");
+        assert_eq!(decoded("/docs/a%2Eb.txt", "name"), "a.b");
+        assert_eq!(
+            decoded("/static/css/my%20app.css", "path"),
+            "css/my app.css"
+        );
+        assert_eq!(
+            root.param("/files/my%20doc", "name").as_deref(),
+            Some("my doc")
+        );
+    }
+
+    #[test]
+    fn test_get_params_multiple_captures_per_segment() {
+        let mut root = Node::<HandlerFn>::new("");
+        root.insert("/files/{name}.{ext}", |_| Ok(()));
+        root.insert("/assets/v{version}", |_| Ok(()));
+        root.insert("/archives/{name}.{ext}/download", |_| Ok(()));
+
+        let mut params = HashMap::new();
+        assert!(root.get_params("/files/report.pdf", &mut params).is_some());
+        assert_eq!(params.get("name").map(String::as_str), Some("report"));
+        assert_eq!(params.get("ext").map(String::as_str), Some("pdf"));
+
+        let mut params = HashMap::new();
+        assert!(root.get_params("/assets/v2", &mut params).is_some());
+        assert_eq!(params.get("version").map(String::as_str), Some("2"));
+
+        let mut params = HashMap::new();
+        assert!(root
+            .get_params("/archives/backup.tar.gz/download", &mut params)
+            .is_some());
+        assert_eq!(params.get("name").map(String::as_str), Some("backup.tar"));
+        assert_eq!(params.get("ext").map(String::as_str), Some("gz"));
+
+        assert!(root.get("/files/report").is_none());
+        assert!(root.get("/files/.pdf").is_none());
+        assert!(root.get("/assets/2").is_none());
+        assert!(root.get("/assets/v").is_none());
+    }
+
+    #[test]
+    fn test_catch_all() {
+        let mut root = Node::new("");
+        root.insert("/static/{*file}", "static");
+        root.insert("/static/index.html", "index");
+        root.insert("/users/{id}/files/*path", "files");
+
+        let (value, params) = root.find("/static/css/app.css").unwrap();
+        assert_eq!(*value, "static");
+        assert_eq!(params["file"], "css/app.css");
+        assert_eq!(root.find("/static/index.html").unwrap().0, &"index");
+        assert_eq!(
+            root.find("/static/index.html/raw").unwrap().1["file"],
+            "index.html/raw"
+        );
+
+        let (value, params) = root.find("/static/").unwrap();
+        assert_eq!(*value, "static");
+        assert_eq!(params["file"], "");
+        assert!(root.get("/static").is_none());
+
+        let (value, params) = root.find("/users/42/files/a/b.txt").unwrap();
+        assert_eq!(*value, "files");
+        assert_eq!(params["id"], "42");
+        assert_eq!(params["path"], "a/b.txt");
+        assert_eq!(
+            root.param("/users/42/files/a/b.txt", "path").as_deref(),
+            Some("a/b.txt")
+        );
+        assert!(root.get("/users/42/other/a").is_none());
+    }
+
+    #[test]
+    fn test_static_beside_capture() {
+        let mut root = Node::new("");
+        root.insert("/users/{id}", "user");
+        root.insert("/users/me", "me");
+        root.insert("/users/{uid}/posts", "posts");
+
+        assert_eq!(root.find("/users/me").unwrap().0, &"me");
+        let (value, params) = root.find("/users/42").unwrap();
+        assert_eq!(*value, "user");
+        assert_eq!(params["id"], "42");
+
+        let (value, params) = root.find("/users/42/posts").unwrap();
+        assert_eq!(*value, "posts");
+        assert_eq!(params.len(), 1);
+        assert_eq!(params["uid"], "42");
+        assert_eq!(root.param("/users/42/posts", "uid").as_deref(), Some("42"));
+        assert_eq!(root.param("/users/42/posts", "id").as_deref(), None);
+
+        let steps = root.trace("/users/42/posts");
+        assert_eq!(steps[1].tried, vec!["me", "{id}"]);
+        assert_eq!(
+            steps[1].matched,
+            Some((String::from("{uid}"), Branch::Wildcard))
+        );
+    }
+
+    #[test]
+    fn test_catch_all_beside_capture() {
+        let mut root = Node::new("");
+        root.insert("/users/{id}/profile", "profile");
+        root.insert("/users/{*rest}", "rest");
+
+        let (value, params) = root.find("/users/42/profile").unwrap();
+        assert_eq!(*value, "profile");
+        assert_eq!(params.len(), 1);
+        assert_eq!(params["id"], "42");
+
+        let (value, params) = root.find("/users/42/other").unwrap();
+        assert_eq!(*value, "rest");
+        assert_eq!(params.len(), 1);
+        assert_eq!(params["rest"], "42/other");
+
+        let mut params = HashMap::new();
+        assert!(root.get_params("/teams/42/other", &mut params).is_none());
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "must be the last segment")]
+    fn test_catch_all_not_last() {
+        let mut root = Node::new("");
+        root.insert("/static/{*file}/raw", ());
+    }
+
+    #[test]
+    fn test_find_generic_payload() {
+        let mut root = Node::new("");
+        root.insert("/admin", vec!["admin"]);
+        root.insert("/teams/{team}/members/{member}", vec!["owner", "member"]);
+
+        let (roles, params) = root.find("/teams/core/members/7").unwrap();
+        assert_eq!(roles, &vec!["owner", "member"]);
+        assert_eq!(params.len(), 2);
+        assert_eq!(params["team"], "core");
+        assert_eq!(params["member"], "7");
+
+        let (roles, params) = root.find("/admin").unwrap();
+        assert_eq!(roles, &vec!["admin"]);
+        assert!(params.is_empty());
+
+        assert!(root.find("/teams/core").is_none());
+    }
+
+    #[test]
+    fn test_find_fallback() {
+        let mut root = Node::new("");
+        root.insert("/api/users", "users");
+        root.insert_fallback("/", "site");
+        root.insert_fallback("/api", "api");
+        root.insert_fallback("/api/{version}/admin", "admin");
+
+        assert_eq!(root.find_fallback("/about").unwrap().0, &"site");
+        assert_eq!(root.find_fallback("/api").unwrap().0, &"api");
+        assert_eq!(root.find_fallback("/api/missing").unwrap().0, &"api");
+        assert_eq!(root.find_fallback("/api/users/1/x").unwrap().0, &"api");
+
+        let (fallback, params) = root.find_fallback("/api/v2/admin/x").unwrap();
+        assert_eq!(fallback, &"admin");
+        assert_eq!(params["version"], "v2");
+    }
+
+    #[test]
+    fn test_find_fallback_beside_static() {
+        let mut root = Node::new("");
+        root.insert("/api/v1/users", "users");
+        root.insert_fallback("/", "site");
+        root.insert_fallback("/api/{version}/admin", "admin");
+
+        let (fallback, params) = root.find_fallback("/api/v1/admin/x").unwrap();
+        assert_eq!(fallback, &"admin");
+        assert_eq!(params["version"], "v1");
+        assert_eq!(
+            root.param("/api/v1/admin/x", "version").as_deref(),
+            Some("v1")
+        );
+
+        let (fallback, params) = root.find_fallback("/api/v1/users/x").unwrap();
+        assert_eq!(fallback, &"site");
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut root = Node::new("");
+        root.insert("/files/{name}.{ext}", "file");
+        root.insert("/files/{id}", "id");
+
+        let steps = root.trace("/files/a.txt/x");
+        assert_eq!(steps.len(), 3);
+        assert_eq!(
+            steps[0].matched,
+            Some((String::from("files"), Branch::Static))
+        );
+        assert_eq!(
+            steps[1].matched,
+            Some((String::from("{name}.{ext}"), Branch::Pattern))
+        );
+        assert_eq!(steps[2].segment, "x");
+        assert_eq!(steps[2].matched, None);
+
+        let steps = root.trace("/files/readme");
+        assert_eq!(steps[1].tried, vec!["{name}.{ext}"]);
+        assert_eq!(
+            steps[1].matched,
+            Some((String::from("{id}"), Branch::Wildcard))
+        );
+
+        root.insert("/static/{*file}", "static");
+        let steps = root.trace("/static/css/site.css");
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].segment, "css/site.css");
+        assert_eq!(
+            steps[1].matched,
+            Some((String::from("{*file}"), Branch::CatchAll))
+        );
+    }
+
+    #[test]
+    fn test_segment_compile() {
+        assert_eq!(Segment::compile("foo"), Segment::Static);
+        assert_eq!(Segment::compile("{foo"), Segment::Static);
+        assert!(Segment::compile("{id}").is_wildcard());
+        assert!(!Segment::compile("{name}.{ext}").is_wildcard());
+        assert_eq!(
+            Segment::compile("{name}.{ext}"),
+            Segment::Pattern(vec![
+                Part::Param(String::from("name")),
+                Part::Literal(String::from(".")),
+                Part::Param(String::from("ext")),
+            ])
+        );
+    }
 }
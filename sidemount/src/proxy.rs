@@ -0,0 +1,147 @@
+use std::net::{IpAddr, SocketAddr};
+
+use hyper::HeaderMap;
+
+/// An IP network in CIDR notation, e.g. `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Parses a network such as `10.0.0.0/8`, or a single address which is
+    /// treated as a network of one.
+    fn parse(cidr: &str) -> Option<Cidr> {
+        let (addr, prefix) = match cidr.trim().split_once('/') {
+            Some((addr, prefix)) => (addr.parse().ok()?, Some(prefix.parse().ok()?)),
+            None => (cidr.trim().parse().ok()?, None),
+        };
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = prefix.unwrap_or(max);
+        if prefix > max {
+            return None;
+        }
+        Some(Cidr { addr, prefix })
+    }
+
+    fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, canonical(addr)) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Maps IPv4-mapped IPv6 addresses, as reported by dual-stack listeners, back
+/// to plain IPv4 so they match IPv4 networks.
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(addr),
+        addr => addr,
+    }
+}
+
+/// The proxies whose `X-Forwarded-*` headers are trusted, attached to each
+/// request by the server.
+#[derive(Debug, Default)]
+pub(crate) struct TrustedProxies(Vec<Cidr>);
+
+impl TrustedProxies {
+    /// Parses the given networks, returning the first invalid one on failure.
+    pub fn parse<'a>(cidrs: &[&'a str]) -> Result<Self, &'a str> {
+        cidrs
+            .iter()
+            .map(|cidr| Cidr::parse(cidr).ok_or(*cidr))
+            .collect::<Result<_, _>>()
+            .map(TrustedProxies)
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        self.0.iter().any(|cidr| cidr.contains(addr))
+    }
+}
+
+/// Gets the last comma separated entry of every header with the name, which
+/// was added by the nearest proxy; entries before it came from further hops
+/// or from the client itself.
+pub(crate) fn last_entry<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .rfind(|entry| !entry.is_empty())
+}
+
+/// Parses the addresses of every `X-Forwarded-For` header in order, skipping
+/// entries that are not addresses such as `unknown`.
+pub(crate) fn forwarded_for(headers: &HeaderMap) -> Vec<IpAddr> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|entry| {
+            let entry = entry.trim();
+            entry
+                .parse::<IpAddr>()
+                .or_else(|_| entry.parse::<SocketAddr>().map(|addr| addr.ip()))
+                .ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr() {
+        let net = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(net.contains([10, 1, 2, 3].into()));
+        assert!(!net.contains([10, 2, 0, 1].into()));
+        assert!(net.contains("::ffff:10.1.0.1".parse().unwrap()));
+
+        let any = Cidr::parse("0.0.0.0/0").unwrap();
+        assert!(any.contains([192, 168, 0, 1].into()));
+
+        let single = Cidr::parse("fd00::1").unwrap();
+        assert!(single.contains("fd00::1".parse().unwrap()));
+        assert!(!single.contains("fd00::2".parse().unwrap()));
+
+        assert_eq!(Cidr::parse("10.0.0.0/33"), None);
+        assert_eq!(Cidr::parse("proxy"), None);
+        assert_eq!(
+            TrustedProxies::parse(&["10.0.0.0/8", "nope"]).unwrap_err(),
+            "nope"
+        );
+    }
+
+    #[test]
+    fn test_forwarded_for() {
+        let mut headers = HeaderMap::new();
+        headers.append("x-forwarded-for", "203.0.113.7, unknown".parse().unwrap());
+        headers.append(
+            "x-forwarded-for",
+            "[2001:db8::1]:443, 10.0.0.2".parse().unwrap(),
+        );
+        let expected: Vec<IpAddr> = vec![
+            [203, 0, 113, 7].into(),
+            "2001:db8::1".parse().unwrap(),
+            [10, 0, 0, 2].into(),
+        ];
+        assert_eq!(forwarded_for(&headers), expected);
+    }
+}
@@ -1,6 +1,132 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, net::IpAddr, str::FromStr, sync::Arc, time::Duration};
 
-use crate::{http, Method};
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{self, AsHeaderName};
+use hyper::http::Extensions;
+use hyper::{Body, HeaderMap, Version};
+
+#[cfg(feature = "cookies")]
+use crate::cookies::{self, Cookies};
+use crate::proxy::{self, TrustedProxies};
+use crate::state::StateMap;
+use crate::{http, ConnectionInfo, Method};
+
+/// The most bytes [Request::body_bytes] and the body parsers, such as
+/// [Request::cbor], read from a request body by default, see
+/// [crate::Server::with_max_body_size].
+pub(crate) const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// The most bytes read from the request body, set by the server.
+#[derive(Clone, Copy)]
+pub(crate) struct MaxBodySize(pub usize);
+
+/// Gets the media type of the `Content-Type` header in lowercase, without
+/// parameters such as `charset`.
+pub(crate) fn media_type(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+    let essence = value.split(';').next().unwrap_or("").trim();
+    Some(essence.to_ascii_lowercase())
+}
+
+/// What the server does with the part of a request body the handler did not
+/// read, such as when it rejects an upload early, see
+/// [crate::Server::with_unread_body].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnreadBody {
+    /// Reads and discards up to the given number of bytes once the request is
+    /// dropped, so the connection can be reused for the next request. A longer
+    /// body, or one that takes longer than 5 seconds, closes the connection.
+    Drain(usize),
+    /// Closes the connection, or resets the stream over HTTP/2, once the
+    /// response is sent.
+    Close,
+}
+
+/// The longest an unread request body is drained for.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The error deserializing a query string, see [Request::query_as], such as a
+/// missing required field or a value of the wrong type.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct QueryError(serde_urlencoded::de::Error);
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid query string: {}", self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for QueryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// The error reading a request body, see [Request::body_bytes] and
+/// [Request::json].
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body could not be read, such as when the connection dropped.
+    Read(Box<dyn std::error::Error + Send + Sync>),
+    /// The body exceeds the limit of the server, carrying the limit in bytes,
+    /// see [crate::Server::with_max_body_size].
+    TooLarge(usize),
+    /// The request was sent without the expected `Content-Type`, carrying the
+    /// media type it was sent with, if any.
+    ContentType(Option<String>),
+    /// The body is malformed, carrying the deserializer's message.
+    Deserialize(String),
+}
+
+impl std::fmt::Display for BodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyError::Read(err) => write!(f, "Failed to read request body: {}", err),
+            BodyError::TooLarge(limit) => write!(f, "Request body exceeds {} bytes", limit),
+            BodyError::ContentType(Some(media_type)) => {
+                write!(f, "Unexpected Content-Type {}", media_type)
+            }
+            BodyError::ContentType(None) => write!(f, "Missing Content-Type"),
+            BodyError::Deserialize(message) => write!(f, "Invalid request body: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BodyError {}
+
+/// The error getting a typed path param, see [Request::param_parse].
+#[derive(Debug)]
+pub enum ParamError {
+    /// The route captured no param with the name.
+    Missing(String),
+    /// The param failed to parse, carrying its name and the parse error.
+    Invalid { name: String, message: String },
+}
+
+impl std::fmt::Display for ParamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamError::Missing(name) => write!(f, "Missing path param {}", name),
+            ParamError::Invalid { name, message } => {
+                write!(f, "Invalid path param {}: {}", name, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+/// The path of a request before it was rewritten by the server.
+pub(crate) struct OriginalPath(pub String);
+
+/// The request body once read by [Request::body_bytes].
+struct BufferedBody(Bytes);
+
+/// The pattern of the route a request matched.
+pub(crate) struct MatchedPattern(pub Arc<str>);
 
 pub struct Request {
     req: http::Request,
@@ -20,7 +146,979 @@ impl Request {
         self.req.uri().path()
     }
 
+    /// Gets the HTTP version of the request.
+    pub fn version(&self) -> Version {
+        self.req.version()
+    }
+
+    /// Gets the pattern of the route the request matched, such as
+    /// `/users/{id}`, which unlike the path groups requests by route.
+    pub fn matched_pattern(&self) -> Option<&str> {
+        let pattern = self.req.extensions().get::<MatchedPattern>()?;
+        Some(&pattern.0)
+    }
+
+    /// Gets the path the client requested, before any rewrite by the server
+    /// (see [crate::Server::with_rewriter]).
+    pub fn original_path(&self) -> &str {
+        match self.req.extensions().get::<OriginalPath>() {
+            Some(original) => &original.0,
+            None => self.path(),
+        }
+    }
+
+    /// Gets a reference to the request headers.
+    pub fn headers(&self) -> &HeaderMap {
+        self.req.headers()
+    }
+
+    /// Gets the first value of the header, or `None` when the header is
+    /// missing or its value is not valid UTF-8.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn agent(req: Request) -> Response {
+    ///     let agent = req.header("user-agent").unwrap_or("unknown");
+    ///     Response::text(format!("hello {}", agent))
+    /// }
+    /// ```
+    pub fn header(&self, name: impl AsHeaderName) -> Option<&str> {
+        self.req.headers().get(name)?.to_str().ok()
+    }
+
+    /// Gets every value of the header in the order received, such as each
+    /// `Accept` header line, skipping values that are not valid UTF-8.
+    pub fn header_all(&self, name: impl AsHeaderName) -> Vec<&str> {
+        self.req
+            .headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect()
+    }
+
+    /// Parses the first value of the header into a typed value, returning
+    /// `None` when the header is missing and the parse error when invalid.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn retry(req: Request) -> Response {
+    ///     match req.typed_header::<u32>("x-retry-count") {
+    ///         Some(Ok(count)) => println!("retry {}", count),
+    ///         Some(Err(err)) => println!("invalid retry count: {}", err),
+    ///         None => println!("first attempt"),
+    ///     }
+    ///     Response::default()
+    /// }
+    /// ```
+    pub fn typed_header<T: FromStr>(&self, name: impl AsHeaderName) -> Option<Result<T, T::Err>> {
+        let value = self.req.headers().get(name)?;
+        Some(String::from_utf8_lossy(value.as_bytes()).trim().parse())
+    }
+
+    /// Gets the `Content-Length` of the request body, if sent and valid.
+    pub fn content_length(&self) -> Option<u64> {
+        self.typed_header(header::CONTENT_LENGTH)?.ok()
+    }
+
     pub fn param(&self, key: &str) -> Option<&String> {
         self.params.get(key)
     }
+
+    /// Gets every path param captured by the route, keyed by name.
+    pub fn params(&self) -> &HashMap<String, String> {
+        &self.params
+    }
+
+    /// Gets the number of path params captured by the route.
+    pub fn params_len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Parses the path param into a typed value, returning `None` when the
+    /// route captured no param with the name and the parse error when invalid.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn user(req: Request) -> Response {
+    ///     match req.param_as::<u64>("id") {
+    ///         Some(Ok(id)) => Response::text(format!("user {}", id)),
+    ///         _ => hyper::StatusCode::BAD_REQUEST.into(),
+    ///     }
+    /// }
+    /// ```
+    pub fn param_as<T: FromStr>(&self, key: &str) -> Option<Result<T, T::Err>> {
+        Some(self.params.get(key)?.parse())
+    }
+
+    /// Parses the path param into a typed value like [Request::param_as],
+    /// folding a missing param and a parse error into a [ParamError], which
+    /// handlers returning it answer with a `400 Bad Request`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{ParamError, Request, Response};
+    ///
+    /// async fn user(req: Request) -> Result<Response, ParamError> {
+    ///     let id = req.param_parse::<u64>("id")?;
+    ///     Ok(Response::text(format!("user {}", id)))
+    /// }
+    /// ```
+    pub fn param_parse<T>(&self, key: &str) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        match self.param_as::<T>(key) {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(err)) => Err(ParamError::Invalid {
+                name: String::from(key),
+                message: err.to_string(),
+            }),
+            None => Err(ParamError::Missing(String::from(key))),
+        }
+    }
+
+    /// Parses the query string into a map, percent-decoding both keys and
+    /// values. A repeated key keeps its last value, a key without a value such
+    /// as `?flag` maps to an empty string, and a request without a query string
+    /// yields an empty map.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn search(req: Request) -> Response {
+    ///     let query = req.query();
+    ///     let page = query.get("page").map_or("1", String::as_str);
+    ///     Response::text(format!("page {}", page))
+    /// }
+    /// ```
+    pub fn query(&self) -> HashMap<String, String> {
+        let query = self.req.uri().query().unwrap_or_default();
+        form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect()
+    }
+
+    /// Deserializes the query string into a typed value, where a request
+    /// without a query string deserializes as an empty one, so a struct whose
+    /// fields are all optional always succeeds.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use serde::Deserialize;
+    /// use sidemount::{Request, Response};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Page {
+    ///     page: Option<u32>,
+    ///     limit: Option<u32>,
+    /// }
+    ///
+    /// async fn list(req: Request) -> Response {
+    ///     match req.query_as::<Page>() {
+    ///         Ok(page) => Response::text(format!("page {}", page.page.unwrap_or(1))),
+    ///         Err(err) => Response::text(err.to_string()),
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn query_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, QueryError> {
+        let query = self.req.uri().query().unwrap_or_default();
+        serde_urlencoded::from_str(query).map_err(QueryError)
+    }
+
+    /// Gets the percent-decoded value of a query string key, the last one if
+    /// repeated, see [Request::query].
+    pub fn query_param(&self, key: &str) -> Option<String> {
+        let query = self.req.uri().query()?;
+        form_urlencoded::parse(query.as_bytes())
+            .filter(|(k, _)| k == key)
+            .last()
+            .map(|(_, value)| value.into_owned())
+    }
+
+    /// Gets the information about the connection the request was received on.
+    pub fn connection_info(&self) -> Option<&ConnectionInfo> {
+        self.req
+            .extensions()
+            .get::<Arc<ConnectionInfo>>()
+            .map(|info| info.as_ref())
+    }
+
+    /// Gets the address of the client. When the connection comes from a trusted
+    /// proxy (see [crate::Server::with_trusted_proxies]) this is the nearest
+    /// untrusted address in `X-Forwarded-For`, otherwise the peer address.
+    pub fn remote_addr(&self) -> Option<IpAddr> {
+        let peer = self.connection_info()?.remote_addr?.ip();
+        let proxies = match self.trusted_proxies(peer) {
+            Some(proxies) => proxies,
+            None => return Some(peer),
+        };
+        let chain = proxy::forwarded_for(self.headers());
+        let client = chain
+            .iter()
+            .rev()
+            .find(|addr| !proxies.contains(**addr))
+            .or_else(|| chain.first());
+        Some(client.copied().unwrap_or(peer))
+    }
+
+    /// Gets the addresses the request passed through, from the client to the
+    /// peer that connected to the server. The `X-Forwarded-For` addresses are
+    /// only included when the peer is a trusted proxy.
+    pub fn forwarded_for_chain(&self) -> Vec<IpAddr> {
+        let peer = match self.connection_info().and_then(|info| info.remote_addr) {
+            Some(addr) => addr.ip(),
+            None => return Vec::new(),
+        };
+        let mut chain = match self.trusted_proxies(peer) {
+            Some(_) => proxy::forwarded_for(self.headers()),
+            None => Vec::new(),
+        };
+        chain.push(peer);
+        chain
+    }
+
+    /// Gets the host the client requested, honoring `X-Forwarded-Host` when
+    /// the connection comes from a trusted proxy. Only the entry added by
+    /// that proxy is used, since earlier ones may come from the client.
+    pub fn host(&self) -> Option<&str> {
+        if self.via_trusted_proxy() {
+            if let Some(host) = proxy::last_entry(self.headers(), "x-forwarded-host") {
+                return Some(host);
+            }
+        }
+        self.headers()
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| self.req.uri().host())
+    }
+
+    /// Gets the scheme the client requested, `http` or `https`, honoring
+    /// `X-Forwarded-Proto` when the connection comes from a trusted proxy,
+    /// using the entry added by that proxy like [Request::host].
+    pub fn scheme(&self) -> &str {
+        if self.via_trusted_proxy() {
+            if let Some(proto) = proxy::last_entry(self.headers(), "x-forwarded-proto") {
+                return proto;
+            }
+        }
+        match self.req.uri().scheme_str() {
+            Some(scheme) => scheme,
+            None if self.tls_version().is_some() => "https",
+            None => "http",
+        }
+    }
+
+    fn via_trusted_proxy(&self) -> bool {
+        self.connection_info()
+            .and_then(|info| info.remote_addr)
+            .and_then(|addr| self.trusted_proxies(addr.ip()))
+            .is_some()
+    }
+
+    /// Gets the trusted proxies when the peer is one of them.
+    fn trusted_proxies(&self, peer: IpAddr) -> Option<&TrustedProxies> {
+        let proxies = self.req.extensions().get::<Arc<TrustedProxies>>()?;
+        Some(proxies.as_ref()).filter(|proxies| proxies.contains(peer))
+    }
+
+    /// Gets the SNI server name the client requested over TLS, if any.
+    pub fn tls_server_name(&self) -> Option<&str> {
+        self.connection_info()?.server_name.as_deref()
+    }
+
+    /// Gets the ALPN protocol negotiated over TLS, if any.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.connection_info()?.alpn_protocol.as_deref()
+    }
+
+    /// Gets the TLS protocol version negotiated for the connection, if any.
+    pub fn tls_version(&self) -> Option<&'static str> {
+        self.connection_info()?.tls_version
+    }
+
+    /// Gets the shared application state of the given type, registered with
+    /// [crate::Server::with_state].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// struct Config {
+    ///     greeting: String,
+    /// }
+    ///
+    /// async fn greet(req: Request) -> Response {
+    ///     let config = req.state::<Config>().unwrap();
+    ///     Response::text(config.greeting.clone())
+    /// }
+    /// ```
+    pub fn state<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        self.req.extensions().get::<Arc<StateMap>>()?.get()
+    }
+
+    /// Attaches a value to the request, keyed by its type, replacing any
+    /// previous value of the same type, so middleware can pass data such as
+    /// the authenticated user on to the handler.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use async_trait::async_trait;
+    /// use sidemount::{Middleware, Next, Request, Response};
+    ///
+    /// struct UserId(u64);
+    ///
+    /// struct Auth;
+    ///
+    /// #[async_trait]
+    /// impl Middleware for Auth {
+    ///     async fn handle(&self, mut req: Request, next: Next) -> Response {
+    ///         req.insert_ext(UserId(42));
+    ///         next.run(req).await
+    ///     }
+    /// }
+    ///
+    /// async fn profile(req: Request) -> Response {
+    ///     let UserId(id) = req.ext::<UserId>().unwrap();
+    ///     Response::text(format!("user {}", id))
+    /// }
+    /// ```
+    pub fn insert_ext<T: Send + Sync + 'static>(&mut self, val: T) {
+        self.req.extensions_mut().insert(val);
+    }
+
+    /// Gets the value of the given type attached with [Request::insert_ext].
+    pub fn ext<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.req.extensions().get()
+    }
+
+    /// Gets a reference to the extensions attached to the request.
+    pub fn extensions(&self) -> &Extensions {
+        self.req.extensions()
+    }
+
+    /// Gets a mutable reference to the extensions attached to the request.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        self.req.extensions_mut()
+    }
+
+    /// Takes the body of the request, leaving an empty body behind, to hand it
+    /// to another library such as a multipart parser or to forward it.
+    /// Reading the body again afterwards, including through parsers such as
+    /// [Request::cbor], sees an empty body unless it was already read by
+    /// [Request::body_bytes].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn upload(mut req: Request) -> Response {
+    ///     let body = req.take_body();
+    ///     match hyper::body::to_bytes(body).await {
+    ///         Ok(bytes) => Response::text(format!("received {} bytes", bytes.len())),
+    ///         Err(err) => Response::from_error(err),
+    ///     }
+    /// }
+    /// ```
+    pub fn take_body(&mut self) -> Body {
+        std::mem::take(self.req.body_mut())
+    }
+
+    /// Reads the whole request body, failing with [BodyError::TooLarge] if it
+    /// is larger than the limit of the server, 2 MiB by default (see
+    /// [crate::Server::with_max_body_size]). A `Content-Length` over the limit
+    /// fails before reading anything.
+    ///
+    /// The body is cached in the request extensions on the first call, so
+    /// later calls, including through parsers such as [Request::cbor], return
+    /// the same bytes.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{BodyError, Request, Response};
+    ///
+    /// // A body over the limit is answered with `413 Payload Too Large`.
+    /// async fn echo(mut req: Request) -> Result<Response, BodyError> {
+    ///     let body = req.body_bytes().await?;
+    ///     Ok(Response::text(String::from_utf8_lossy(&body).into_owned()))
+    /// }
+    /// ```
+    pub async fn body_bytes(&mut self) -> Result<Bytes, BodyError> {
+        if let Some(BufferedBody(body)) = self.req.extensions().get::<BufferedBody>() {
+            return Ok(body.clone());
+        }
+        let limit = self
+            .req
+            .extensions()
+            .get::<MaxBodySize>()
+            .map_or(MAX_BODY_BYTES, |max| max.0);
+        if self.content_length().unwrap_or(0) > limit as u64 {
+            return Err(BodyError::TooLarge(limit));
+        }
+
+        let mut body = self.take_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|err| BodyError::Read(err.into()))?;
+            if bytes.len() + chunk.len() > limit {
+                return Err(BodyError::TooLarge(limit));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        let bytes = Bytes::from(bytes);
+        self.req
+            .extensions_mut()
+            .insert(BufferedBody(bytes.clone()));
+        Ok(bytes)
+    }
+
+    /// Reads the whole body for a parser of the given media type, failing if
+    /// the request has another `Content-Type`, see [Request::body_bytes].
+    #[cfg(feature = "cbor")]
+    async fn read_body(&mut self, expected: &str) -> Result<Bytes, BodyError> {
+        match media_type(self.headers()) {
+            Some(media_type) if media_type == expected => {}
+            media_type => return Err(BodyError::ContentType(media_type)),
+        }
+        self.body_bytes().await
+    }
+
+    /// Deserializes the CBOR body of the request, which must be sent with
+    /// `Content-Type: application/cbor`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use serde::Deserialize;
+    /// use sidemount::{Request, Response};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Reading {
+    ///     sensor: u32,
+    ///     celsius: f32,
+    /// }
+    ///
+    /// async fn record(mut req: Request) -> Response {
+    ///     match req.cbor::<Reading>().await {
+    ///         Ok(reading) => Response::text(format!("{}: {}", reading.sensor, reading.celsius)),
+    ///         Err(_) => Response::from(hyper::StatusCode::BAD_REQUEST),
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "cbor")]
+    pub async fn cbor<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, BodyError> {
+        let body = self.read_body("application/cbor").await?;
+        ciborium::de::from_reader(&body[..]).map_err(|err| BodyError::Deserialize(err.to_string()))
+    }
+
+    /// Deserializes the JSON body of the request, which must be sent with
+    /// `Content-Type: application/json`, see [Request::json_lenient] to accept
+    /// any content type.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use serde::Deserialize;
+    /// use sidemount::{BodyError, Request, Response};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct NewUser {
+    ///     name: String,
+    /// }
+    ///
+    /// async fn create(mut req: Request) -> Response {
+    ///     match req.json::<NewUser>().await {
+    ///         Ok(user) => Response::text(format!("created {}", user.name)),
+    ///         Err(BodyError::ContentType(_)) => {
+    ///             Response::from(hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE)
+    ///         }
+    ///         Err(_) => Response::from(hyper::StatusCode::BAD_REQUEST),
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub async fn json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, BodyError> {
+        match media_type(self.headers()) {
+            Some(media_type) if media_type == "application/json" => {}
+            media_type => return Err(BodyError::ContentType(media_type)),
+        }
+        self.json_lenient().await
+    }
+
+    /// Deserializes the JSON body of the request regardless of its
+    /// `Content-Type`, for clients that send JSON mislabeled.
+    #[cfg(feature = "json")]
+    pub async fn json_lenient<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, BodyError> {
+        let body = self.body_bytes().await?;
+        serde_json::from_slice(&body).map_err(|err| BodyError::Deserialize(err.to_string()))
+    }
+
+    /// Gets the cookies sent with the request as a map of names to values.
+    ///
+    /// All `Cookie` headers are parsed on the first call and cached in the
+    /// request extensions, so reading several cookies only parses them once.
+    #[cfg(feature = "cookies")]
+    pub fn cookies(&mut self) -> &HashMap<String, String> {
+        if self.req.extensions().get::<Cookies>().is_none() {
+            let cookies = cookies::parse(self.req.headers());
+            self.req.extensions_mut().insert(cookies);
+        }
+        &self.req.extensions().get::<Cookies>().unwrap().0
+    }
+
+    /// Gets the value of the cookie sent with the request, using the cookies
+    /// cached by [Request::cookies] or parsing the `Cookie` headers otherwise.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn theme(req: Request) -> Response {
+    ///     let theme = req.cookie("theme").unwrap_or_else(|| String::from("light"));
+    ///     Response::text(theme)
+    /// }
+    /// ```
+    #[cfg(feature = "cookies")]
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        match self.req.extensions().get::<Cookies>() {
+            Some(cookies) => cookies.0.get(name).cloned(),
+            None => cookies::parse(self.req.headers()).0.remove(name),
+        }
+    }
+}
+
+impl Drop for Request {
+    /// Drains the unread body in the background when configured to, see
+    /// [UnreadBody::Drain]. Dropping the body instead tells hyper to close the
+    /// connection once the response is sent.
+    fn drop(&mut self) {
+        let limit = match self.req.extensions().get::<UnreadBody>() {
+            Some(UnreadBody::Drain(limit)) => *limit,
+            _ => return,
+        };
+        if self.req.body().is_end_stream() {
+            return;
+        }
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => runtime,
+            Err(_) => return,
+        };
+        let mut body = self.take_body();
+        runtime.spawn(async move {
+            let drain = async {
+                let mut read = 0;
+                while let Some(Ok(chunk)) = body.data().await {
+                    read += chunk.len();
+                    if read > limit {
+                        break;
+                    }
+                }
+            };
+            let _ = tokio::time::timeout(DRAIN_TIMEOUT, drain).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Middleware, Next, Response};
+
+    #[test]
+    fn test_typed_header() {
+        let req = hyper::Request::builder()
+            .header("x-retry-count", " 3 ")
+            .header("x-ratio", "half")
+            .header("content-length", "42")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let req = Request::new(req, HashMap::new());
+
+        assert_eq!(req.typed_header::<u32>("x-retry-count"), Some(Ok(3)));
+        assert!(matches!(req.typed_header::<f64>("x-ratio"), Some(Err(_))));
+        assert_eq!(req.typed_header::<u32>("x-missing"), None);
+        assert_eq!(req.content_length(), Some(42));
+    }
+
+    #[test]
+    fn test_header() {
+        let req = hyper::Request::builder()
+            .header(header::ACCEPT, "text/html")
+            .header("x-tag", "a")
+            .header("x-tag", header::HeaderValue::from_bytes(b"\xff").unwrap())
+            .header("x-tag", "b")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let req = Request::new(req, HashMap::new());
+
+        assert_eq!(req.header("accept"), Some("text/html"));
+        assert_eq!(req.header(header::ACCEPT), Some("text/html"));
+        assert_eq!(req.header("x-tag"), Some("a"));
+        assert_eq!(req.header("x-missing"), None);
+        assert_eq!(req.header_all("x-tag"), vec!["a", "b"]);
+        assert!(req.header_all("x-missing").is_empty());
+        assert_eq!(req.headers().get_all("x-tag").iter().count(), 3);
+    }
+
+    #[test]
+    fn test_param_as() {
+        let req = hyper::Request::get("/users/42").body(hyper::Body::empty());
+        let params = HashMap::from([
+            (String::from("id"), String::from("42")),
+            (String::from("name"), String::from("ada")),
+        ]);
+        let req = Request::new(req.unwrap(), params);
+
+        assert_eq!(req.param_as::<u64>("id"), Some(Ok(42)));
+        assert!(matches!(req.param_as::<u64>("name"), Some(Err(_))));
+        assert_eq!(req.param_as::<u64>("missing"), None);
+
+        assert_eq!(req.param_parse::<u64>("id").unwrap(), 42);
+        let err = req.param_parse::<u64>("name").unwrap_err();
+        assert!(matches!(&err, ParamError::Invalid { name, .. } if name == "name"));
+        assert_eq!(
+            err.to_string(),
+            "Invalid path param name: invalid digit found in string"
+        );
+        let err = req.param_parse::<u64>("missing").unwrap_err();
+        assert!(matches!(&err, ParamError::Missing(name) if name == "missing"));
+    }
+
+    #[test]
+    fn test_query() {
+        let query = |uri: &str| {
+            let req = hyper::Request::get(uri).body(hyper::Body::empty()).unwrap();
+            Request::new(req, HashMap::new())
+        };
+
+        assert!(query("/search").query().is_empty());
+        assert!(query("/search?").query().is_empty());
+
+        let req = query("/search?q=hello%20world&sort=asc&sort=desc&flag&na%6De=a+b");
+        let expected = HashMap::from([
+            (String::from("q"), String::from("hello world")),
+            (String::from("sort"), String::from("desc")),
+            (String::from("flag"), String::new()),
+            (String::from("name"), String::from("a b")),
+        ]);
+        assert_eq!(req.query(), expected);
+        assert_eq!(req.query_param("q").as_deref(), Some("hello world"));
+        assert_eq!(req.query_param("sort").as_deref(), Some("desc"));
+        assert_eq!(req.query_param("flag").as_deref(), Some(""));
+        assert_eq!(req.query_param("missing"), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_query_as() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Page {
+            page: Option<u32>,
+            limit: Option<u32>,
+            sort: Option<String>,
+        }
+        #[derive(Debug, serde::Deserialize)]
+        struct Required {
+            #[allow(dead_code)]
+            limit: u32,
+        }
+        let request = |uri: &str| {
+            let req = hyper::Request::get(uri).body(hyper::Body::empty()).unwrap();
+            Request::new(req, HashMap::new())
+        };
+
+        let page = request("/items?page=2&limit=10")
+            .query_as::<Page>()
+            .unwrap();
+        assert_eq!(
+            page,
+            Page {
+                page: Some(2),
+                limit: Some(10),
+                sort: None
+            }
+        );
+        let empty = request("/items").query_as::<Page>().unwrap();
+        assert_eq!(
+            empty,
+            Page {
+                page: None,
+                limit: None,
+                sort: None
+            }
+        );
+
+        let err = request("/items?limit=abc").query_as::<Page>().unwrap_err();
+        assert!(err.to_string().contains("invalid digit"), "{}", err);
+        let err = request("/items").query_as::<Required>().unwrap_err();
+        assert!(err.to_string().contains("missing field `limit`"), "{}", err);
+    }
+
+    fn forwarded(peer: [u8; 4], headers: &[(&str, &str)]) -> Request {
+        let mut req = hyper::Request::get("/").header("host", "internal:8080");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let mut req = req.body(hyper::Body::empty()).unwrap();
+        let info = ConnectionInfo {
+            remote_addr: Some((peer, 4000).into()),
+            ..ConnectionInfo::default()
+        };
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8"]).unwrap();
+        req.extensions_mut().insert(Arc::new(info));
+        req.extensions_mut().insert(Arc::new(proxies));
+        Request::new(req, HashMap::new())
+    }
+
+    #[test]
+    fn test_forwarded_headers() {
+        let headers = [
+            ("x-forwarded-for", "198.51.100.1, 203.0.113.7, 10.0.0.2"),
+            ("x-forwarded-host", "example.com"),
+            ("x-forwarded-proto", "https"),
+        ];
+
+        let req = forwarded([10, 0, 0, 1], &headers);
+        assert_eq!(req.remote_addr(), Some([203, 0, 113, 7].into()));
+        assert_eq!(req.host(), Some("example.com"));
+        assert_eq!(req.scheme(), "https");
+        let chain: Vec<IpAddr> = vec![
+            [198, 51, 100, 1].into(),
+            [203, 0, 113, 7].into(),
+            [10, 0, 0, 2].into(),
+            [10, 0, 0, 1].into(),
+        ];
+        assert_eq!(req.forwarded_for_chain(), chain);
+
+        let req = forwarded([203, 0, 113, 9], &headers);
+        assert_eq!(req.remote_addr(), Some([203, 0, 113, 9].into()));
+        assert_eq!(req.host(), Some("internal:8080"));
+        assert_eq!(req.scheme(), "http");
+        assert_eq!(
+            req.forwarded_for_chain(),
+            vec![IpAddr::from([203, 0, 113, 9])]
+        );
+
+        let spoofed = [
+            ("x-forwarded-host", "evil.example, example.com"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-proto", "javascript, http"),
+        ];
+        let req = forwarded([10, 0, 0, 1], &spoofed);
+        assert_eq!(req.host(), Some("example.com"));
+        assert_eq!(req.scheme(), "http");
+    }
+
+    #[tokio::test]
+    async fn test_take_body() {
+        let req = hyper::Request::post("/").body(Body::from("hello")).unwrap();
+        let mut req = Request::new(req, HashMap::new());
+
+        let body = hyper::body::to_bytes(req.take_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+        let body = hyper::body::to_bytes(req.take_body()).await.unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_body_bytes() {
+        let req = hyper::Request::post("/").body(Body::from("hello")).unwrap();
+        let mut req = Request::new(req, HashMap::new());
+        assert_eq!(&req.body_bytes().await.unwrap()[..], b"hello");
+        assert_eq!(&req.body_bytes().await.unwrap()[..], b"hello");
+
+        let req = hyper::Request::post("/")
+            .header(header::CONTENT_LENGTH, MAX_BODY_BYTES + 1)
+            .body(Body::empty())
+            .unwrap();
+        assert!(Request::new(req, HashMap::new())
+            .body_bytes()
+            .await
+            .is_err());
+
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                let chunk = Bytes::from(vec![0; MAX_BODY_BYTES / 2]);
+                if sender.send_data(chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let mut req = Request::new(
+            hyper::Request::post("/").body(body).unwrap(),
+            HashMap::new(),
+        );
+        let err = req.body_bytes().await.unwrap_err();
+        assert!(
+            matches!(err, BodyError::TooLarge(MAX_BODY_BYTES)),
+            "{}",
+            err
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn test_json() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct User {
+            name: String,
+        }
+        let req = |content_type: Option<&str>, body: &'static str| {
+            let mut req = hyper::Request::post("/");
+            if let Some(content_type) = content_type {
+                req = req.header(header::CONTENT_TYPE, content_type);
+            }
+            Request::new(req.body(Body::from(body)).unwrap(), HashMap::new())
+        };
+        let json = Some("application/json; charset=utf-8");
+
+        let user = req(json, r#"{"name":"ada"}"#).json::<User>().await.unwrap();
+        assert_eq!(user.name, "ada");
+
+        let err = req(json, r#"{"name":"ada"} trailing"#).json::<User>().await;
+        assert!(
+            matches!(err, Err(BodyError::Deserialize(message)) if message.contains("trailing"))
+        );
+
+        let err = req(Some("text/plain"), r#"{"name":"ada"}"#)
+            .json::<User>()
+            .await;
+        assert!(
+            matches!(err, Err(BodyError::ContentType(Some(media_type))) if media_type == "text/plain")
+        );
+        let err = req(None, r#"{"name":"ada"}"#).json::<User>().await;
+        assert!(matches!(err, Err(BodyError::ContentType(None))));
+
+        let user = req(Some("text/plain"), r#"{"name":"ada"}"#)
+            .json_lenient::<User>()
+            .await;
+        assert_eq!(user.unwrap().name, "ada");
+    }
+
+    #[cfg(feature = "cbor")]
+    #[tokio::test]
+    async fn test_cbor() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Reading {
+            sensor: u32,
+            celsius: f32,
+        }
+
+        let reading = Reading {
+            sensor: 7,
+            celsius: 21.5,
+        };
+        let res: http::Response = crate::Response::cbor(&reading).unwrap().into();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/cbor");
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let req = |content_type: &str| {
+            let req = hyper::Request::post("/")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(hyper::Body::from(body.clone()))
+                .unwrap();
+            Request::new(req, HashMap::new())
+        };
+        assert_eq!(
+            req("application/cbor").cbor::<Reading>().await.unwrap(),
+            reading
+        );
+        let err = req("application/json").cbor::<Reading>().await;
+        assert!(
+            matches!(err, Err(BodyError::ContentType(Some(media_type))) if media_type == "application/json")
+        );
+        let mut bare = Request::new(
+            hyper::Request::post("/")
+                .body(hyper::Body::from(body.clone()))
+                .unwrap(),
+            HashMap::new(),
+        );
+        let err = bare.cbor::<Reading>().await;
+        assert!(matches!(err, Err(BodyError::ContentType(None))));
+    }
+
+    #[cfg(feature = "cookies")]
+    #[test]
+    fn test_cookies() {
+        let req = hyper::Request::builder()
+            .header("cookie", "session=abc123; theme=\"dark\"")
+            .header("cookie", "csrf=xyz; session=ignored")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let mut req = Request::new(req, HashMap::new());
+
+        let cookies = req.cookies();
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies["session"], "abc123");
+        assert_eq!(cookies["theme"], "dark");
+        assert_eq!(cookies["csrf"], "xyz");
+        assert!(req.req.extensions().get::<Cookies>().is_some());
+        assert_eq!(req.cookie("csrf").as_deref(), Some("xyz"));
+    }
+
+    #[cfg(feature = "cookies")]
+    #[test]
+    fn test_cookie() {
+        let req = hyper::Request::builder()
+            .header("cookie", "session=abc123; theme=dark; lang=\"en\"")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let req = Request::new(req, HashMap::new());
+        assert_eq!(req.cookie("session").as_deref(), Some("abc123"));
+        assert_eq!(req.cookie("theme").as_deref(), Some("dark"));
+        assert_eq!(req.cookie("lang").as_deref(), Some("en"));
+        assert_eq!(req.cookie("missing"), None);
+
+        let req = hyper::Request::builder()
+            .header("cookie", "theme=dark%3B%20Domain%3Devil.com")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let req = Request::new(req, HashMap::new());
+        assert_eq!(
+            req.cookie("theme").as_deref(),
+            Some("dark; Domain=evil.com")
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct UserId(u64);
+
+    struct Auth;
+
+    #[async_trait::async_trait]
+    impl Middleware for Auth {
+        async fn handle(&self, mut req: Request, next: Next) -> Response {
+            assert!(req.ext::<UserId>().is_none());
+            req.insert_ext(UserId(42));
+            next.run(req).await
+        }
+    }
+
+    async fn profile(req: Request) -> Response {
+        let UserId(id) = req.ext::<UserId>().unwrap();
+        Response::text(format!("user {}", id))
+    }
+
+    #[tokio::test]
+    async fn test_ext() {
+        let next = Next::new(Arc::new(vec![Arc::new(Auth)]), Arc::new(profile));
+        let req = hyper::Request::get("/").body(Body::empty()).unwrap();
+        let res: http::Response = next.run(Request::new(req, HashMap::new())).await.into();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"user 42");
+
+        let req = hyper::Request::get("/").body(Body::empty()).unwrap();
+        let mut req = Request::new(req, HashMap::new());
+        req.insert_ext(UserId(1));
+        req.insert_ext(UserId(2));
+        assert_eq!(req.ext::<UserId>(), Some(&UserId(2)));
+    }
 }
@@ -1,12 +1,1373 @@
-use crate::http;
+use std::{
+    io::SeekFrom,
+    path::Path,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::UNIX_EPOCH,
+};
+
+use futures::{Stream, StreamExt};
+use hyper::body::{Bytes, HttpBody, Sender, SizeHint};
+use hyper::header::{self, HeaderValue, IntoHeaderName};
+use hyper::http::Extensions;
+use hyper::{Body, HeaderMap, StatusCode};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::{http, Request, Result};
+
+/// The message of the error a response was created from, rendered in place
+/// of its body in dev mode.
+pub(crate) struct ErrorDetail(pub String);
 
 #[derive(Default)]
 pub struct Response {
     res: http::Response,
 }
 
+impl Response {
+    /// Creates a plain text response with `Content-Type: text/plain;
+    /// charset=utf-8`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn index(req: Request) -> Response {
+    ///     Response::text("hello world")
+    /// }
+    /// ```
+    pub fn text(body: impl Into<String>) -> Response {
+        Response::buffered("text/plain; charset=utf-8", body.into())
+    }
+
+    /// Creates an HTML response with `Content-Type: text/html; charset=utf-8`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn index(req: Request) -> Response {
+    ///     Response::html("<h1>hello world</h1>")
+    /// }
+    /// ```
+    pub fn html(body: impl Into<String>) -> Response {
+        Response::buffered("text/html; charset=utf-8", body.into())
+    }
+
+    /// Creates a response with the value serialized as JSON and
+    /// `Content-Type: application/json`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use serde::Serialize;
+    /// use sidemount::{Request, Response};
+    ///
+    /// #[derive(Serialize)]
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// async fn user(req: Request) -> Response {
+    ///     match Response::json(&User { name: String::from("ada") }) {
+    ///         Ok(res) => res,
+    ///         Err(err) => Response::from_error(err),
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize>(
+        value: &T,
+    ) -> std::result::Result<Response, serde_json::Error> {
+        let body = serde_json::to_vec(value)?;
+        Ok(Response::buffered("application/json", body))
+    }
+
+    /// Creates a response with the value serialized as JSON, see
+    /// [Response::json], for values that always serialize such as plain
+    /// structs without maps keyed by non-strings.
+    ///
+    /// ## Panics
+    /// Panics if the value fails to serialize.
+    #[cfg(feature = "json")]
+    pub fn json_unchecked<T: serde::Serialize>(value: &T) -> Response {
+        Response::json(value).expect("Failed to serialize JSON response")
+    }
+
+    /// Creates a response with the value serialized as CBOR and
+    /// `Content-Type: application/cbor`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use serde::Serialize;
+    /// use sidemount::{Request, Response};
+    ///
+    /// #[derive(Serialize)]
+    /// struct Config {
+    ///     interval_secs: u32,
+    /// }
+    ///
+    /// async fn config(req: Request) -> sidemount::Result<Response> {
+    ///     Response::cbor(&Config { interval_secs: 60 })
+    /// }
+    /// ```
+    #[cfg(feature = "cbor")]
+    pub fn cbor<T: serde::Serialize>(value: &T) -> Result<Response> {
+        let mut body = Vec::new();
+        ciborium::ser::into_writer(value, &mut body)?;
+        Ok(Response::buffered("application/cbor", body))
+    }
+
+    /// Creates a `500 Internal Server Error` response for the error, logging it
+    /// at error level. The body is a generic message, or the error message
+    /// when the server is in dev mode (see [crate::Server::with_dev_mode]).
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn config(req: Request) -> Response {
+    ///     match tokio::fs::read_to_string("config.toml").await {
+    ///         Ok(config) => Response::text(config),
+    ///         Err(err) => Response::from_error(err),
+    ///     }
+    /// }
+    /// ```
+    pub fn from_error(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Response {
+        let err = err.into();
+        log::error!("{}", err);
+        let mut res = Response::buffered("text/plain; charset=utf-8", "Internal Server Error");
+        *res.res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        res.extensions_mut().insert(ErrorDetail(err.to_string()));
+        res
+    }
+
+    /// Creates a response streaming the chunks of the stream with chunked
+    /// encoding and `Content-Type: application/octet-stream`, which can be
+    /// overridden through [Response::headers_mut]. An error from the stream
+    /// aborts the response. Handlers can also return the stream wrapped in a
+    /// [StreamBody].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn numbers(req: Request) -> Response {
+    ///     let chunks = (0..3).map(|n| Ok::<_, std::io::Error>(n.to_string()));
+    ///     Response::stream(futures::stream::iter(chunks))
+    /// }
+    /// ```
+    pub fn stream<S, O, E>(stream: S) -> Response
+    where
+        S: Stream<Item = std::result::Result<O, E>> + Send + 'static,
+        O: Into<Bytes> + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        Response::streaming("application/octet-stream", Body::wrap_stream(stream))
+    }
+
+    /// Creates a response streaming each string of the stream as a chunk of
+    /// text with `Content-Type: text/plain; charset=utf-8`, which can be
+    /// overridden through [Response::headers_mut]. See
+    /// [crate::sse::EventStream] for server-sent events.
+    pub fn text_stream<S>(stream: S) -> Response
+    where
+        S: Stream<Item = String> + Send + 'static,
+    {
+        let stream = stream.map(Ok::<_, std::convert::Infallible>);
+        Response::streaming("text/plain; charset=utf-8", Body::wrap_stream(stream))
+    }
+
+    fn streaming(content_type: &'static str, body: Body) -> Response {
+        let res = hyper::Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(body)
+            .unwrap();
+        Response { res }
+    }
+
+    /// Creates a response with a fully buffered body, setting `Content-Length`
+    /// from its size so it is never sent with chunked encoding.
+    fn buffered(content_type: &'static str, body: impl Into<Bytes>) -> Response {
+        let body = body.into();
+        let res = hyper::Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap();
+        Response { res }
+    }
+
+    /// Creates a response streaming the file at the given path, setting the
+    /// `Content-Type` guessed from its extension along with `Content-Length`
+    /// and `Last-Modified`. Returns an error if the file cannot be opened.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn favicon(req: Request) -> Response {
+    ///     match Response::file("static/favicon.ico").await {
+    ///         Ok(res) => res,
+    ///         Err(_) => Response::default(),
+    ///     }
+    /// }
+    /// ```
+    pub async fn file(path: impl AsRef<Path>) -> Result<Response> {
+        let path = path.as_ref();
+        let file = tokio::fs::File::open(path).await?;
+        let metadata = file.metadata().await?;
+        if !metadata.is_file() {
+            return Err(format!("{} is not a file", path.display()).into());
+        }
+
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+        let mut res = hyper::Response::builder()
+            .header(header::CONTENT_TYPE, mime.as_ref())
+            .header(header::CONTENT_LENGTH, metadata.len());
+        if let Ok(modified) = metadata.modified() {
+            res = res.header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+        }
+        let res = res.body(Body::wrap_stream(ReaderStream::new(file)))?;
+        Ok(Response { res })
+    }
+
+    /// Creates a response for the file at the given path that honors the
+    /// `Range` and `If-Range` headers of the request, for resumable downloads.
+    ///
+    /// A single satisfiable byte range produces a `206 Partial Content`
+    /// response, unless an `If-Range` validator (an `ETag` or a date) no longer
+    /// matches the file, in which case the full file is sent with a `200` so a
+    /// client never stitches together parts of different versions. Ranges that
+    /// cannot be satisfied produce a `416 Range Not Satisfiable`.
+    pub async fn partial_file(req: &Request, path: impl AsRef<Path>) -> Result<Response> {
+        let path = path.as_ref();
+        let mut file = tokio::fs::File::open(path).await?;
+        let metadata = file.metadata().await?;
+        if !metadata.is_file() {
+            return Err(format!("{} is not a file", path.display()).into());
+        }
+
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+        let last_modified = modified.map(httpdate::fmt_http_date);
+        let etag = modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| format!("\"{:x}-{:x}\"", len, modified.as_nanos()));
+
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+        let mut res = hyper::Response::builder()
+            .header(header::CONTENT_TYPE, mime.as_ref())
+            .header(header::ACCEPT_RANGES, "bytes");
+        if let Some(last_modified) = &last_modified {
+            res = res.header(header::LAST_MODIFIED, last_modified);
+        }
+        if let Some(etag) = &etag {
+            res = res.header(header::ETAG, etag);
+        }
+
+        let headers = req.headers();
+        let range = headers
+            .get(header::RANGE)
+            .and_then(|range| range.to_str().ok())
+            .filter(|_| if_range_matches(headers, etag.as_deref(), last_modified.as_deref()))
+            .and_then(|range| parse_range(range, len));
+
+        let res = match range {
+            Some(Some((start, end))) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                let body = ReaderStream::new(file.take(end - start + 1));
+                res.status(StatusCode::PARTIAL_CONTENT)
+                    .header(
+                        header::CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", start, end, len),
+                    )
+                    .header(header::CONTENT_LENGTH, end - start + 1)
+                    .body(Body::wrap_stream(body))?
+            }
+            Some(None) => res
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty())?,
+            None => res
+                .header(header::CONTENT_LENGTH, len)
+                .body(Body::wrap_stream(ReaderStream::new(file)))?,
+        };
+        Ok(Response { res })
+    }
+
+    /// Creates a `302 Found` redirect to the location, falling back to `/`
+    /// when the location is empty or not a valid header value.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn old_profile(req: Request) -> Response {
+    ///     Response::redirect("/profile")
+    /// }
+    /// ```
+    pub fn redirect(location: impl Into<String>) -> Response {
+        Response::redirect_with(StatusCode::FOUND, location.into())
+    }
+
+    /// Creates a `301 Moved Permanently` redirect to the location, see
+    /// [Response::redirect].
+    pub fn redirect_permanent(location: impl Into<String>) -> Response {
+        Response::redirect_with(StatusCode::MOVED_PERMANENTLY, location.into())
+    }
+
+    /// Creates a `303 See Other` redirect to the location, such as after a
+    /// form submission, see [Response::redirect].
+    pub fn redirect_see_other(location: impl Into<String>) -> Response {
+        Response::redirect_with(StatusCode::SEE_OTHER, location.into())
+    }
+
+    fn redirect_with(status: StatusCode, location: String) -> Response {
+        let location = match location.trim() {
+            "" => "/",
+            location => location,
+        };
+        let location =
+            HeaderValue::from_str(location).unwrap_or_else(|_| HeaderValue::from_static("/"));
+        let mut res = Response::from(status);
+        res.res.headers_mut().insert(header::LOCATION, location);
+        res
+    }
+
+    /// Sets the status code of the response.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use hyper::StatusCode;
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn create(req: Request) -> Response {
+    ///     Response::text("created")
+    ///         .status(StatusCode::CREATED)
+    ///         .header("x-id", "1")
+    /// }
+    /// ```
+    pub fn status(mut self, status: StatusCode) -> Self {
+        *self.res.status_mut() = status;
+        self
+    }
+
+    /// Sets the header, replacing any previous values. A value that is not a
+    /// valid header value is ignored.
+    pub fn header<V>(mut self, name: impl IntoHeaderName, value: V) -> Self
+    where
+        V: TryInto<HeaderValue>,
+    {
+        if let Ok(value) = value.try_into() {
+            self.res.headers_mut().insert(name, value);
+        }
+        self
+    }
+
+    /// Adds a `Link` header asking the browser to preload the resource, such
+    /// as a stylesheet with `kind` set to `style`, keeping any links added
+    /// before. A link that is not a valid header value is ignored.
+    ///
+    /// ## Early Hints
+    /// The links are sent with the final response: `103 Early Hints` cannot be
+    /// sent ahead of it, as hyper 0.14 has no API for interim responses.
+    ///
+    /// - Over HTTP/1.1, an interim response is written on the connection before
+    ///   the final one, but hyper replaces a 1xx status returned by a service
+    ///   with a `500`. Some HTTP/1.1 clients and proxies also mishandle
+    ///   unexpected 1xx responses, so early hints are best limited to HTTP/2.
+    /// - Over HTTP/2, interim responses are extra header frames on the stream
+    ///   of the request, which hyper does not expose to services.
+    ///
+    /// Browsers still start preloading as soon as the headers arrive, so
+    /// streaming the body with [Response::channel] or [Response::stream] gets
+    /// most of the benefit while the page is being generated.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn page(req: Request) -> Response {
+    ///     Response::html("<link rel=\"stylesheet\" href=\"/site.css\">")
+    ///         .preload("/site.css", "style")
+    ///         .preload("/app.js", "script")
+    /// }
+    /// ```
+    pub fn preload(mut self, uri: &str, kind: &str) -> Self {
+        let link = format!("<{}>; rel=preload; as={}", uri, kind);
+        if let Ok(link) = HeaderValue::from_str(&link) {
+            self.res.headers_mut().append(header::LINK, link);
+        }
+        self
+    }
+
+    /// Replaces the body of the response, dropping any `Content-Length` of the
+    /// previous body. The `Content-Type` is kept.
+    pub fn body(mut self, body: impl Into<Body>) -> Self {
+        self.res.headers_mut().remove(header::CONTENT_LENGTH);
+        *self.res.body_mut() = body.into();
+        self
+    }
+
+    /// Gets the status code of the response.
+    pub fn status_code(&self) -> StatusCode {
+        self.res.status()
+    }
+
+    /// Gets a reference to the response headers.
+    pub fn headers(&self) -> &HeaderMap {
+        self.res.headers()
+    }
+
+    /// Gets a mutable reference to the response headers.
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        self.res.headers_mut()
+    }
+
+    /// Appends a `Set-Cookie` header for the cookie, keeping any cookies set
+    /// before.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Cookie, Request, Response};
+    ///
+    /// async fn login(req: Request) -> Response {
+    ///     let mut res = Response::text("welcome");
+    ///     res.set_cookie(Cookie::new("user", "ada").http_only(true).secure(true));
+    ///     res
+    /// }
+    /// ```
+    #[cfg(feature = "cookies")]
+    pub fn set_cookie(&mut self, cookie: crate::Cookie) {
+        match HeaderValue::from_str(&cookie.to_string()) {
+            Ok(value) => {
+                self.res.headers_mut().append(header::SET_COOKIE, value);
+            }
+            Err(_) => log::warn!("Ignoring cookie with an invalid path or domain: {}", cookie),
+        }
+    }
+
+    /// Gets a reference to the extensions attached to the response.
+    pub fn extensions(&self) -> &Extensions {
+        self.res.extensions()
+    }
+
+    /// Gets a mutable reference to the extensions attached to the response,
+    /// for passing typed data from a handler to the middleware it returns
+    /// through, such as [crate::middleware::SkipAccessLog].
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        self.res.extensions_mut()
+    }
+
+    /// Adds the request header name to `Vary`, telling caches the response
+    /// depends on it. Existing values are merged into a single header and
+    /// names already listed, in any case, are not repeated; a `Vary: *`
+    /// already covers every header and is left as is.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn greeting(req: Request) -> Response {
+    ///     let french = req.headers().get("accept-language").map_or(false, |lang| lang == "fr");
+    ///     let mut res = Response::text(if french { "bonjour" } else { "hello" });
+    ///     res.add_vary("Accept-Language");
+    ///     res
+    /// }
+    /// ```
+    pub fn add_vary(&mut self, name: &str) {
+        let mut names = self
+            .res
+            .headers()
+            .get_all(header::VARY)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>();
+        if names.iter().any(|existing| existing == "*") {
+            names = vec![String::from("*")];
+        } else if name == "*" {
+            names = vec![String::from(name)];
+        } else if !names
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(name))
+        {
+            names.push(String::from(name));
+        }
+        if let Ok(value) = HeaderValue::from_str(&names.join(", ")) {
+            self.res.headers_mut().insert(header::VARY, value);
+        }
+    }
+
+    /// Creates a streaming response along with the sender used to write its
+    /// body, which can finish the stream with trailers computed along the way
+    /// such as a checksum or a gRPC status.
+    ///
+    /// Trailers are only delivered over HTTP/2; HTTP/1 connections send the
+    /// body and silently drop them, so clients must not rely on them there.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{http, Request, Response};
+    ///
+    /// async fn stream(req: Request) -> Response {
+    ///     let (mut sender, res) = Response::channel();
+    ///     tokio::spawn(async move {
+    ///         sender.send_data("hello".into()).await?;
+    ///         let mut trailers = http::HeaderMap::new();
+    ///         trailers.insert("grpc-status", "0".parse()?);
+    ///         sender.send_trailers(trailers).await?;
+    ///         sidemount::Result::Ok(())
+    ///     });
+    ///     res
+    /// }
+    /// ```
+    pub fn channel() -> (Sender, Response) {
+        let (sender, body) = Body::channel();
+        let res = Response {
+            res: hyper::Response::new(body),
+        };
+        (sender, res)
+    }
+
+    /// Sends the trailers after the response body completes, along with any
+    /// trailers of the body itself, announcing their names in the `Trailer`
+    /// header. Trailers are only delivered over HTTP/2, see
+    /// [Response::channel].
+    ///
+    /// The trailers are kept with the response and sent by the connections of
+    /// a [crate::Server], so responses returned by
+    /// [crate::Server::handle_request] do not carry them in their body.
+    pub fn trailers(&mut self, trailers: HeaderMap) {
+        let trailers = match self.res.extensions_mut().remove::<Trailers>() {
+            Some(Trailers(mut existing)) => {
+                existing.extend(trailers);
+                existing
+            }
+            None => trailers,
+        };
+        let names = trailers
+            .keys()
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>();
+        if let Ok(names) = HeaderValue::from_str(&names.join(", ")) {
+            self.res.headers_mut().insert(header::TRAILER, names);
+        }
+        self.res.extensions_mut().insert(Trailers(trailers));
+    }
+
+    /// Registers a callback that is called once the response body is no longer
+    /// being sent, with the number of bytes actually handed to the connection
+    /// and whether the body was sent in full. A client disconnecting midway
+    /// through a stream results in `complete` being `false`.
+    ///
+    /// Trailers sent through [Response::channel] are not sent; those set with
+    /// [Response::trailers] are.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn download(req: Request) -> Response {
+    ///     let mut res = match Response::file("static/archive.zip").await {
+    ///         Ok(res) => res,
+    ///         Err(_) => return Response::default(),
+    ///     };
+    ///     res.on_complete(|stats| {
+    ///         println!("sent {} bytes, complete: {}", stats.bytes, stats.complete);
+    ///     });
+    ///     res
+    /// }
+    /// ```
+    pub fn on_complete(&mut self, callback: impl FnOnce(BodyStats) + Send + 'static) {
+        let body = std::mem::take(self.res.body_mut());
+        *self.res.body_mut() = Body::wrap_stream(CountingBody {
+            body,
+            stats: BodyStats::default(),
+            callback: Some(Box::new(callback)),
+        });
+    }
+}
+
+/// Trailers set with [Response::trailers], sent after the body by
+/// [TrailersBody].
+pub(crate) struct Trailers(HeaderMap);
+
+/// Body wrapper sending the trailers set with [Response::trailers] once the
+/// body completes, merged over any trailers of the body itself.
+pub(crate) struct TrailersBody {
+    body: Body,
+    trailers: Option<HeaderMap>,
+}
+
+impl TrailersBody {
+    /// Wraps the body of the response, taking the trailers set on it.
+    pub(crate) fn wrap(res: http::Response) -> hyper::Response<TrailersBody> {
+        let (mut parts, body) = res.into_parts();
+        let trailers = parts
+            .extensions
+            .remove::<Trailers>()
+            .map(|trailers| trailers.0);
+        hyper::Response::from_parts(parts, TrailersBody { body, trailers })
+    }
+}
+
+impl HttpBody for TrailersBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Bytes, hyper::Error>>> {
+        Pin::new(&mut self.body).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::result::Result<Option<HeaderMap>, hyper::Error>> {
+        let inner = ready!(Pin::new(&mut self.body).poll_trailers(cx))?;
+        let trailers = match (inner, self.trailers.take()) {
+            (Some(mut inner), Some(trailers)) => {
+                inner.extend(trailers);
+                Some(inner)
+            }
+            (inner, trailers) => trailers.or(inner),
+        };
+        Poll::Ready(Ok(trailers))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.trailers.is_none() && self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        HttpBody::size_hint(&self.body)
+    }
+}
+
+/// Statistics about a sent response body, see [Response::on_complete].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BodyStats {
+    /// Number of body bytes handed to the connection.
+    pub bytes: u64,
+    /// Whether the end of the body was reached.
+    pub complete: bool,
+}
+
+/// Body wrapper counting the bytes polled from the inner body, reporting them
+/// when dropped, which happens both on completion and on disconnect.
+struct CountingBody {
+    body: Body,
+    stats: BodyStats,
+    callback: Option<Box<dyn FnOnce(BodyStats) + Send>>,
+}
+
+impl Stream for CountingBody {
+    type Item = hyper::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.body).poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => self.stats.bytes += chunk.len() as u64,
+            Poll::Ready(None) => self.stats.complete = true,
+            _ => {}
+        }
+        poll
+    }
+}
+
+impl Drop for CountingBody {
+    fn drop(&mut self) {
+        if let Some(callback) = self.callback.take() {
+            callback(self.stats);
+        }
+    }
+}
+
+/// A value a handler can return, converted into the [Response] sent to the
+/// client.
+///
+/// ## Examples
+/// ```rust
+/// use hyper::StatusCode;
+/// use sidemount::Request;
+///
+/// async fn index(req: Request) -> &'static str {
+///     "hello world"
+/// }
+///
+/// async fn create(req: Request) -> (StatusCode, String) {
+///     (StatusCode::CREATED, format!("created {}", req.path()))
+/// }
+///
+/// let mut app = sidemount::new();
+/// app.at("/").get(index);
+/// app.at("/users").post(create);
+/// ```
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        Response::text(self)
+    }
+}
+
+impl IntoResponse for &'static str {
+    fn into_response(self) -> Response {
+        self.into()
+    }
+}
+
+impl IntoResponse for StatusCode {
+    fn into_response(self) -> Response {
+        self.into()
+    }
+}
+
+impl IntoResponse for (StatusCode, String) {
+    fn into_response(self) -> Response {
+        self.into()
+    }
+}
+
+/// An empty `200 OK` response.
+impl IntoResponse for () {
+    fn into_response(self) -> Response {
+        Response::default()
+    }
+}
+
+/// A stream of chunks returned from a handler, sent like [Response::stream]
+/// with chunked encoding and `Content-Type: application/octet-stream` unless
+/// another content type is set with [StreamBody::content_type].
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::{Request, StreamBody};
+///
+/// async fn export(req: Request) -> StreamBody<impl futures::Stream<Item = std::io::Result<String>>> {
+///     let rows = ["id,name\n", "1,ada\n"].map(|row| Ok(String::from(row)));
+///     StreamBody::new(futures::stream::iter(rows)).content_type("text/csv")
+/// }
+///
+/// let mut app = sidemount::new();
+/// app.at("/export").get(export);
+/// ```
+pub struct StreamBody<S> {
+    stream: S,
+    content_type: &'static str,
+}
+
+impl<S> StreamBody<S> {
+    /// Wraps the stream to send as the response body.
+    pub fn new(stream: S) -> Self {
+        StreamBody {
+            stream,
+            content_type: "application/octet-stream",
+        }
+    }
+
+    /// Sets the `Content-Type` of the response.
+    pub fn content_type(mut self, content_type: &'static str) -> Self {
+        self.content_type = content_type;
+        self
+    }
+}
+
+impl<S, O, E> IntoResponse for StreamBody<S>
+where
+    S: Stream<Item = std::result::Result<O, E>> + Send + 'static,
+    O: Into<Bytes> + 'static,
+    E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+{
+    fn into_response(self) -> Response {
+        Response::streaming(self.content_type, Body::wrap_stream(self.stream))
+    }
+}
+
+/// The response of the value, or of the error, so handlers can use `?`.
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for std::result::Result<T, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(err) => err.into_response(),
+        }
+    }
+}
+
+/// The client error for the request body, such as `413 Payload Too Large` for
+/// a body over the limit of the server, with the error message as the body.
+impl IntoResponse for crate::BodyError {
+    fn into_response(self) -> Response {
+        let status = client_status(&self).unwrap_or(StatusCode::BAD_REQUEST);
+        (status, self.to_string()).into_response()
+    }
+}
+
+/// A `400 Bad Request` with the error message as the body.
+impl IntoResponse for crate::ParamError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+    }
+}
+
+/// The response of [Response::from_error] for handlers returning
+/// [crate::Result], so the message only reaches the client in dev mode. A
+/// [crate::BodyError] or [crate::ParamError] keeps its client error status
+/// and message.
+impl IntoResponse for Box<dyn std::error::Error + Send + Sync> {
+    fn into_response(self) -> Response {
+        match client_status(&*self) {
+            Some(status) => (status, self.to_string()).into_response(),
+            None => Response::from_error(self),
+        }
+    }
+}
+
+/// The client error status for errors caused by the request, which are safe
+/// to show to the client.
+fn client_status(err: &(dyn std::error::Error + 'static)) -> Option<StatusCode> {
+    if let Some(err) = err.downcast_ref::<crate::BodyError>() {
+        return Some(match err {
+            crate::BodyError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            crate::BodyError::ContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            crate::BodyError::Read(_) | crate::BodyError::Deserialize(_) => StatusCode::BAD_REQUEST,
+        });
+    }
+    err.is::<crate::ParamError>()
+        .then_some(StatusCode::BAD_REQUEST)
+}
+
+impl From<String> for Response {
+    fn from(body: String) -> Self {
+        Response::text(body)
+    }
+}
+
+impl From<&str> for Response {
+    fn from(body: &str) -> Self {
+        Response::text(body)
+    }
+}
+
+impl From<(StatusCode, String)> for Response {
+    fn from((status, body): (StatusCode, String)) -> Self {
+        Response::text(body).status(status)
+    }
+}
+
+impl From<Vec<u8>> for Response {
+    fn from(body: Vec<u8>) -> Self {
+        Response::buffered("application/octet-stream", body)
+    }
+}
+
+impl From<StatusCode> for Response {
+    fn from(status: StatusCode) -> Self {
+        let mut res = Response::default();
+        *res.res.status_mut() = status;
+        res
+    }
+}
+
+impl From<http::Response> for Response {
+    fn from(res: http::Response) -> Self {
+        Response { res }
+    }
+}
+
 impl From<Response> for http::Response {
     fn from(res: Response) -> Self {
         res.res
     }
 }
+
+/// Determines if the `If-Range` validator of the request, if any, still
+/// matches the resource. ETags use the strong comparison required by RFC 7233
+/// and dates must exactly match the `Last-Modified` date.
+fn if_range_matches(headers: &HeaderMap, etag: Option<&str>, last_modified: Option<&str>) -> bool {
+    let if_range = match headers.get(header::IF_RANGE) {
+        Some(if_range) => match if_range.to_str() {
+            Ok(if_range) => if_range.trim(),
+            Err(_) => return false,
+        },
+        None => return true,
+    };
+    if if_range.starts_with('"') {
+        Some(if_range) == etag
+    } else if if_range.starts_with("W/") {
+        false
+    } else {
+        Some(if_range) == last_modified
+    }
+}
+
+/// Parses a single `bytes` range header against the length of the resource.
+///
+/// Returns `None` when the header should be ignored (malformed, another unit
+/// or multiple ranges), and `Some(None)` when the range cannot be satisfied.
+fn parse_range(range: &str, len: u64) -> Option<Option<(u64, u64)>> {
+    let range = range.trim().strip_prefix("bytes=")?;
+    if range.contains(',') {
+        return None;
+    }
+    let (start, end) = range.trim().split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return Some(None);
+            }
+            (len.saturating_sub(suffix), len.saturating_sub(1))
+        }
+        (start, "") => (start.parse().ok()?, len.saturating_sub(1)),
+        (start, end) => {
+            let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+            if end < start {
+                return None;
+            }
+            (start, end.min(len.saturating_sub(1)))
+        }
+    };
+    if start >= len {
+        return Some(None);
+    }
+    Some(Some((start, end)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file() {
+        let path = std::env::temp_dir().join("sidemount-test-file.html");
+        std::fs::write(&path, "<h1>hello</h1>").unwrap();
+
+        let res: http::Response = Response::file(&path).await.unwrap().into();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "14");
+        assert!(res.headers().contains_key(header::LAST_MODIFIED));
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"<h1>hello</h1>");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_missing() {
+        let path = std::env::temp_dir().join("sidemount-test-missing.txt");
+        assert!(Response::file(&path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_text() {
+        let res: http::Response = Response::text("hello").into();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "5");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+
+        let res: http::Response = Response::html("<p>héllo</p>").into();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "13");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], "<p>héllo</p>".as_bytes());
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn test_json() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct User {
+            id: u32,
+            name: String,
+        }
+        let user = User {
+            id: 7,
+            name: String::from("ada"),
+        };
+
+        for res in [
+            Response::json(&user).unwrap(),
+            Response::json_unchecked(&user),
+        ] {
+            let res: http::Response = res.into();
+            assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(serde_json::from_slice::<User>(&body).unwrap(), user);
+        }
+
+        let keyed_by_tuple = std::collections::HashMap::from([((1, 2), "point")]);
+        assert!(Response::json(&keyed_by_tuple).is_err());
+    }
+
+    #[test]
+    fn test_from_error() {
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, "config.toml missing");
+        let res: http::Response = Response::from_error(err).into();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "21");
+        let detail = res.extensions().get::<ErrorDetail>().unwrap();
+        assert_eq!(detail.0, "config.toml missing");
+    }
+
+    #[test]
+    fn test_extensions() {
+        struct CacheFor(u64);
+
+        let mut res = Response::text("hello");
+        res.extensions_mut().insert(CacheFor(60));
+        assert_eq!(res.extensions().get::<CacheFor>().unwrap().0, 60);
+        let res: http::Response = res.into();
+        assert!(res.extensions().get::<CacheFor>().is_some());
+    }
+
+    #[cfg(feature = "cookies")]
+    #[test]
+    fn test_set_cookie() {
+        use crate::{Cookie, SameSite};
+
+        let mut res = Response::default();
+        res.set_cookie(
+            Cookie::new("session", "abc123")
+                .path("/")
+                .domain("example.com")
+                .http_only(true)
+                .secure(true)
+                .same_site(SameSite::Strict)
+                .max_age(std::time::Duration::from_secs(60)),
+        );
+        res.set_cookie(Cookie::new("theme", "dark"));
+        let cookies: Vec<_> = res.headers().get_all(header::SET_COOKIE).iter().collect();
+        assert_eq!(
+            cookies,
+            [
+                "session=abc123; HttpOnly; SameSite=Strict; Secure; Path=/; Domain=example.com; Max-Age=60",
+                "theme=dark",
+            ]
+        );
+
+        let mut res = Response::default();
+        res.set_cookie(Cookie::new("theme", "dark; Domain=evil.com"));
+        assert_eq!(
+            res.headers()[header::SET_COOKIE],
+            "theme=dark%3B%20Domain%3Devil.com"
+        );
+    }
+
+    #[test]
+    fn test_add_vary() {
+        let mut res = Response::default();
+        res.headers_mut()
+            .append(header::VARY, "accept-encoding".parse().unwrap());
+        res.headers_mut()
+            .append(header::VARY, "Cookie, ".parse().unwrap());
+        res.add_vary("Accept-Encoding");
+        res.add_vary("Origin");
+        res.add_vary("origin");
+        let vary = res
+            .headers()
+            .get_all(header::VARY)
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(vary, ["accept-encoding, Cookie, Origin"]);
+
+        res.add_vary("*");
+        res.add_vary("Accept");
+        assert_eq!(res.headers()[header::VARY], "*");
+    }
+
+    #[test]
+    fn test_preload() {
+        let res = Response::text("hello")
+            .preload("/site.css", "style")
+            .preload("/app.js", "script")
+            .preload("/bad\n", "script");
+        let links = res
+            .headers()
+            .get_all(header::LINK)
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            links,
+            [
+                "</site.css>; rel=preload; as=style",
+                "</app.js>; rel=preload; as=script"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_from_body_types() {
+        let res: http::Response = Response::from("hello").into();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        let res: http::Response = Response::from(String::from("hello")).into();
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "5");
+
+        let res: Response = "hello".into();
+        assert_eq!(res.status_code(), StatusCode::OK);
+        let body = hyper::body::to_bytes(http::Response::from(res).into_body())
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"hello");
+        let borrowed = String::from("borrowed");
+        let res: Response = borrowed.as_str().into();
+        let body = hyper::body::to_bytes(http::Response::from(res).into_body())
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"borrowed");
+        let res: Response = String::from("owned").into();
+        assert_eq!(res.status_code(), StatusCode::OK);
+        let body = hyper::body::to_bytes(http::Response::from(res).into_body())
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"owned");
+
+        let res: Response = (StatusCode::CREATED, String::from("created")).into();
+        assert_eq!(res.status_code(), StatusCode::CREATED);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        let body = hyper::body::to_bytes(http::Response::from(res).into_body())
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"created");
+
+        let res: http::Response = Response::from(vec![1, 2, 3]).into();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/octet-stream"
+        );
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], &[1, 2, 3]);
+
+        let res: http::Response = Response::from(StatusCode::NO_CONTENT).into();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(res.headers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_builder() {
+        let res = Response::default()
+            .status(StatusCode::CREATED)
+            .header("x-id", "1")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .header("x-invalid", "line\nbreak")
+            .body("created");
+        assert_eq!(res.status_code(), StatusCode::CREATED);
+        assert_eq!(res.headers()["x-id"], "1");
+        assert!(!res.headers().contains_key("x-invalid"));
+        let body = hyper::body::to_bytes(http::Response::from(res).into_body())
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"created");
+
+        let res = Response::text("hello").header("x-id", "2").body("hi");
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+        assert_eq!(res.headers()["x-id"], "2");
+    }
+
+    #[test]
+    fn test_redirect() {
+        let res = Response::redirect("/profile");
+        assert_eq!(res.status_code(), StatusCode::FOUND);
+        assert_eq!(res.headers()[header::LOCATION], "/profile");
+
+        let res = Response::redirect_permanent(String::from("https://example.com/"));
+        assert_eq!(res.status_code(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers()[header::LOCATION], "https://example.com/");
+
+        let res = Response::redirect_see_other("/orders/7");
+        assert_eq!(res.status_code(), StatusCode::SEE_OTHER);
+        assert_eq!(res.headers()[header::LOCATION], "/orders/7");
+
+        let res = Response::redirect("");
+        assert_eq!(res.headers()[header::LOCATION], "/");
+        let res = Response::redirect("/line\nbreak");
+        assert_eq!(res.headers()[header::LOCATION], "/");
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        let chunks: Vec<std::io::Result<&str>> = vec![Ok("a"), Ok("b")];
+        let res: http::Response = Response::stream(futures::stream::iter(chunks)).into();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/octet-stream"
+        );
+        assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"ab");
+
+        let lines = futures::stream::iter(vec![String::from("data: 1\n\n")]);
+        let mut res = Response::text_stream(lines);
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/event-stream"),
+        );
+        let res: http::Response = res.into();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/event-stream");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"data: 1\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_on_complete() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let chunks: Vec<Result<&str>> = vec![Ok("hello "), Ok("world")];
+        let mut res = Response {
+            res: hyper::Response::new(Body::wrap_stream(futures::stream::iter(chunks))),
+        };
+        let sender = tx.clone();
+        res.on_complete(move |stats| sender.send(stats).unwrap());
+        let res: http::Response = res.into();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello world");
+        let stats = rx.recv().unwrap();
+        assert_eq!(stats.bytes, 11);
+        assert!(stats.complete);
+
+        let chunks: Vec<Result<&str>> = vec![Ok("hello "), Ok("world")];
+        let mut res = Response {
+            res: hyper::Response::new(Body::wrap_stream(futures::stream::iter(chunks))),
+        };
+        res.on_complete(move |stats| tx.send(stats).unwrap());
+        let res: http::Response = res.into();
+        let mut body = res.into_body();
+        assert_eq!(&body.data().await.unwrap().unwrap()[..], b"hello ");
+        drop(body);
+        let stats = rx.recv().unwrap();
+        assert_eq!(stats.bytes, 6);
+        assert!(!stats.complete);
+    }
+
+    #[test]
+    fn test_trailers() {
+        let mut res = Response::text("hello");
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        res.trailers(trailers);
+
+        let res = TrailersBody::wrap(res.into());
+        assert_eq!(res.headers()[header::TRAILER], "grpc-status");
+        let mut body = res.into_body();
+        futures::executor::block_on(async {
+            assert_eq!(&body.data().await.unwrap().unwrap()[..], b"hello");
+            assert!(body.data().await.is_none());
+            let trailers = body.trailers().await.unwrap().unwrap();
+            assert_eq!(trailers["grpc-status"], "0");
+        });
+    }
+
+    #[tokio::test]
+    async fn test_trailers_merged_with_body() {
+        let (mut sender, mut res) = Response::channel();
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        res.trailers(trailers);
+        tokio::spawn(async move {
+            sender.send_data("hello".into()).await.unwrap();
+            let mut trailers = HeaderMap::new();
+            trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+            trailers.insert("grpc-status", HeaderValue::from_static("2"));
+            sender.send_trailers(trailers).await.unwrap();
+        });
+
+        let mut body = TrailersBody::wrap(res.into()).into_body();
+        assert_eq!(&body.data().await.unwrap().unwrap()[..], b"hello");
+        assert!(body.data().await.is_none());
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["x-checksum"], "abc");
+        assert_eq!(trailers["grpc-status"], "0");
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-4", 10), Some(Some((0, 4))));
+        assert_eq!(parse_range("bytes=5-", 10), Some(Some((5, 9))));
+        assert_eq!(parse_range("bytes=-3", 10), Some(Some((7, 9))));
+        assert_eq!(parse_range("bytes=5-100", 10), Some(Some((5, 9))));
+        assert_eq!(parse_range("bytes=10-", 10), Some(None));
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+        assert_eq!(parse_range("bytes=5-1", 10), None);
+    }
+
+    async fn partial(path: &Path, headers: &[(&str, &str)]) -> http::Response {
+        let mut req = hyper::Request::get("/");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = Request::new(req.body(Body::empty()).unwrap(), Default::default());
+        Response::partial_file(&req, path).await.unwrap().into()
+    }
+
+    #[tokio::test]
+    async fn test_partial_file_if_range() {
+        let path = std::env::temp_dir().join("sidemount-test-partial.txt");
+        std::fs::write(&path, "0123456789").unwrap();
+
+        let res = partial(&path, &[]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let etag = res.headers()[header::ETAG].to_str().unwrap().to_owned();
+        let modified = res.headers()[header::LAST_MODIFIED]
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        let res = partial(&path, &[("range", "bytes=2-5")]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 2-5/10");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"2345");
+
+        let res = partial(&path, &[("range", "bytes=2-5"), ("if-range", &etag)]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        let res = partial(&path, &[("range", "bytes=2-5"), ("if-range", &modified)]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+
+        let res = partial(&path, &[("range", "bytes=2-5"), ("if-range", "\"stale\"")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"0123456789");
+
+        let stale = "Thu, 01 Jan 1970 00:00:00 GMT";
+        let res = partial(&path, &[("range", "bytes=2-5"), ("if-range", stale)]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = partial(&path, &[("range", "bytes=20-")]).await;
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes */10");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,27 +1,83 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{borrow::Cow, collections::HashMap, sync::Arc, time::Duration};
 
+use hyper::header;
+use hyper::HeaderMap;
+#[cfg(feature = "regex")]
+use regex::Regex;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::node::TraceStep;
+use crate::request::media_type;
 use crate::Method;
-use crate::{Handler, Node};
+use crate::{Handler, Middleware, Node};
+
+/// The params captured from the `{name}` segments of a matched path.
+pub type Params = HashMap<String, String>;
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum RouteResult<T> {
     NotFound,
-    MethodNotAllowed,
+    /// The path matched a route without a handler for the method, carrying
+    /// the methods the route does handle (see [Route::allowed_methods]).
+    MethodNotAllowed(Vec<Method>),
     Found(T),
 }
 
 impl<T> RouteResult<T> {
     pub fn is_found(&self) -> bool {
-        match self {
-            RouteResult::Found(_) => true,
-            _ => false,
-        }
+        matches!(self, RouteResult::Found(_))
     }
 
     pub fn is_not_allowed(&self) -> bool {
-        match self {
-            RouteResult::MethodNotAllowed => true,
-            _ => false,
+        matches!(self, RouteResult::MethodNotAllowed(_))
+    }
+}
+
+/// Explains how the router resolved a path and method, see [Router::explain].
+#[derive(Debug)]
+pub struct MatchExplanation {
+    /// The nodes tried and matched at each path segment.
+    pub steps: Vec<TraceStep>,
+    /// The template of the matched route, such as `/users/{id}`, when the path
+    /// matched a route rather than a fallback.
+    pub template: Option<String>,
+    /// Whether the result is from a fallback handler.
+    pub fallback: bool,
+    /// The result of routing the path and method.
+    pub result: RouteResult<()>,
+}
+
+/// A route matched by [Router::lookup], which captures its params only when
+/// they are read.
+pub struct RouteMatch<'a> {
+    root: &'a Node<Route>,
+    path: &'a str,
+    route: &'a Route,
+    handler: &'a Arc<dyn Handler>,
+    #[cfg(feature = "regex")]
+    regex: Option<&'a RegexRoute>,
+}
+
+impl<'a> RouteMatch<'a> {
+    /// Gets the matched route.
+    pub fn route(&self) -> &'a Route {
+        self.route
+    }
+
+    /// Gets the handler for the method.
+    pub fn handler(&self) -> &'a Arc<dyn Handler> {
+        self.handler
+    }
+
+    /// Gets the percent-decoded value of the param with the given name,
+    /// scanning the matched path segments on each call.
+    pub fn param(&self, name: &str) -> Option<Cow<'a, str>> {
+        #[cfg(feature = "regex")]
+        if let Some(entry) = self.regex {
+            let captures = entry.regex.captures(entry.rest(self.path)?)?;
+            return Some(crate::node::decode(captures.name(name)?.as_str()));
         }
+        self.root.param(self.path, name)
     }
 }
 
@@ -30,16 +86,298 @@ impl<T> RouteResult<T> {
 pub struct Route {
     methods: HashMap<Method, Arc<dyn Handler>>,
     _all: Option<Arc<dyn Handler>>,
+    excluded: Vec<Method>,
+    limit: Option<ConcurrencyLimit>,
+    accepts: Vec<String>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    pattern: Arc<str>,
+    mounted: bool,
+}
+
+/// The standard HTTP methods, in the order [Route::allowed_methods] lists them.
+const STANDARD_METHODS: [Method; 9] = [
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+    Method::OPTIONS,
+    Method::CONNECT,
+    Method::TRACE,
+];
+
+/// Caps the number of concurrent executions of a route's handlers.
+struct ConcurrencyLimit {
+    semaphore: Semaphore,
+    timeout: Option<Duration>,
 }
 
 impl Route {
+    /// Limits the number of requests to this route that may execute at once.
+    /// Requests beyond the limit fail fast with a `503 Service Unavailable`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn slow(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.at("/slow").concurrency_limit(10).get(slow);
+    /// ```
+    pub fn concurrency_limit(&mut self, limit: usize) -> &mut Self {
+        self.limit = Some(ConcurrencyLimit {
+            semaphore: Semaphore::new(limit),
+            timeout: None,
+        });
+        self
+    }
+
+    /// Limits the number of requests to this route that may execute at once.
+    /// Requests beyond the limit wait up to the timeout for a slot to free up
+    /// before failing with a `503 Service Unavailable`.
+    pub fn concurrency_limit_with_timeout(&mut self, limit: usize, timeout: Duration) -> &mut Self {
+        self.limit = Some(ConcurrencyLimit {
+            semaphore: Semaphore::new(limit),
+            timeout: Some(timeout),
+        });
+        self
+    }
+
+    /// Acquires a permit to execute the route under its concurrency limit,
+    /// returning `Err` if no permit could be acquired.
+    pub(crate) async fn acquire(&self) -> Result<Option<SemaphorePermit<'_>>, ()> {
+        let limit = match &self.limit {
+            Some(limit) => limit,
+            None => return Ok(None),
+        };
+        let permit = match limit.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, limit.semaphore.acquire())
+                .await
+                .map_err(|_| ())?
+                .ok(),
+            None => limit.semaphore.try_acquire().ok(),
+        };
+        permit.map(Some).ok_or(())
+    }
+
+    /// Restricts the request bodies the route accepts to the given content
+    /// type, such as `application/json` or `text/*`; call it again to accept
+    /// several. Requests with a body of any other type, or without a
+    /// `Content-Type`, are rejected with a `415 Unsupported Media Type` before
+    /// reaching the handler. Requests without a body are always accepted.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn create(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.at("/users").accepts("application/json").post(create);
+    /// ```
+    pub fn accepts(&mut self, content_type: &str) -> &mut Self {
+        self.accepts.push(content_type.trim().to_ascii_lowercase());
+        self
+    }
+
+    /// Determines if the route accepts the body of a request with the given
+    /// headers, see [Route::accepts].
+    pub(crate) fn accepts_body(&self, headers: &HeaderMap) -> bool {
+        if self.accepts.is_empty() || !has_body(headers) {
+            return true;
+        }
+        let essence = match media_type(headers) {
+            Some(essence) => essence,
+            None => return false,
+        };
+        self.accepts
+            .iter()
+            .any(|accepted| match accepted.strip_suffix("/*") {
+                Some(kind) => essence
+                    .split_once('/')
+                    .is_some_and(|(essence_kind, _)| essence_kind == kind),
+                None => *accepted == essence,
+            })
+    }
+
+    /// Gets the path pattern the route was registered under, such as
+    /// `/users/{id}`, including the path of any router it was mounted on.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Gets a shared reference to the pattern, for attaching to requests.
+    pub(crate) fn shared_pattern(&self) -> Arc<str> {
+        self.pattern.clone()
+    }
+
+    /// Mounts middleware on this route only, running after the middleware
+    /// mounted on the server, in the order mounted, before the handler.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use async_trait::async_trait;
+    /// use sidemount::*;
+    ///
+    /// struct Auth;
+    ///
+    /// #[async_trait]
+    /// impl Middleware for Auth {
+    ///     async fn handle(&self, req: Request, next: Next) -> Response {
+    ///         match req.headers().get("authorization") {
+    ///             Some(_) => next.run(req).await,
+    ///             None => hyper::StatusCode::UNAUTHORIZED.into(),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// async fn dashboard(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.at("/admin").with(Auth).get(dashboard);
+    /// ```
+    pub fn with(&mut self, mid: impl Middleware) -> &mut Self {
+        self.middleware.push(Arc::new(mid));
+        self
+    }
+
     /// Inserts a handler implementation on the given HTTP method.
     pub fn method(&mut self, method: Method, handler: impl Handler) {
         self.methods.insert(method, Arc::new(handler));
     }
-    /// Inserts a handler implementation on the all HTTP methods.
+    /// Inserts one handler implementation on several HTTP methods, sharing a
+    /// single instance of it between them.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn search(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.at("/search").methods_for(&[Method::GET, Method::POST], search);
+    /// assert!(router.find("/search", Method::POST).is_found());
+    /// ```
+    pub fn methods_for(&mut self, methods: &[Method], handler: impl Handler) {
+        let handler: Arc<dyn Handler> = Arc::new(handler);
+        for method in methods {
+            self.methods.insert(method.clone(), handler.clone());
+        }
+    }
+    /// Inserts a handler implementation on the all HTTP methods. Handlers
+    /// inserted on a specific method take precedence over this handler.
     pub fn all(&mut self, handler: impl Handler) {
         self._all = Some(Arc::new(handler));
+        self.excluded.clear();
+    }
+    /// Inserts a handler implementation on all HTTP methods except the given
+    /// ones, which are left to their own method handlers (if any).
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn resource(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.at("/foo").all_except(&[Method::OPTIONS], resource);
+    ///
+    /// assert!(router.find("/foo", Method::GET).is_found());
+    /// assert!(router.find("/foo", Method::OPTIONS).is_not_allowed());
+    /// ```
+    pub fn all_except(&mut self, methods: &[Method], handler: impl Handler) {
+        self._all = Some(Arc::new(handler));
+        self.excluded = methods.to_vec();
+    }
+    /// Determines if a handler is registered on the given HTTP method itself,
+    /// ignoring any handler registered on all methods.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn index(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// let route = router.at("/foo");
+    /// if !route.has_method(&Method::GET) {
+    ///     route.get(index);
+    /// }
+    /// assert!(route.has_method(&Method::GET));
+    /// ```
+    pub fn has_method(&self, method: &Method) -> bool {
+        self.methods.contains_key(method)
+    }
+    /// Gets the handler registered on the given HTTP method itself, ignoring
+    /// any handler registered on all methods.
+    pub fn handler(&self, method: &Method) -> Option<&Arc<dyn Handler>> {
+        self.methods.get(method)
+    }
+    /// Gets the methods the route handles, including through a handler
+    /// registered on all methods and `HEAD` through the `GET` handler. The
+    /// standard methods come first in a fixed order, followed by any extension
+    /// methods alphabetically.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn index(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// let route = router.at("/foo");
+    /// route.post(index);
+    /// route.get(index);
+    /// assert_eq!(route.allowed_methods(), vec![Method::GET, Method::HEAD, Method::POST]);
+    /// ```
+    pub fn allowed_methods(&self) -> Vec<Method> {
+        let mut allowed: Vec<Method> = STANDARD_METHODS
+            .into_iter()
+            .filter(|method| self.handler_for(method).is_some())
+            .collect();
+        let mut extensions: Vec<Method> = self
+            .methods
+            .keys()
+            .filter(|method| !STANDARD_METHODS.contains(method))
+            .cloned()
+            .collect();
+        extensions.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        allowed.extend(extensions);
+        allowed
+    }
+    /// Gets the handler for the method, preferring a handler registered on the
+    /// method itself over the handler registered on all methods. `HEAD`
+    /// requests without a handler of their own use the `GET` handler, whose
+    /// body the server drops.
+    fn handler_for(&self, method: &Method) -> Option<&Arc<dyn Handler>> {
+        let own = match *method {
+            Method::HEAD => self
+                .methods
+                .get(method)
+                .or_else(|| self.methods.get(&Method::GET)),
+            _ => self.methods.get(method),
+        };
+        own.or_else(|| {
+            self._all
+                .as_ref()
+                .filter(|_| !self.excluded.contains(method))
+        })
     }
     /// Inserts a handler implementation on the GET HTTP method.
     pub fn get(&mut self, handler: impl Handler) {
@@ -57,11 +395,135 @@ impl Route {
     pub fn delete(&mut self, handler: impl Handler) {
         self.method(Method::DELETE, handler);
     }
+    /// Inserts a handler implementation on the PATCH HTTP method.
+    pub fn patch(&mut self, handler: impl Handler) {
+        self.method(Method::PATCH, handler);
+    }
+    /// Inserts a handler implementation on the HEAD HTTP method.
+    pub fn head(&mut self, handler: impl Handler) {
+        self.method(Method::HEAD, handler);
+    }
+    /// Inserts a handler implementation on the OPTIONS HTTP method.
+    pub fn options(&mut self, handler: impl Handler) {
+        self.method(Method::OPTIONS, handler);
+    }
+    /// Inserts a handler implementation on the CONNECT HTTP method.
+    pub fn connect(&mut self, handler: impl Handler) {
+        self.method(Method::CONNECT, handler);
+    }
+    /// Inserts a handler implementation on the TRACE HTTP method.
+    pub fn trace(&mut self, handler: impl Handler) {
+        self.method(Method::TRACE, handler);
+    }
+}
+
+/// Determines if the request headers announce a body.
+fn has_body(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::TRANSFER_ENCODING)
+        || headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .is_some_and(|len| len.trim() != "0")
 }
 
 /// Represents a router that can build and handle [Route] handler implementations.
+///
+/// ## Thread safety
+/// A router is `Send + Sync`: once built it is only read, so the server shares
+/// a single `Arc<Router>` across every connection task without locking. Every
+/// handler, middleware and per-route setting it stores must therefore be
+/// `Send + Sync` too, and any state that changes while serving (such as a
+/// route's concurrency limit) must synchronize internally.
 pub struct Router {
     route: Node<Route>,
+    #[cfg(feature = "regex")]
+    regex_routes: Vec<RegexRoute>,
+    trailing_slash: TrailingSlash,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+/// How a router treats a trailing slash on a path that only matches without
+/// it, such as `/foo/` for a route at `/foo`, see [Router::trailing_slash].
+///
+/// The root path `/` is never affected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// `/foo/` and `/foo` are distinct paths, except at the root of a mounted
+    /// router.
+    #[default]
+    Strict,
+    /// A single trailing slash is dropped before matching.
+    Ignore,
+    /// The server answers with a `301 Moved Permanently` to the path without
+    /// the trailing slash, keeping the query string.
+    RedirectToNoSlash,
+}
+
+/// A route matching the whole path with a regular expression, see
+/// [Router::at_regex].
+#[cfg(feature = "regex")]
+struct RegexRoute {
+    /// The path the router was mounted on, empty when not mounted.
+    prefix: String,
+    regex: Regex,
+    route: Route,
+}
+
+#[cfg(feature = "regex")]
+impl RegexRoute {
+    /// Gets the part of the path the regex is matched against, after the path
+    /// the router was mounted on.
+    fn rest<'p>(&self, path: &'p str) -> Option<&'p str> {
+        match path.strip_prefix(self.prefix.as_str())? {
+            "" => Some("/"),
+            rest if self.prefix.is_empty() || rest.starts_with('/') => Some(rest),
+            _ => None,
+        }
+    }
+
+    /// Renders the pattern of a mounted route as the prefix followed by the
+    /// regex without its `^` and `$` anchors, which only apply to the rest of
+    /// the path.
+    fn mounted_pattern(&self) -> String {
+        let source = self.regex.as_str();
+        let source = source.strip_prefix('^').unwrap_or(source);
+        let source = match source.strip_suffix('$') {
+            Some(stripped) if !stripped.ends_with('\\') => stripped,
+            _ => source,
+        };
+        format!("{}{}", self.prefix, source)
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        self.rest(path)
+            .is_some_and(|rest| self.regex.is_match(rest))
+    }
+
+    /// Captures the named groups of the regex into params, percent-decoded
+    /// like the params of pattern routes.
+    fn captures(&self, path: &str) -> Option<Params> {
+        let captures = self.regex.captures(self.rest(path)?)?;
+        let params = self
+            .regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| {
+                Some((
+                    String::from(name),
+                    crate::node::decode(captures.name(name)?.as_str()).into_owned(),
+                ))
+            })
+            .collect();
+        Some(params)
+    }
+}
+
+// Fails to compile if a field makes routers unsafe to share across tasks.
+fn _assert_send_sync()
+where
+    Router: Send + Sync,
+    Route: Send + Sync,
+{
 }
 
 impl<F> From<F> for Router
@@ -73,14 +535,103 @@ where
     }
 }
 
+impl Default for Router {
+    fn default() -> Self {
+        Router::new()
+    }
+}
+
 impl Router {
     /// Creates a new router with the default route and middleware
     pub fn new() -> Self {
         Router {
             route: Node::default(),
+            #[cfg(feature = "regex")]
+            regex_routes: Vec::new(),
+            trailing_slash: TrailingSlash::Strict,
+            middleware: Vec::new(),
         }
     }
 
+    /// Mounts middleware on every route of this router, running after the
+    /// middleware of the server and of any router this one is mounted into,
+    /// and before the middleware of the route itself.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use async_trait::async_trait;
+    /// use sidemount::*;
+    ///
+    /// struct Auth;
+    ///
+    /// #[async_trait]
+    /// impl Middleware for Auth {
+    ///     async fn handle(&self, req: Request, next: Next) -> Response {
+    ///         match req.headers().get("authorization") {
+    ///             Some(_) => next.run(req).await,
+    ///             None => hyper::StatusCode::UNAUTHORIZED.into(),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// async fn settings(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut admin = Router::new();
+    /// admin.mount(Auth);
+    /// admin.at("/settings").get(settings);
+    ///
+    /// let mut router = Router::new();
+    /// router.route("/admin", admin);
+    /// ```
+    pub fn mount(&mut self, mid: impl Middleware) {
+        self.middleware.push(Arc::new(mid));
+    }
+
+    /// Gets the middleware chain of a route of this router: the given chain of
+    /// the server, then the middleware of the router and of the route. The
+    /// given chain is shared as is when there are none.
+    pub(crate) fn chain(
+        &self,
+        route: &Route,
+        middleware: &Arc<Vec<Arc<dyn Middleware>>>,
+    ) -> Arc<Vec<Arc<dyn Middleware>>> {
+        if self.middleware.is_empty() && route.middleware.is_empty() {
+            return middleware.clone();
+        }
+        Arc::new(
+            middleware
+                .iter()
+                .chain(&self.middleware)
+                .chain(&route.middleware)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Sets how a trailing slash is treated on paths that only match without
+    /// it, which are distinct by default.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn test(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.trailing_slash(TrailingSlash::Ignore).at("/foo").get(test);
+    ///
+    /// assert!(router.find("/foo/", Method::GET).is_found());
+    /// ```
+    pub fn trailing_slash(&mut self, policy: TrailingSlash) -> &mut Self {
+        self.trailing_slash = policy;
+        self
+    }
+
     /// Creates a new node route or returns a mutable reference to an existing one.
     ///
     /// ## Examples
@@ -88,63 +639,464 @@ impl Router {
     /// ```rust
     /// use sidemount::*;
     ///
-    /// fn test() {}
+    /// async fn test(req: Request) -> Response {
+    ///     Response::default()
+    /// }
     ///
     /// let mut router = Router::new();
     /// router.at("/foo").get(test);
     /// ```
     pub fn at(&mut self, path: &str) -> &mut Route {
-        if let None = self.route.get_mut(path) {
-            let node = Route::default();
+        if self.route.get_mut(path).is_none() {
+            let node = Route {
+                pattern: normalize(path).into(),
+                ..Route::default()
+            };
             self.route.insert(path, node);
         }
 
         self.route.get_mut(path).unwrap()
     }
 
+    /// Configures every method of a resource in one call, which is sugar over
+    /// [Router::at] that keeps the handlers of a REST resource together.
+    ///
+    /// ## Examples
+    ///
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn list(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    /// async fn create(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.resource("/users", |r| {
+    ///     r.get(list);
+    ///     r.post(create);
+    /// });
+    /// ```
+    pub fn resource(&mut self, path: &str, configure: impl FnOnce(&mut Route)) -> &mut Self {
+        configure(self.at(path));
+        self
+    }
+
     /// Inserts a route handler for the given path and HTTP method
     ///
     /// ## Examples
     /// ```rust
     /// use sidemount::*;
     ///
-    /// fn test() {}
-    /// fn index() {}
+    /// async fn test(req: Request) -> i32 {
+    ///     42
+    /// }
+    /// async fn index(n: i32) -> Response {
+    ///     Response::default()
+    /// }
     ///
     /// let mut router = Router::new();
     /// router.insert(Method::GET, "/foo/bar", (test, index));
     /// ```
     pub fn insert(&mut self, method: Method, path: &str, handler: impl Handler) {
-        if let Some(node) = self.route.get_mut(path) {
-            node.method(method, handler);
-        } else {
-            let mut node = Route::default();
-            node.method(method, handler);
-            self.route.insert(path, node);
+        self.at(path).method(method, handler);
+    }
+
+    /// Inserts a route handler for the given path on several HTTP methods, see
+    /// [Route::methods_for].
+    pub fn insert_many(&mut self, methods: &[Method], path: &str, handler: impl Handler) {
+        self.at(path).methods_for(methods, handler);
+    }
+
+    /// Registers one shared handler on the method for several paths, such as
+    /// `/` and `/index.html`, without constructing the handler again for each.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use sidemount::*;
+    ///
+    /// async fn index(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.alias(&["/", "/index.html"], Method::GET, Arc::new(index));
+    /// assert!(router.find("/index.html", Method::GET).is_found());
+    /// ```
+    pub fn alias(&mut self, paths: &[&str], method: Method, handler: Arc<dyn Handler>) {
+        for path in paths {
+            self.at(path)
+                .methods
+                .insert(method.clone(), handler.clone());
         }
     }
 
+    /// Creates a route matched by a regular expression over the whole path,
+    /// or returns the existing route for the same expression. Named capture
+    /// groups, such as `(?P<year>\d{4})`, populate the params.
+    ///
+    /// Regex routes are only tried when no route of the tree matches, in the
+    /// order they were added, before any fallback. Each is tried in turn, so
+    /// lookups are linear in the number of regex routes: use them sparingly,
+    /// for paths that do not fit `{name}` segments.
+    ///
+    /// Once the router is mounted with [Router::route], the pattern of the
+    /// route is the mount path followed by the expression without its `^` and
+    /// `$` anchors, e.g. `/blog/(?P<slug>[a-z-]+)`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use regex::Regex;
+    /// use sidemount::*;
+    ///
+    /// async fn archive(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router
+    ///     .at_regex(Regex::new(r"^/(?P<year>\d{4})/(?P<month>\d{2})(/.*)?$").unwrap())
+    ///     .get(archive);
+    ///
+    /// let found = router.lookup("/2024/05/some-post", Method::GET).unwrap();
+    /// assert_eq!(found.param("year").as_deref(), Some("2024"));
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn at_regex(&mut self, regex: Regex) -> &mut Route {
+        let existing = self
+            .regex_routes
+            .iter()
+            .position(|entry| entry.prefix.is_empty() && entry.regex.as_str() == regex.as_str());
+        let index = match existing {
+            Some(index) => index,
+            None => {
+                let route = Route {
+                    pattern: regex.as_str().into(),
+                    ..Route::default()
+                };
+                self.regex_routes.push(RegexRoute {
+                    prefix: String::new(),
+                    regex,
+                    route,
+                });
+                self.regex_routes.len() - 1
+            }
+        };
+        &mut self.regex_routes[index].route
+    }
+
+    /// Finds the first regex route matching the path.
+    #[cfg(feature = "regex")]
+    fn find_regex(&self, path: &str) -> Option<&RegexRoute> {
+        self.regex_routes.iter().find(|entry| entry.is_match(path))
+    }
+
     /// Routes a path on the router to an existing router implementation.
     ///
+    /// The mount path is matched with or without a trailing slash, so with a
+    /// router mounted on `/api`, both `/api` and `/api/` reach its `/` route
+    /// and `/api/users` reaches its `/users` route.
+    ///
     /// ## Examples
     /// ```rust
     /// use sidemount::*;
     ///
-    /// fn security() {}
-    /// fn settings() {}
-    /// fn authenticated() {}
+    /// async fn security(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    /// async fn settings(req: Request) -> Response {
+    ///     Response::default()
+    /// }
     ///
     /// let mut router = Router::new();
     ///
     /// let mut manager = Router::new();
-    /// manager.mount(authenticated);
     /// manager.at("/settings").get(settings);
     /// manager.at("/security").get(security);
     ///
     /// router.route("/admin", manager);
     /// ```
+    ///
+    /// Middleware mounted on the router with [Router::mount] keeps applying to
+    /// its routes, after the middleware of this router.
     pub fn route(&mut self, path: &str, router: impl Into<Router>) {
-        self.route.insert_node(path, router.into().route);
+        let path = normalize(path);
+        let router = router.into();
+        #[cfg(feature = "regex")]
+        {
+            let prefix = if path == "/" { "" } else { path.as_str() };
+            for mut entry in router.regex_routes {
+                entry.prefix = format!("{}{}", prefix, entry.prefix);
+                entry.route.pattern = entry.mounted_pattern().into();
+                entry
+                    .route
+                    .middleware
+                    .splice(0..0, router.middleware.iter().cloned());
+                self.regex_routes.push(entry);
+            }
+        }
+        let mut route = router.route;
+        prefix_patterns(&mut route, &path);
+        scope_middleware(&mut route, &router.middleware);
+        if let Some(root) = &mut route.handler {
+            root.mounted = true;
+        }
+        self.route.insert_node(&path, route);
+    }
+
+    /// Merges the routes of another router into this one at the root, for
+    /// splitting route definitions across modules. Middleware mounted on the
+    /// other router with [Router::mount] keeps applying to its routes only,
+    /// and its trailing slash policy is ignored in favor of this router's.
+    ///
+    /// A path may be registered in only one of the routers: routes sharing a
+    /// path are kept separate as they may carry their own settings, such as a
+    /// concurrency limit, so the merge panics rather than silently picking one.
+    /// The same goes for fallbacks on the same path.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn users(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    /// async fn orders(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.at("/users").get(users);
+    ///
+    /// let mut billing = Router::new();
+    /// billing.at("/orders").get(orders);
+    ///
+    /// router.merge(billing);
+    /// assert!(router.find("/orders", Method::GET).is_found());
+    /// ```
+    ///
+    /// ## Panics
+    /// Panics if both routers handle a method on the same path, including
+    /// paths differing only in their param names.
+    pub fn merge(&mut self, other: Router) {
+        #[cfg(feature = "regex")]
+        for mut entry in other.regex_routes {
+            entry
+                .route
+                .middleware
+                .splice(0..0, other.middleware.iter().cloned());
+            self.regex_routes.push(entry);
+        }
+        let mut route = other.route;
+        if let Some(path) = merge_conflict(&self.route, &route) {
+            panic!("Conflicting routes for {} when merging", path);
+        }
+        scope_middleware(&mut route, &other.middleware);
+        graft(&mut self.route, route);
+    }
+
+    /// Resolves the path to look up in the tree: a trailing slash after a
+    /// mounted router, such as `/api/`, is dropped so it reaches the root of
+    /// that router like `/api` does, as is any trailing slash when ignored
+    /// (see [TrailingSlash::Ignore]).
+    fn resolve<'p>(&self, path: &'p str) -> &'p str {
+        match path.strip_suffix('/') {
+            Some(trimmed) if !trimmed.is_empty() && self.route.get(path).is_none() => {
+                let ignored = self.trailing_slash == TrailingSlash::Ignore;
+                if ignored || self.route.get(trimmed).is_some_and(|route| route.mounted) {
+                    trimmed
+                } else {
+                    path
+                }
+            }
+            _ => path,
+        }
+    }
+
+    /// Gets the path without its trailing slash to redirect to, when
+    /// redirecting (see [TrailingSlash::RedirectToNoSlash]) and only that path
+    /// matches a route. Paths starting with `//` or `/\` are never
+    /// redirected, since browsers would read the location as another host.
+    pub(crate) fn redirect_path<'p>(&self, path: &'p str) -> Option<&'p str> {
+        if self.trailing_slash != TrailingSlash::RedirectToNoSlash
+            || path.starts_with("//")
+            || path.starts_with("/\\")
+        {
+            return None;
+        }
+        let trimmed = path
+            .strip_suffix('/')
+            .filter(|trimmed| !trimmed.is_empty())?;
+        if self.route.get(path).is_some() || self.route.get(trimmed).is_none() {
+            return None;
+        }
+        Some(trimmed)
+    }
+    /// Sets a fallback handler for any unmatched path under the given path.
+    /// The fallback nearest to the requested path is used, so a subtree such
+    /// as `/api` can respond differently than the rest of the site.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn not_found(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    /// async fn api_not_found(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.fallback("/", not_found);
+    /// router.fallback("/api", api_not_found);
+    ///
+    /// assert!(router.find("/api/missing", Method::GET).is_found());
+    /// assert!(router.find("/missing", Method::GET).is_found());
+    /// ```
+    pub fn fallback(&mut self, path: &str, handler: impl Handler) {
+        let mut route = Route {
+            pattern: normalize(path).into(),
+            ..Route::default()
+        };
+        route.all(handler);
+        self.route.insert_fallback(path, route);
+    }
+
+    /// Looks up the route for the path and method like [Router::find], but
+    /// params are not collected into a map until read: each call to
+    /// [RouteMatch::param] rescans the matched path for the one param. Returns
+    /// `None` both when no route matches and when the matched route does not
+    /// handle the method.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn user(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.at("/users/{id}").get(user);
+    ///
+    /// let found = router.lookup("/users/42", Method::GET).unwrap();
+    /// assert_eq!(found.param("id").as_deref(), Some("42"));
+    /// ```
+    pub fn lookup<'a>(&'a self, path: &'a str, method: Method) -> Option<RouteMatch<'a>> {
+        let path = self.resolve(path);
+        #[allow(unused_mut)]
+        let mut route = self.route.get(path);
+        #[cfg(feature = "regex")]
+        let regex = match route {
+            Some(_) => None,
+            None => self.find_regex(path),
+        };
+        #[cfg(feature = "regex")]
+        if let Some(entry) = regex {
+            route = Some(&entry.route);
+        }
+        let route = match route {
+            Some(route) => route,
+            None => self.route.get_fallback_ref(path)?,
+        };
+        Some(RouteMatch {
+            root: &self.route,
+            path,
+            route,
+            handler: route.handler_for(&method)?,
+            #[cfg(feature = "regex")]
+            regex,
+        })
+    }
+
+    /// Explains how the path and method are routed, including the nodes tried
+    /// at each segment and which static, pattern, wildcard or catch-all
+    /// branches were taken. This walks the tree separately from [Router::find]
+    /// and is meant for debugging a request that reaches an unexpected handler.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn index(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.at("/users/{id}").get(index);
+    ///
+    /// let explanation = router.explain("/users/42", Method::GET);
+    /// assert_eq!(explanation.template.as_deref(), Some("/users/{id}"));
+    /// assert!(explanation.result.is_found());
+    /// ```
+    pub fn explain(&self, path: &str, method: Method) -> MatchExplanation {
+        let path = self.resolve(path);
+        let steps = self.route.trace(path);
+        let template = self
+            .route
+            .get(path)
+            .map(|route| String::from(route.pattern()));
+        #[cfg(feature = "regex")]
+        let template = template.or_else(|| {
+            self.find_regex(path)
+                .map(|entry| String::from(entry.route.pattern()))
+        });
+        let result = match self.find(path, method) {
+            RouteResult::Found(_) => RouteResult::Found(()),
+            RouteResult::NotFound => RouteResult::NotFound,
+            RouteResult::MethodNotAllowed(allowed) => RouteResult::MethodNotAllowed(allowed),
+        };
+        MatchExplanation {
+            fallback: template.is_none() && result.is_found(),
+            steps,
+            template,
+            result,
+        }
+    }
+
+    /// Lists every registered route with the methods it handles, see
+    /// [Route::allowed_methods], sorted by path. Paths are rebuilt from the
+    /// keys of the tree, so params, patterns and catch-alls appear as
+    /// registered, such as `/files/{*path}`, including the path of any router
+    /// mounted with [Router::route]. Fallbacks are not listed.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::*;
+    ///
+    /// async fn index(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut router = Router::new();
+    /// router.at("/users/{id}").get(index);
+    ///
+    /// assert_eq!(
+    ///     router.routes(),
+    ///     vec![(String::from("/users/{id}"), vec![Method::GET, Method::HEAD])]
+    /// );
+    /// ```
+    pub fn routes(&self) -> Vec<(String, Vec<Method>)> {
+        let mut routes = Vec::new();
+        collect_routes(&self.route, "", &mut routes);
+        #[cfg(feature = "regex")]
+        routes.extend(
+            self.regex_routes
+                .iter()
+                .map(|entry| {
+                    (
+                        String::from(entry.route.pattern()),
+                        entry.route.allowed_methods(),
+                    )
+                })
+                .filter(|(_, methods)| !methods.is_empty()),
+        );
+        routes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        routes
     }
 
     /// Finds a route result along the given path and method.
@@ -153,8 +1105,12 @@ impl Router {
     /// ```rust
     /// use sidemount::*;
     ///
-    /// fn index() {}
-    /// fn foo() {}
+    /// async fn index(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    /// async fn foo(req: Request) -> Response {
+    ///     Response::default()
+    /// }
     ///
     /// let mut router = Router::new();
     /// router.at("/foo/bar").get(index);
@@ -169,15 +1125,28 @@ impl Router {
         &self,
         path: &str,
         method: Method,
-    ) -> RouteResult<(Arc<dyn Handler>, HashMap<String, String>)> {
-        let mut params = HashMap::new();
-        if let Some(node) = self.route.get_params(path, &mut params) {
-            if let Some(handler) = &node._all {
-                RouteResult::Found((handler.clone(), params))
-            } else if let Some(handler) = node.methods.get(&method) {
-                RouteResult::Found((handler.clone(), params))
-            } else {
-                RouteResult::MethodNotAllowed
+    ) -> RouteResult<(&Route, Arc<dyn Handler>, Params)> {
+        let path = self.resolve(path);
+        if let Some((node, params)) = self.route.find(path) {
+            return match node.handler_for(&method) {
+                Some(handler) => RouteResult::Found((node, handler.clone(), params)),
+                None => RouteResult::MethodNotAllowed(node.allowed_methods()),
+            };
+        }
+        #[cfg(feature = "regex")]
+        if let Some(entry) = self.find_regex(path) {
+            return match entry.route.handler_for(&method) {
+                Some(handler) => {
+                    let params = entry.captures(path).unwrap_or_default();
+                    RouteResult::Found((&entry.route, handler.clone(), params))
+                }
+                None => RouteResult::MethodNotAllowed(entry.route.allowed_methods()),
+            };
+        }
+        if let Some((node, params)) = self.route.find_fallback(path) {
+            match node.handler_for(&method) {
+                Some(handler) => RouteResult::Found((node, handler.clone(), params)),
+                None => RouteResult::NotFound,
             }
         } else {
             RouteResult::NotFound
@@ -185,26 +1154,240 @@ impl Router {
     }
 }
 
+/// A move-based builder that collects routes and builds a [Router] in one
+/// shot, detecting routes registered twice.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::*;
+///
+/// async fn users(req: Request) -> Response {
+///     Response::default()
+/// }
+/// async fn create_user(req: Request) -> Response {
+///     Response::default()
+/// }
+///
+/// let router = RouteTable::new()
+///     .get("/users", users)
+///     .post("/users", create_user)
+///     .build()
+///     .unwrap();
+/// assert!(router.find("/users", Method::POST).is_found());
+/// ```
+#[derive(Default)]
+pub struct RouteTable {
+    routes: Vec<(Method, String, Arc<dyn Handler>)>,
+}
+
+impl RouteTable {
+    /// Creates a new empty route table.
+    pub fn new() -> Self {
+        RouteTable::default()
+    }
+
+    /// Adds a handler for the given HTTP method and path.
+    pub fn route(mut self, method: Method, path: &str, handler: impl Handler) -> Self {
+        self.routes
+            .push((method, String::from(path), Arc::new(handler)));
+        self
+    }
+
+    /// Adds a handler for the GET HTTP method on the path.
+    pub fn get(self, path: &str, handler: impl Handler) -> Self {
+        self.route(Method::GET, path, handler)
+    }
+
+    /// Adds a handler for the POST HTTP method on the path.
+    pub fn post(self, path: &str, handler: impl Handler) -> Self {
+        self.route(Method::POST, path, handler)
+    }
+
+    /// Adds a handler for the PUT HTTP method on the path.
+    pub fn put(self, path: &str, handler: impl Handler) -> Self {
+        self.route(Method::PUT, path, handler)
+    }
+
+    /// Adds a handler for the DELETE HTTP method on the path.
+    pub fn delete(self, path: &str, handler: impl Handler) -> Self {
+        self.route(Method::DELETE, path, handler)
+    }
+
+    /// Builds the router, returning an error if a method is registered twice
+    /// on the same path. Paths differing only in their param names, such as
+    /// `/users/{id}` and `/users/{name}`, are the same route and must use the
+    /// same names for every method, since only one of them could ever match.
+    ///
+    /// Static segments take precedence over params, so `/users/me` and
+    /// `/users/{id}` are separate routes whatever order they are added in.
+    pub fn build(self) -> crate::Result<Router> {
+        let mut seen: HashMap<String, Vec<(Method, String)>> = HashMap::new();
+        let mut router = Router::new();
+        for (method, path, handler) in self.routes {
+            let registered = seen.entry(route_shape(&path)).or_default();
+            let conflict = registered.iter().find(|(existing, other)| {
+                *existing == method || normalize(other) != normalize(&path)
+            });
+            if let Some((existing, other)) = conflict {
+                return Err(format!(
+                    "Conflicting routes for {} {} and {} {}",
+                    existing, other, method, path
+                )
+                .into());
+            }
+            registered.push((method.clone(), path.clone()));
+            router.at(&path).methods.insert(method, handler);
+        }
+        Ok(router)
+    }
+}
+
+/// Normalizes a route path to start with a single `/` and have no empty
+/// segments.
+fn normalize(path: &str) -> String {
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    format!("/{}", segments.join("/"))
+}
+
+/// Prefixes the patterns of every route in the tree with the path it is being
+/// mounted on.
+fn prefix_patterns(node: &mut Node<Route>, prefix: &str) {
+    for route in node.handler.iter_mut().chain(node.fallback.iter_mut()) {
+        route.pattern = normalize(&format!("{}/{}", prefix, route.pattern)).into();
+    }
+    for child in &mut node.nodes {
+        prefix_patterns(child, prefix);
+    }
+}
+
+/// Puts the middleware of a mounted router before that of every route in its
+/// tree.
+fn scope_middleware(node: &mut Node<Route>, middleware: &[Arc<dyn Middleware>]) {
+    if middleware.is_empty() {
+        return;
+    }
+    for route in node.handler.iter_mut().chain(node.fallback.iter_mut()) {
+        route.middleware.splice(0..0, middleware.iter().cloned());
+    }
+    for child in &mut node.nodes {
+        scope_middleware(child, middleware);
+    }
+}
+
+/// Finds a route of the other tree handling methods on a path that the tree
+/// already handles, comparing paths with their param names erased, since only
+/// one of `/users/{id}` and `/users/{user}` could ever match.
+fn merge_conflict(node: &Node<Route>, other: &Node<Route>) -> Option<String> {
+    let mut existing = Vec::new();
+    collect_routes(node, "", &mut existing);
+    let shapes = existing
+        .iter()
+        .map(|(path, _)| route_shape(path))
+        .collect::<Vec<_>>();
+    let mut routes = Vec::new();
+    collect_routes(other, "", &mut routes);
+    routes
+        .into_iter()
+        .map(|(path, _)| path)
+        .find(|path| shapes.contains(&route_shape(path)))
+}
+
+/// Grafts the nodes of another tree onto the node, merging nodes with the same
+/// key like [Node::insert] would, see [Router::merge].
+fn graft(node: &mut Node<Route>, other: Node<Route>) {
+    node.handler = merge_route(node.handler.take(), other.handler);
+    node.fallback = merge_route(node.fallback.take(), other.fallback);
+    for child in other.nodes {
+        match node.nodes.iter_mut().find(|m| m.key == child.key) {
+            Some(existing) => graft(existing, child),
+            None => {
+                node.push_child(child);
+            }
+        }
+    }
+}
+
+/// Picks the route handling methods out of two routes at the same path,
+/// panicking if both do.
+fn merge_route(existing: Option<Route>, other: Option<Route>) -> Option<Route> {
+    let handles = |route: &Route| !route.allowed_methods().is_empty();
+    match (existing, other) {
+        (Some(existing), Some(other)) if handles(&existing) && handles(&other) => {
+            panic!("Conflicting routes for {} when merging", other.pattern())
+        }
+        (Some(existing), Some(other)) if !handles(&other) => Some(existing),
+        (existing, other) => other.or(existing),
+    }
+}
+
+/// Collects the routes of the tree handling at least one method, rebuilding
+/// the path of each node from the keys along the way.
+fn collect_routes(node: &Node<Route>, prefix: &str, routes: &mut Vec<(String, Vec<Method>)>) {
+    let path = match node.key.as_str() {
+        "" | "/" => String::from(prefix),
+        key => format!("{}/{}", prefix, key),
+    };
+    if let Some(route) = &node.handler {
+        let methods = route.allowed_methods();
+        if !methods.is_empty() {
+            let path = if path.is_empty() { "/" } else { path.as_str() };
+            routes.push((String::from(path), methods));
+        }
+    }
+    for child in &node.nodes {
+        collect_routes(child, &path, routes);
+    }
+}
+
+/// Gets the shape of a route path, with empty segments removed and param
+/// names erased so routes that match the same paths compare equal.
+fn route_shape(path: &str) -> String {
+    let mut shape = String::new();
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        shape.push('/');
+        if segment.starts_with('*') || segment.starts_with("{*") {
+            shape.push_str("{*}");
+            continue;
+        }
+        let mut rest = segment;
+        while let Some(start) = rest.find('{') {
+            match rest[start..].find('}') {
+                Some(end) => {
+                    shape.push_str(&rest[..start]);
+                    shape.push_str("{}");
+                    rest = &rest[start + end + 1..];
+                }
+                None => break,
+            }
+        }
+        shape.push_str(rest);
+    }
+    shape
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Request, Response};
+    use crate::{Branch, Request, Response};
 
     fn test() -> Router {
-        async fn test(req: Request) -> Response {
-            Response::default()
-        }
-
         let mut router = Router::new();
-        router.at("/foo/bar").get(test);
+        router.at("/foo/bar").get(index);
         router
     }
 
-    async fn tester(req: Request) -> i32 {
+    async fn index(_req: Request) -> Response {
+        Response::default()
+    }
+
+    async fn tester(_req: Request) -> i32 {
         3
     }
 
-    async fn tester2(a: i32) -> Response {
+    async fn tester2(_a: i32) -> Response {
         Response::default()
     }
 
@@ -212,7 +1395,7 @@ mod tests {
         a.to_string()
     }
 
-    async fn test4(s: String) -> Response {
+    async fn test4(_s: String) -> Response {
         Response::default()
     }
 
@@ -220,15 +1403,15 @@ mod tests {
     fn test_router() {
         let mut router = Router::new();
         router.route("/", test);
-        router.at("/foo/bar").get(test);
+        router.at("/foo/bar").get(index);
 
         router.at("/foo/bar/baz").get((tester, tester2));
         router.at("/bah").get((tester, tester2));
         router.at("/boo").get((tester, test3, test4));
 
         let mut sub_router = Router::new();
-        sub_router.at("/bleh").get(test);
-        sub_router.at("/foo/bar").post(test);
+        sub_router.at("/bleh").get(index);
+        sub_router.at("/foo/bar").post(index);
         router.route("/hi", sub_router);
 
         assert!(router.find("/hi/bleh", Method::GET).is_found());
@@ -236,4 +1419,599 @@ mod tests {
         assert!(router.find("/foo/bar", Method::GET).is_found());
         assert!(router.find("/foo/bar/baz", Method::GET).is_found());
     }
+
+    #[test]
+    fn test_method_helpers() {
+        let mut router = Router::new();
+        router.at("/users/{id}").patch(index);
+        router.at("/ping").options(index);
+        router.at("/proxy").connect(index);
+        router.at("/echo").trace(index);
+
+        assert!(router.find("/users/7", Method::PATCH).is_found());
+        assert!(!router.find("/users/7", Method::PUT).is_found());
+        assert!(router.find("/ping", Method::OPTIONS).is_found());
+        assert!(router.find("/proxy", Method::CONNECT).is_found());
+        assert!(router.find("/echo", Method::TRACE).is_found());
+    }
+
+    #[test]
+    fn test_routes() {
+        let mut router = Router::new();
+        router.at("/").get(index);
+        router.at("/users/{id}").get(index);
+        router.at("/users/{id}").delete(index);
+        router.at("/files/{name}.{ext}").put(index);
+        router.at("/static/{*path}").get(index);
+        router.at("/hooks").all(index);
+        router.fallback("/", index);
+
+        let mut admin = Router::new();
+        admin.at("/settings").post(index);
+        router.route("/admin", admin);
+
+        let get = vec![Method::GET, Method::HEAD];
+        assert_eq!(
+            router.routes(),
+            vec![
+                (String::from("/"), get.clone()),
+                (String::from("/admin/settings"), vec![Method::POST]),
+                (String::from("/files/{name}.{ext}"), vec![Method::PUT]),
+                (String::from("/hooks"), STANDARD_METHODS.to_vec()),
+                (String::from("/static/{*path}"), get),
+                (
+                    String::from("/users/{id}"),
+                    vec![Method::GET, Method::HEAD, Method::DELETE]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut router = Router::new();
+        router.at("/users").get(index);
+        router.at("/users/{id}").get(index);
+
+        let mut billing = Router::new();
+        billing.at("/orders").post(index);
+        billing.at("/users/{user}/invoices").get(index);
+        billing.fallback("/billing", index);
+        router.merge(billing);
+
+        assert!(router.find("/users", Method::GET).is_found());
+        assert!(router.find("/users/7", Method::GET).is_found());
+        assert!(router.find("/orders", Method::POST).is_found());
+        assert!(router.find("/users/7/invoices", Method::GET).is_found());
+        assert!(router.find("/billing/missing", Method::GET).is_found());
+
+        let found = router.lookup("/users/7/invoices", Method::GET).unwrap();
+        assert_eq!(found.param("user").as_deref(), Some("7"));
+        assert_eq!(found.param("id").as_deref(), None);
+        match router.find("/users/7/invoices", Method::GET) {
+            RouteResult::Found((_, _, params)) => assert_eq!(params["user"], "7"),
+            _ => panic!("expected route to be found"),
+        }
+
+        let mut me = Router::new();
+        me.at("/users/me").get(index);
+        router.merge(me);
+        let found = router.lookup("/users/7", Method::GET).unwrap();
+        assert_eq!(found.param("id").as_deref(), Some("7"));
+        assert_eq!(found.route().pattern(), "/users/{id}");
+        let found = router.lookup("/users/me", Method::GET).unwrap();
+        assert_eq!(found.route().pattern(), "/users/me");
+    }
+
+    #[test]
+    #[should_panic(expected = "Conflicting routes for /users/{user}")]
+    fn test_merge_conflict() {
+        let mut router = Router::new();
+        router.at("/users/{id}").get(index);
+
+        let mut other = Router::new();
+        other.at("/users/{user}").delete(index);
+        router.merge(other);
+    }
+
+    #[test]
+    fn test_method_overrides_all() {
+        let mut router = Router::new();
+        router.at("/foo").all(index);
+        router.at("/foo").get((tester, tester2));
+        router
+            .at("/bar")
+            .all_except(&[Method::OPTIONS, Method::DELETE], index);
+        router.at("/bar").method(Method::OPTIONS, index);
+
+        for method in [Method::GET, Method::POST, Method::PUT, Method::DELETE] {
+            assert!(router.find("/foo", method).is_found());
+        }
+        assert!(router.find("/bar", Method::GET).is_found());
+        assert!(router.find("/bar", Method::PUT).is_found());
+        assert!(router.find("/bar", Method::OPTIONS).is_found());
+        assert!(router.find("/bar", Method::DELETE).is_not_allowed());
+
+        let all = router.route.get("/foo").unwrap()._all.clone().unwrap();
+        let get = router.route.get("/foo").unwrap().methods[&Method::GET].clone();
+        match router.find("/foo", Method::GET) {
+            RouteResult::Found((_, handler, _)) => {
+                assert!(Arc::ptr_eq(&handler, &get));
+                assert!(!Arc::ptr_eq(&handler, &all));
+            }
+            _ => panic!("expected route to be found"),
+        }
+        match router.find("/foo", Method::POST) {
+            RouteResult::Found((_, handler, _)) => assert!(Arc::ptr_eq(&handler, &all)),
+            _ => panic!("expected route to be found"),
+        }
+    }
+
+    #[test]
+    fn test_route_inspection() {
+        let mut router = Router::new();
+        let route = router.at("/foo");
+        route.all(index);
+        assert!(!route.has_method(&Method::GET));
+        assert!(route.handler(&Method::GET).is_none());
+
+        route.get(index);
+        assert!(route.has_method(&Method::GET));
+        assert!(!route.has_method(&Method::POST));
+
+        let handler = route.handler(&Method::GET).unwrap().clone();
+        match router.find("/foo", Method::GET) {
+            RouteResult::Found((_, found, _)) => assert!(Arc::ptr_eq(&handler, &found)),
+            _ => panic!("expected route to be found"),
+        }
+    }
+
+    fn tagged(tag: &'static str) -> impl Handler {
+        move |_req: Request| async move {
+            let mut res = Response::default();
+            res.headers_mut().insert("x-handler", tag.parse().unwrap());
+            res
+        }
+    }
+
+    async fn handled_by(router: &Router, path: &str) -> Option<String> {
+        match router.find(path, Method::GET) {
+            RouteResult::Found((_, handler, params)) => {
+                let req = hyper::Request::get(path)
+                    .body(hyper::Body::empty())
+                    .unwrap();
+                let res = handler.call(Request::new(req, params)).await;
+                Some(res.headers()["x-handler"].to_str().unwrap().to_owned())
+            }
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mount_prefix_slashes() {
+        for mount in ["/api", "/api/"] {
+            let mut api = Router::new();
+            api.at("/").get(tagged("api"));
+            api.at("/users").get(tagged("users"));
+            let mut router = Router::new();
+            router.at("/").get(tagged("home"));
+            router.route(mount, api);
+
+            for path in ["/api", "/api/"] {
+                assert_eq!(handled_by(&router, path).await.as_deref(), Some("api"));
+                assert!(router.lookup(path, Method::GET).is_some());
+                assert_eq!(
+                    router.explain(path, Method::GET).template.as_deref(),
+                    Some("/api")
+                );
+            }
+            assert_eq!(
+                handled_by(&router, "/api/users").await.as_deref(),
+                Some("users")
+            );
+            assert_eq!(handled_by(&router, "/").await.as_deref(), Some("home"));
+            assert!(!router.find("/api/users/", Method::GET).is_found());
+            assert!(!router.find("/apix", Method::GET).is_found());
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    #[tokio::test]
+    async fn test_regex_routes() {
+        let mut api = Router::new();
+        api.at("/posts/latest").get(tagged("latest"));
+        api.at_regex(regex::Regex::new(r"^/posts/(?P<year>\d{4})-(?P<slug>[a-z-]+)$").unwrap())
+            .get(tagged("post"));
+        api.at_regex(regex::Regex::new(r"^/tags/(?P<tag>[^/]+)$").unwrap())
+            .get(tagged("tag"));
+        let mut router = Router::new();
+        router.fallback("/", tagged("fallback"));
+        router.route("/blog", api);
+
+        assert_eq!(
+            handled_by(&router, "/blog/posts/latest").await.as_deref(),
+            Some("latest")
+        );
+        match router.find("/blog/posts/2024-hello-world", Method::GET) {
+            RouteResult::Found((route, _, params)) => {
+                assert_eq!(
+                    route.pattern(),
+                    r"/blog/posts/(?P<year>\d{4})-(?P<slug>[a-z-]+)"
+                );
+                assert_eq!(params["year"], "2024");
+                assert_eq!(params["slug"], "hello-world");
+            }
+            _ => panic!("regex route not found"),
+        }
+        let patterns = router
+            .routes()
+            .into_iter()
+            .map(|(pattern, _)| pattern)
+            .collect::<Vec<_>>();
+        assert!(patterns.contains(&String::from(r"/blog/tags/(?P<tag>[^/]+)")));
+        let found = router
+            .lookup("/blog/posts/2024-hello-world", Method::GET)
+            .unwrap();
+        assert_eq!(found.param("slug").as_deref(), Some("hello-world"));
+        assert!(router
+            .find("/blog/posts/2024-hello-world", Method::POST)
+            .is_not_allowed());
+        match router.find("/blog/tags/rust%20lang", Method::GET) {
+            RouteResult::Found((_, _, params)) => assert_eq!(params["tag"], "rust lang"),
+            _ => panic!("regex route not found"),
+        }
+        assert_eq!(
+            handled_by(&router, "/posts/2024-hello-world")
+                .await
+                .as_deref(),
+            Some("fallback")
+        );
+
+        let mut site = Router::new();
+        site.route("/site", router);
+        assert!(site
+            .routes()
+            .iter()
+            .any(|(pattern, _)| pattern == r"/site/blog/tags/(?P<tag>[^/]+)"));
+        assert!(site.find("/site/blog/tags/rust", Method::GET).is_found());
+    }
+
+    #[test]
+    fn test_route_table() {
+        let router = RouteTable::new()
+            .get("/users", index)
+            .post("/users", index)
+            .get("/users/{id}", index)
+            .build()
+            .unwrap();
+        assert!(router.find("/users", Method::GET).is_found());
+        assert!(router.find("/users", Method::POST).is_found());
+        assert!(router.find("/users/1", Method::GET).is_found());
+        assert!(router.find("/users/1", Method::POST).is_not_allowed());
+
+        let conflict = RouteTable::new()
+            .get("/users/{id}", index)
+            .get("/users/{name}/", index)
+            .build();
+        let err = conflict.err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "Conflicting routes for GET /users/{id} and GET /users/{name}/"
+        );
+
+        let conflict = RouteTable::new()
+            .get("/users/{id}", index)
+            .delete("/users/{name}", index)
+            .build();
+        let err = conflict.err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "Conflicting routes for GET /users/{id} and DELETE /users/{name}"
+        );
+
+        assert_eq!(route_shape("/files/{name}.{ext}"), "/files/{}.{}");
+        assert_eq!(route_shape("/files/*path"), route_shape("/files/{*rest}"));
+        assert_ne!(route_shape("/files/{*path}"), route_shape("/files/{name}"));
+    }
+
+    #[tokio::test]
+    async fn test_route_table_static_beside_param() {
+        let router = RouteTable::new()
+            .get("/users/{id}", tagged("user"))
+            .get("/users/me", tagged("me"))
+            .get("/files/{*path}", tagged("files"))
+            .get("/files/{name}", tagged("file"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            handled_by(&router, "/users/42").await.as_deref(),
+            Some("user")
+        );
+        assert_eq!(
+            handled_by(&router, "/users/me").await.as_deref(),
+            Some("me")
+        );
+        assert_eq!(
+            handled_by(&router, "/files/a").await.as_deref(),
+            Some("file")
+        );
+        assert_eq!(
+            handled_by(&router, "/files/a/b").await.as_deref(),
+            Some("files")
+        );
+    }
+
+    #[test]
+    fn test_route_pattern() {
+        let mut api = Router::new();
+        api.at("/users/{id}").get(index);
+        api.fallback("/", index);
+
+        let mut router = Router::new();
+        router.at("/").get(index);
+        router.insert(Method::GET, "about", index);
+        router.route("/api", api);
+
+        let pattern = |path: &str| match router.find(path, Method::GET) {
+            RouteResult::Found((route, _, _)) => route.pattern().to_owned(),
+            _ => panic!("{} not found", path),
+        };
+        assert_eq!(pattern("/"), "/");
+        assert_eq!(pattern("/about"), "/about");
+        assert_eq!(pattern("/api/users/1"), "/api/users/{id}");
+        assert_eq!(pattern("/api/missing"), "/api");
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut router = Router::new();
+        router.at("/users/{id}/files/{name}.{ext}").get(index);
+        router.at("/users/{id}").post(index);
+        router.fallback("/docs/{version}", index);
+
+        let found = router
+            .lookup("/users/42/files/a.tar.gz", Method::GET)
+            .unwrap();
+        assert_eq!(found.param("id").as_deref(), Some("42"));
+        assert_eq!(found.param("name").as_deref(), Some("a.tar"));
+        assert_eq!(found.param("ext").as_deref(), Some("gz"));
+        assert_eq!(found.param("missing").as_deref(), None);
+        assert_eq!(found.route().pattern(), "/users/{id}/files/{name}.{ext}");
+
+        let found = router
+            .lookup("/users/ada%20lovelace/files/my%20notes.txt", Method::GET)
+            .unwrap();
+        assert_eq!(found.param("id").as_deref(), Some("ada lovelace"));
+        assert_eq!(found.param("name").as_deref(), Some("my notes"));
+        assert!(matches!(found.param("ext"), Some(Cow::Borrowed("txt"))));
+
+        assert!(router.lookup("/users/42", Method::POST).is_some());
+        assert!(router.lookup("/users/42", Method::GET).is_none());
+        assert!(router.lookup("/missing", Method::GET).is_none());
+
+        let found = router.lookup("/docs/v2/intro", Method::GET).unwrap();
+        assert_eq!(found.route().pattern(), "/docs/{version}");
+        assert_eq!(found.param("version").as_deref(), Some("v2"));
+
+        router.at("/static/{*file}").get(index);
+        let found = router.lookup("/static/css/app.css", Method::GET).unwrap();
+        assert_eq!(found.route().pattern(), "/static/{*file}");
+        assert_eq!(found.param("file").as_deref(), Some("css/app.css"));
+        let found = router
+            .lookup("/static/css/my%20app.css", Method::GET)
+            .unwrap();
+        assert_eq!(found.param("file").as_deref(), Some("css/my app.css"));
+        assert!(router.find("/static/css/app.css", Method::GET).is_found());
+    }
+
+    #[test]
+    fn test_resource() {
+        let mut router = Router::new();
+        router
+            .resource("/users", |r| {
+                r.get(index);
+                r.post(index);
+            })
+            .resource("/users/{id}", |r| {
+                r.put(index);
+                r.delete(index);
+            });
+
+        assert!(router.find("/users", Method::GET).is_found());
+        assert!(router.find("/users", Method::POST).is_found());
+        assert!(router.find("/users", Method::DELETE).is_not_allowed());
+        assert!(router.find("/users/1", Method::DELETE).is_found());
+        assert!(router.find("/users/1", Method::GET).is_not_allowed());
+    }
+
+    #[test]
+    fn test_trailing_slash() {
+        let mut router = Router::new();
+        router.at("/").get(index);
+        router.at("/foo").get(index);
+
+        assert!(!router.find("/foo/", Method::GET).is_found());
+        assert!(router.find("/", Method::GET).is_found());
+        assert_eq!(router.redirect_path("/foo/"), None);
+
+        router.trailing_slash(TrailingSlash::Ignore);
+        assert!(router.find("/foo/", Method::GET).is_found());
+        assert!(router.find("/foo", Method::GET).is_found());
+        assert!(!router.find("/foo//", Method::GET).is_found());
+        assert!(router.find("/", Method::GET).is_found());
+        assert!(!router.find("/missing/", Method::GET).is_found());
+
+        router.trailing_slash(TrailingSlash::RedirectToNoSlash);
+        assert!(!router.find("/foo/", Method::GET).is_found());
+        assert_eq!(router.redirect_path("/foo/"), Some("/foo"));
+        assert_eq!(router.redirect_path("/foo"), None);
+        assert_eq!(router.redirect_path("/"), None);
+        assert_eq!(router.redirect_path("/missing/"), None);
+
+        router.at("/{page}").get(index);
+        assert_eq!(router.redirect_path("/evil.com/"), Some("/evil.com"));
+        assert_eq!(router.redirect_path("//evil.com/"), None);
+        assert_eq!(router.redirect_path("/\\evil.com/"), None);
+    }
+
+    #[test]
+    fn test_alias() {
+        let handler: Arc<dyn Handler> = Arc::new(index);
+        let mut router = Router::new();
+        router.alias(&["/", "/index.html", "/home"], Method::GET, handler.clone());
+
+        for path in ["/", "/index.html", "/home"] {
+            match router.find(path, Method::GET) {
+                RouteResult::Found((_, found, _)) => assert!(Arc::ptr_eq(&found, &handler)),
+                _ => panic!("{} not found", path),
+            }
+        }
+        assert!(router.find("/home", Method::POST).is_not_allowed());
+    }
+
+    #[test]
+    fn test_insert_many() {
+        let mut router = Router::new();
+        router.insert_many(&[Method::GET, Method::POST], "/search", index);
+
+        let get = match router.find("/search", Method::GET) {
+            RouteResult::Found((_, handler, _)) => handler,
+            _ => panic!("GET /search not found"),
+        };
+        match router.find("/search", Method::POST) {
+            RouteResult::Found((_, handler, _)) => assert!(Arc::ptr_eq(&handler, &get)),
+            _ => panic!("POST /search not found"),
+        }
+        assert!(router.find("/search", Method::PUT).is_not_allowed());
+    }
+
+    #[test]
+    fn test_head_falls_back_to_get() {
+        let get: Arc<dyn Handler> = Arc::new(index);
+        let head: Arc<dyn Handler> = Arc::new(index);
+        let mut router = Router::new();
+        router.alias(&["/get", "/head"], Method::GET, get.clone());
+        router.alias(&["/head"], Method::HEAD, head.clone());
+        router.at("/post").post(index);
+
+        match router.find("/get", Method::HEAD) {
+            RouteResult::Found((_, found, _)) => assert!(Arc::ptr_eq(&found, &get)),
+            _ => panic!("HEAD /get not found"),
+        }
+        match router.find("/head", Method::HEAD) {
+            RouteResult::Found((_, found, _)) => assert!(Arc::ptr_eq(&found, &head)),
+            _ => panic!("HEAD /head not found"),
+        }
+        assert!(router.find("/post", Method::HEAD).is_not_allowed());
+    }
+
+    #[test]
+    fn test_allowed_methods() {
+        let mut router = Router::new();
+        router.at("/users").get(index);
+        router.at("/users").post(index);
+        router
+            .at("/users")
+            .method(Method::from_bytes(b"PURGE").unwrap(), index);
+        router
+            .at("/any")
+            .all_except(&[Method::DELETE, Method::TRACE], index);
+
+        match router.find("/users", Method::DELETE) {
+            RouteResult::MethodNotAllowed(allowed) => assert_eq!(
+                allowed,
+                vec![
+                    Method::GET,
+                    Method::HEAD,
+                    Method::POST,
+                    Method::from_bytes(b"PURGE").unwrap(),
+                ]
+            ),
+            _ => panic!("DELETE /users allowed"),
+        }
+        let any = router.lookup("/any", Method::GET).unwrap();
+        assert_eq!(
+            any.route().allowed_methods(),
+            vec![
+                Method::GET,
+                Method::HEAD,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::OPTIONS,
+                Method::CONNECT,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut router = Router::new();
+        router.at("/users/me").get(index);
+        router.at("/users/{id}").post(index);
+        router.fallback("/users", index);
+
+        let explanation = router.explain("/users/42", Method::POST);
+        assert_eq!(explanation.template.as_deref(), Some("/users/{id}"));
+        assert_eq!(explanation.steps[1].tried, vec!["me"]);
+        assert_eq!(explanation.result, RouteResult::Found(()));
+        assert!(!explanation.fallback);
+
+        let explanation = router.explain("/users/me", Method::POST);
+        assert_eq!(explanation.template.as_deref(), Some("/users/me"));
+        assert_eq!(
+            explanation.result,
+            RouteResult::MethodNotAllowed(vec![Method::GET, Method::HEAD])
+        );
+
+        let explanation = router.explain("/users/42/posts", Method::GET);
+        assert_eq!(explanation.template, None);
+        assert_eq!(explanation.steps[2].matched, None);
+        assert!(explanation.fallback);
+
+        router.at("/static/{*file}").get(index);
+        let explanation = router.explain("/static/css/site.css", Method::GET);
+        assert_eq!(explanation.template.as_deref(), Some("/static/{*file}"));
+        assert_eq!(
+            explanation.steps.last().unwrap().matched,
+            Some((String::from("{*file}"), Branch::CatchAll))
+        );
+        assert!(explanation.result.is_found());
+        assert!(!explanation.fallback);
+    }
+
+    #[tokio::test]
+    async fn test_nested_fallbacks() {
+        let mut router = Router::new();
+        router.at("/api/users").get(tagged("users"));
+        router.at("/about").get(tagged("about"));
+        router.fallback("/api", tagged("api"));
+
+        assert_eq!(handled_by(&router, "/missing").await, None);
+        assert_eq!(handled_by(&router, "/api/missing").await.unwrap(), "api");
+
+        router.fallback("/", tagged("site"));
+        router.fallback("/api/admin", tagged("admin"));
+
+        assert_eq!(handled_by(&router, "/about").await.unwrap(), "about");
+        assert_eq!(handled_by(&router, "/api/users").await.unwrap(), "users");
+        assert_eq!(handled_by(&router, "/missing").await.unwrap(), "site");
+        assert_eq!(handled_by(&router, "/api/missing").await.unwrap(), "api");
+        assert_eq!(handled_by(&router, "/api/admin/x").await.unwrap(), "admin");
+    }
+
+    #[tokio::test]
+    async fn test_mounted_router_fallback() {
+        let mut api = Router::new();
+        api.at("/users").get(tagged("users"));
+        api.fallback("/", tagged("api"));
+
+        let mut router = Router::new();
+        router.fallback("/", tagged("site"));
+        router.route("/api", api);
+
+        assert_eq!(handled_by(&router, "/api/users").await.unwrap(), "users");
+        assert_eq!(handled_by(&router, "/api/missing").await.unwrap(), "api");
+        assert_eq!(handled_by(&router, "/missing").await.unwrap(), "site");
+    }
 }
@@ -1,22 +1,418 @@
 use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::RefCell,
+    collections::HashMap,
     future::Future,
+    io,
+    net::SocketAddr,
+    panic::AssertUnwindSafe,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Once,
+    },
     task::{Context, Poll},
+    time::Duration,
 };
 
-use hyper::Body;
-use hyper::{server::conn::Http, service::Service};
-use tokio::net::{TcpListener, ToSocketAddrs};
+#[cfg(feature = "metrics")]
+use futures::future::Either;
+use futures::future::{self, FutureExt};
 
-use crate::{http, Middleware, Next, Request, Route, RouteResult, Router};
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{self, HeaderValue};
+use hyper::server::conn::Http;
+use hyper::service::{service_fn, Service};
+use hyper::{Body, HeaderMap};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "metrics")]
+use crate::metrics::TaskMonitors;
+use crate::middleware::{MiddlewareProfile, Timings};
+use crate::proxy::TrustedProxies;
+use crate::request::{MatchedPattern, MaxBodySize, OriginalPath, MAX_BODY_BYTES};
+use crate::response::{ErrorDetail, TrailersBody};
+use crate::state::StateMap;
+use crate::{
+    http, ConnectionInfo, Handler, Method, Middleware, Next, Request, Route, RouteResult, Router,
+    StatefulHandler, TrailingSlash, UnreadBody, WithState,
+};
 
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, GenericError>;
 
+#[derive(Clone)]
 pub struct Server {
     middleware: Arc<Vec<Arc<dyn Middleware>>>,
     router: Arc<Router>,
+    hosts: Arc<Hosts>,
+    config: Arc<Config>,
+    connection: Arc<ConnectionInfo>,
+    state: Arc<StateMap>,
+    shutdown: ShutdownHandle,
+    open_connections: Arc<AtomicUsize>,
+}
+
+/// A [Server] bound to a listener, see [Server::bind].
+pub struct BoundServer {
+    server: Server,
+    listener: TcpListener,
+    local_addr: SocketAddr,
+}
+
+impl BoundServer {
+    /// Gets the address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Accepts and serves connections, see [Server::listen].
+    pub async fn serve(self) -> Result<()> {
+        self.server.serve(self.listener, future::pending()).await
+    }
+
+    /// Accepts and serves connections until the signal completes, then shuts
+    /// down gracefully, see [Server::listen_with_shutdown].
+    pub async fn serve_with_shutdown(self, signal: impl Future<Output = ()>) -> Result<()> {
+        self.server.serve(self.listener, signal).await
+    }
+}
+
+/// A handle that puts a [Server] into shutdown, see [Server::shutdown_handle].
+#[derive(Clone, Default)]
+pub struct ShutdownHandle(Arc<AtomicBool>);
+
+impl ShutdownHandle {
+    /// Starts the shutdown: requests already running finish normally while new
+    /// requests, including ones on open keep-alive connections, are rejected
+    /// with a `503 Service Unavailable`.
+    pub fn shutdown(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Determines if the shutdown has started.
+    pub fn is_shutting_down(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Counts a connection as open until dropped.
+struct OpenConnection(Arc<AtomicUsize>);
+
+impl OpenConnection {
+    fn new(open_connections: &Arc<AtomicUsize>) -> Self {
+        open_connections.fetch_add(1, Ordering::AcqRel);
+        OpenConnection(open_connections.clone())
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// Fails to compile if a field makes the server unsafe to clone into
+// connection tasks.
+fn _assert_send_sync()
+where
+    Server: Send + Sync,
+{
+}
+
+/// Routers selected by the `Host` of a request.
+#[derive(Default)]
+struct Hosts {
+    exact: HashMap<String, Arc<Router>>,
+    wildcard: Vec<(String, Arc<Router>)>,
+}
+
+impl Hosts {
+    /// Inserts the router for the hostname, which may be a `*.` wildcard.
+    /// Wildcards are stored with their leading dot so they only match whole
+    /// labels, e.g. `*.example.com` never matches `badexample.com`.
+    fn insert(&mut self, hostname: &str, router: Router) {
+        let hostname = hostname.to_ascii_lowercase();
+        let wildcards = hostname.matches('*').count();
+        assert!(
+            wildcards == 0 || wildcards == 1 && hostname.starts_with("*."),
+            "Wildcard hostname {} must start with `*.`",
+            hostname
+        );
+        match hostname.strip_prefix('*') {
+            Some(suffix) => {
+                self.wildcard.retain(|(s, _)| s != suffix);
+                self.wildcard.push((String::from(suffix), Arc::new(router)));
+                self.wildcard
+                    .sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+            }
+            None => {
+                self.exact.insert(hostname, Arc::new(router));
+            }
+        }
+    }
+
+    /// Selects the router for the host, preferring exact matches over the
+    /// most specific wildcard.
+    fn select(&self, host: &str) -> Option<&Arc<Router>> {
+        let host = host.to_ascii_lowercase();
+        self.exact.get(&host).or_else(|| {
+            self.wildcard
+                .iter()
+                .find(|(suffix, _)| host.len() > suffix.len() && host.ends_with(suffix.as_str()))
+                .map(|(_, router)| router)
+        })
+    }
+}
+
+/// Determines if the request carries both `Transfer-Encoding` and
+/// `Content-Length`, which RFC 7230 treats as a request smuggling attempt.
+///
+/// hyper frames such requests by `Transfer-Encoding` and drops a
+/// `Content-Length` that follows it while parsing, before the headers (or
+/// their raw case map) are available, so only a `Content-Length` sent first
+/// can be seen and rejected here. The other order reaches handlers as a plain
+/// chunked request without any `Content-Length`.
+fn is_ambiguously_framed(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::TRANSFER_ENCODING) && headers.contains_key(header::CONTENT_LENGTH)
+}
+
+/// Corrects a `Content-Length` set by a handler that disagrees with the size
+/// of a buffered body, which would otherwise leave the client waiting for
+/// bytes that never come or reading the rest of the body as the next response.
+///
+/// Streaming bodies have no known size and are left alone, as are empty
+/// bodies, since responses to `HEAD` requests and `304 Not Modified` responses
+/// declare the length of a body they do not send.
+fn correct_content_length(res: &mut http::Response) {
+    let len = match res.body().size_hint().exact() {
+        Some(len) if len > 0 => len,
+        _ => return,
+    };
+    let declared = match res.headers().get(header::CONTENT_LENGTH) {
+        Some(declared) => declared,
+        None => return,
+    };
+    if declared.to_str().ok().and_then(|d| d.parse().ok()) != Some(len) {
+        log::warn!(
+            "Response Content-Length {:?} does not match its body of {} bytes, correcting it",
+            declared,
+            len
+        );
+        res.headers_mut().insert(header::CONTENT_LENGTH, len.into());
+    }
+}
+
+/// Drops the body of a response to a `HEAD` request, declaring the length of
+/// the body it replaces when known and not already declared.
+fn strip_body(res: &mut http::Response) {
+    if let Some(len) = res.body().size_hint().exact().filter(|len| *len > 0) {
+        if !res.headers().contains_key(header::CONTENT_LENGTH) {
+            res.headers_mut().insert(header::CONTENT_LENGTH, len.into());
+        }
+    }
+    *res.body_mut() = Body::empty();
+}
+
+/// Gets the hostname of the request without the port or a trailing dot.
+///
+/// The authority of an absolute-form request target takes precedence over the
+/// `Host` header, as RFC 9112 §3.2.2 requires.
+fn request_host(req: &http::Request) -> Option<&str> {
+    let host = match req.uri().host() {
+        Some(host) => host,
+        None => {
+            let host = req.headers().get(header::HOST)?.to_str().ok()?;
+            if host.starts_with('[') {
+                host.find(']').map(|end| &host[..=end])?
+            } else {
+                host.split(':').next()?
+            }
+        }
+    };
+    Some(host.strip_suffix('.').unwrap_or(host))
+}
+
+/// Server level configuration shared across connections.
+struct Config {
+    default_content_type: Option<HeaderValue>,
+    dev_mode: bool,
+    request_timeout: Option<Duration>,
+    max_headers: usize,
+    max_header_bytes: usize,
+    trusted_proxies: Arc<TrustedProxies>,
+    rewriter: Option<Box<Rewriter>>,
+    profiler: Option<Box<Profiler>>,
+    not_found: Option<ErrorBody>,
+    method_not_allowed: Option<ErrorBody>,
+    shutdown_retry_after: Duration,
+    drain_timeout: Option<Duration>,
+    max_connections: Option<usize>,
+    shed_load_at: Option<usize>,
+    /// The priorities of the mounted middleware, in the same order.
+    priorities: Vec<i32>,
+    unread_body: UnreadBody,
+    max_body_size: usize,
+    #[cfg(feature = "metrics")]
+    task_monitors: Option<Arc<TaskMonitors>>,
+}
+
+/// A fixed body and content type for the default error responses.
+struct ErrorBody {
+    body: Bytes,
+    content_type: HeaderValue,
+}
+
+/// Creates a default error response, with the configured body if any.
+fn error_response(status: hyper::StatusCode, body: &Option<ErrorBody>) -> http::Response {
+    let res = hyper::Response::builder().status(status);
+    match body {
+        Some(ErrorBody { body, content_type }) => res
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body.clone())),
+        None => res.body(Body::empty()),
+    }
+    .unwrap()
+}
+
+/// Rewrites a request before it is routed, see [Server::with_rewriter].
+type Rewriter = dyn Fn(&mut http::RequestParts) + Send + Sync;
+
+/// Receives the middleware timings of each request, see [Server::with_profiler].
+type Profiler = dyn Fn(&MiddlewareProfile) + Send + Sync;
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            default_content_type: None,
+            dev_mode: false,
+            request_timeout: None,
+            max_headers: 100,
+            max_header_bytes: 16 * 1024,
+            trusted_proxies: Arc::default(),
+            rewriter: None,
+            profiler: None,
+            not_found: None,
+            method_not_allowed: None,
+            shutdown_retry_after: Duration::from_secs(5),
+            drain_timeout: None,
+            max_connections: None,
+            shed_load_at: None,
+            priorities: Vec::new(),
+            unread_body: UnreadBody::Drain(64 * 1024),
+            max_body_size: MAX_BODY_BYTES,
+            #[cfg(feature = "metrics")]
+            task_monitors: None,
+        }
+    }
+}
+
+thread_local! {
+    /// The backtrace of the last panic on this thread, captured in dev mode.
+    static PANIC_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Installs a panic hook, once, that captures the backtrace of each panic so it
+/// can be rendered by the server in dev mode. The previous hook still runs.
+fn capture_panic_backtraces() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            PANIC_BACKTRACE.with(|bt| *bt.borrow_mut() = Some(Backtrace::force_capture()));
+            previous(info);
+        }));
+    });
+}
+
+/// The smallest read buffer hyper accepts for HTTP/1 connections.
+const MIN_BUF_SIZE: usize = 8192;
+
+/// How long to stop accepting connections after an error such as running out
+/// of file descriptors, giving connections time to close.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Determines if an error accepting a connection only concerns that
+/// connection, such as a client resetting it before it was accepted.
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+    )
+}
+
+/// Determines if an error accepting a connection means the listener itself is
+/// unusable, such as a socket that is not listening. Other errors, including
+/// running out of file descriptors, are transient and accepting carries on.
+fn is_fatal_accept_error(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::InvalidInput
+}
+
+impl Config {
+    /// Determines if the request headers exceed the configured limits. Header
+    /// sizes are counted as `name: value\r\n`, the way they arrive over HTTP/1.
+    fn headers_too_large(&self, headers: &HeaderMap) -> bool {
+        let bytes: usize = headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len() + 4)
+            .sum();
+        headers.len() > self.max_headers || bytes > self.max_header_bytes
+    }
+
+    /// Creates the 500 response for a handler that panicked. In dev mode the
+    /// panic message and backtrace are rendered into the body.
+    fn panic_response(&self, panic: Box<dyn Any + Send>) -> http::Response {
+        let backtrace = PANIC_BACKTRACE.with(|bt| bt.borrow_mut().take());
+        let res = hyper::Response::builder().status(hyper::StatusCode::INTERNAL_SERVER_ERROR);
+        if !self.dev_mode {
+            return res.body(Body::empty()).unwrap();
+        }
+
+        let message = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+        let mut body = format!("handler panicked: {}\n", message);
+        if let Some(backtrace) = backtrace {
+            body.push_str(&format!("\n{}\n", backtrace));
+        }
+        res.header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    /// Applies the server configuration to a response produced by the router.
+    fn process(&self, mut res: http::Response) -> http::Response {
+        if self.dev_mode {
+            if let Some(ErrorDetail(detail)) = res.extensions_mut().remove() {
+                res.headers_mut()
+                    .insert(header::CONTENT_LENGTH, detail.len().into());
+                *res.body_mut() = Body::from(detail);
+            }
+        }
+        if let Some(content_type) = &self.default_content_type {
+            if !res.headers().contains_key(header::CONTENT_TYPE) && !res.body().is_end_stream() {
+                res.headers_mut()
+                    .insert(header::CONTENT_TYPE, content_type.clone());
+            }
+        }
+        res
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server::new()
+    }
 }
 
 impl Server {
@@ -25,14 +421,131 @@ impl Server {
         Server {
             middleware: Arc::new(Vec::new()),
             router: Arc::new(Router::new()),
+            hosts: Arc::new(Hosts::default()),
+            config: Arc::new(Config::default()),
+            connection: Arc::new(ConnectionInfo::default()),
+            state: Arc::new(StateMap::default()),
+            shutdown: ShutdownHandle::default(),
+            open_connections: Arc::default(),
         }
     }
 
-    /// Mounts middleware implementation to the server.
+    fn config_mut(&mut self) -> &mut Config {
+        Arc::get_mut(&mut self.config).expect("Cannot configure server after binding to listener")
+    }
+
+    /// Sets the content type applied to responses that have a body but do not
+    /// declare a `Content-Type` of their own.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// let mut app = sidemount::new();
+    /// app.with_default_content_type("text/plain; charset=utf-8");
+    /// ```
+    pub fn with_default_content_type(&mut self, content_type: &'static str) -> &mut Self {
+        self.config_mut().default_content_type = Some(HeaderValue::from_static(content_type));
+        self
+    }
+
+    /// Shares the state with every handler through [Request::state], keyed by
+    /// its type so several distinct states can be registered. The state is
+    /// stored behind an [Arc] that is shared by all connections rather than
+    /// cloned, so it may hold resources that are not [Clone] such as a
+    /// database pool.
+    ///
+    /// State that needs async construction can be built before registering it
+    /// or with [Server::with_state_async].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use std::sync::atomic::AtomicU64;
+    ///
+    /// struct Hits(AtomicU64);
+    ///
+    /// let mut app = sidemount::new();
+    /// app.with_state(Hits(AtomicU64::new(0)));
+    /// ```
+    pub fn with_state<S: Send + Sync + 'static>(&mut self, state: S) -> &mut Self {
+        Arc::get_mut(&mut self.state)
+            .expect("Cannot add state after binding to listener")
+            .insert(state);
+        self
+    }
+
+    /// Awaits the future and shares its output as state, see
+    /// [Server::with_state].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// struct Pool;
+    ///
+    /// async fn connect() -> Pool {
+    ///     Pool
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut app = sidemount::new();
+    /// app.with_state_async(connect()).await;
+    /// # }
+    /// ```
+    pub async fn with_state_async<S, F>(&mut self, state: F) -> &mut Self
+    where
+        S: Send + Sync + 'static,
+        F: Future<Output = S>,
+    {
+        let state = state.await;
+        self.with_state(state)
+    }
+
+    /// Mounts middleware implementation to the server, with the default
+    /// priority of `0` (see [Server::mount_with_priority]).
     pub fn mount(&mut self, mid: impl Middleware) {
+        self.mount_with_priority(mid, 0);
+    }
+
+    /// Mounts middleware implementation to the server with a priority. Lower
+    /// priorities run first, wrapping everything after them, and middleware of
+    /// equal priority run in the order they were mounted.
+    ///
+    /// Recommended priorities for the shipped middleware:
+    /// - `-100` for [crate::middleware::AccessLog] and
+    ///   [crate::middleware::Logger], so their latency and status cover every
+    ///   other middleware.
+    /// - `-50` for [crate::middleware::Cors], so preflights are answered before
+    ///   authentication, which browsers send them without, while still being
+    ///   logged.
+    /// - `-10` for [crate::middleware::CatchPanic] and
+    ///   [crate::middleware::Timeout], so panics and timeouts of
+    ///   authentication, the session and the handler become responses that
+    ///   CORS and the access log still see.
+    /// - `0` for the session middleware, so handlers and authentication can
+    ///   read the session.
+    /// - `100` for [crate::middleware::Idempotency], after authentication, so
+    ///   replayed responses are only served to authenticated requests.
+    ///
+    /// Authentication belongs between `-10` and `100`, and response
+    /// compression innermost, at the highest priority.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use std::time::Duration;
+    /// use sidemount::middleware::{AccessLog, Idempotency, MemoryIdempotencyStore};
+    ///
+    /// let mut app = sidemount::new();
+    /// let store = MemoryIdempotencyStore::new(Duration::from_secs(86400));
+    /// app.mount_with_priority(Idempotency::new(store), 100);
+    /// app.mount_with_priority(AccessLog::default(), -100);
+    /// ```
+    pub fn mount_with_priority(&mut self, mid: impl Middleware, priority: i32) {
         let middleware = Arc::get_mut(&mut self.middleware)
             .expect("Cannot mount middleware after binding to listener");
-        middleware.push(Arc::new(mid));
+        let priorities = &mut Arc::get_mut(&mut self.config)
+            .expect("Cannot mount middleware after binding to listener")
+            .priorities;
+        let index = priorities.partition_point(|existing| *existing <= priority);
+        priorities.insert(index, priority);
+        middleware.insert(index, Arc::new(mid));
     }
 
     /// Creates a new node node or returns a mutable reference to an existing one.
@@ -42,6 +555,51 @@ impl Server {
         router.at(path)
     }
 
+    /// Inserts a handler for the path and method that is given the state of
+    /// type `S` registered with [Server::with_state] along with the request,
+    /// instead of looking it up through [Request::state].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::sync::Arc;
+    /// use sidemount::{Method, Request, Response};
+    ///
+    /// struct Hits(AtomicU64);
+    ///
+    /// async fn hits(req: Request, hits: Arc<Hits>) -> Response {
+    ///     let count = hits.0.fetch_add(1, Ordering::Relaxed) + 1;
+    ///     Response::text(count.to_string())
+    /// }
+    ///
+    /// let mut app = sidemount::new();
+    /// app.with_state(Hits(AtomicU64::new(0)));
+    /// app.route_with_state(Method::GET, "/hits", hits);
+    /// ```
+    pub fn route_with_state<S, H>(&mut self, method: Method, path: &str, handler: H)
+    where
+        S: Send + Sync + 'static,
+        H: StatefulHandler<S>,
+    {
+        self.at(path).method(method, WithState::new(handler));
+    }
+
+    /// Configures every method of a resource in one call, see
+    /// [Router::resource].
+    pub fn resource(&mut self, path: &str, configure: impl FnOnce(&mut Route)) -> &mut Self {
+        configure(self.at(path));
+        self
+    }
+
+    /// Sets how the router treats a trailing slash on paths that only match
+    /// without it, see [Router::trailing_slash].
+    pub fn with_trailing_slash(&mut self, policy: TrailingSlash) -> &mut Self {
+        let router =
+            Arc::get_mut(&mut self.router).expect("Cannot mount router after binding to listener");
+        router.trailing_slash(policy);
+        self
+    }
+
     /// Routes a path on the router to an existing router implementation.
     pub fn route(&mut self, path: &str, router: impl Into<Router>) {
         let rt =
@@ -49,22 +607,851 @@ impl Server {
         rt.route(path, router);
     }
 
+    /// Sets a fallback handler for any unmatched path under the given path,
+    /// see [Router::fallback].
+    pub fn fallback(&mut self, path: &str, handler: impl Handler) {
+        let router =
+            Arc::get_mut(&mut self.router).expect("Cannot mount router after binding to listener");
+        router.fallback(path, handler);
+    }
+
+    /// Enables or disables development mode. In development mode a panicking
+    /// handler produces a 500 response with the panic message and backtrace in
+    /// the body, while in production (the default) the 500 has an empty body
+    /// so internals are not leaked to clients.
+    pub fn with_dev_mode(&mut self, dev_mode: bool) -> &mut Self {
+        if dev_mode {
+            capture_panic_backtraces();
+        }
+        self.config_mut().dev_mode = dev_mode;
+        self
+    }
+
+    /// Sets the maximum time a request may take to run through the middleware
+    /// and handler before the server responds with a `503 Service Unavailable`.
+    ///
+    /// When the deadline passes the request future is dropped, which cancels
+    /// the handler and every middleware in the chain at their current `.await`
+    /// point and runs the destructors of anything they hold (database
+    /// connections, semaphore permits, file handles). Tasks the handler started
+    /// with `tokio::spawn` are detached and keep running; keep their
+    /// `JoinHandle` and abort it on drop if they should be cancelled too.
+    pub fn with_request_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config_mut().request_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the `Retry-After` sent with the `503 Service Unavailable` responses
+    /// rejecting requests during shutdown, defaults to 5 seconds.
+    pub fn with_shutdown_retry_after(&mut self, retry_after: Duration) -> &mut Self {
+        self.config_mut().shutdown_retry_after = retry_after;
+        self
+    }
+
+    /// Sets the longest [Server::listen_with_shutdown] waits for open
+    /// connections to finish once its signal fires, after which they are
+    /// dropped. By default it waits for them indefinitely.
+    pub fn with_drain_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config_mut().drain_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of connections served at once by
+    /// [Server::listen], which are unbounded by default. Once reached, new
+    /// connections wait in the listen backlog until a served one closes, so
+    /// a connection flood cannot spawn an unbounded number of tasks.
+    pub fn with_max_connections(&mut self, max: usize) -> &mut Self {
+        self.config_mut().max_connections = Some(max);
+        self
+    }
+
+    /// Enables load shedding: while at least the given number of connections
+    /// are open, or once the shutdown has started, HTTP/1 responses carry
+    /// `Connection: close` and their connection is not reused. Clients then
+    /// reconnect, which spreads them across instances behind a load balancer
+    /// instead of pinning their keep-alive connections to an overloaded one.
+    ///
+    /// Connections are counted across [Server::listen] and
+    /// [Server::serve_connection]. With [Server::with_max_connections], set the
+    /// threshold somewhat below the maximum, such as at 90% of it, so slots are
+    /// freed before new connections start queuing in the listen backlog; a
+    /// threshold at or above the maximum is never reached by
+    /// [Server::listen]. HTTP/2 connections multiplex requests and are not
+    /// shed.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// let mut app = sidemount::new();
+    /// app.with_max_connections(1000).with_load_shedding(900);
+    /// ```
+    pub fn with_load_shedding(&mut self, open_connections: usize) -> &mut Self {
+        self.config_mut().shed_load_at = Some(open_connections);
+        self
+    }
+
+    /// Determines if responses should close their connection to shed load,
+    /// see [Server::with_load_shedding].
+    fn shedding_load(&self) -> bool {
+        self.config.shed_load_at.is_some_and(|threshold| {
+            self.shutdown.is_shutting_down()
+                || self.open_connections.load(Ordering::Acquire) >= threshold
+        })
+    }
+
+    /// Instruments the connection tasks and the requests of each route with
+    /// [tokio_metrics] task monitors, returning them to be sampled, such as
+    /// periodically through [tokio_metrics::TaskMonitor::intervals].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// let mut app = sidemount::new();
+    /// let monitors = app.with_task_monitors();
+    /// // Later, from a reporting task:
+    /// for (pattern, monitor) in monitors.routes() {
+    ///     let metrics = monitor.cumulative();
+    ///     println!("{}: {:?} polling", pattern, metrics.total_poll_duration);
+    /// }
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn with_task_monitors(&mut self) -> Arc<TaskMonitors> {
+        self.config_mut()
+            .task_monitors
+            .get_or_insert_with(Arc::default)
+            .clone()
+    }
+
+    /// Sets what happens to a request body the handler did not read, such as
+    /// when it rejects an upload before reading it, which defaults to
+    /// draining up to 64 KiB so the connection can be reused.
+    ///
+    /// A handler never waits on the client to finish sending: its response is
+    /// sent as soon as it returns, and the unread body is then drained in the
+    /// background or, with [UnreadBody::Close], the connection is closed.
+    pub fn with_unread_body(&mut self, unread_body: UnreadBody) -> &mut Self {
+        self.config_mut().unread_body = unread_body;
+        self
+    }
+
+    /// Sets the most bytes [Request::body_bytes] and the body parsers read
+    /// from a request body, defaults to 2 MiB. A larger body fails with
+    /// [crate::BodyError::TooLarge], which handlers returning it answer with a
+    /// `413 Payload Too Large`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{BodyError, Request, Response};
+    ///
+    /// async fn upload(mut req: Request) -> Result<Response, BodyError> {
+    ///     let body = req.body_bytes().await?;
+    ///     Ok(Response::text(format!("received {} bytes", body.len())))
+    /// }
+    ///
+    /// let mut app = sidemount::new();
+    /// app.with_max_body_size(64 * 1024).at("/upload").post(upload);
+    /// ```
+    pub fn with_max_body_size(&mut self, bytes: usize) -> &mut Self {
+        self.config_mut().max_body_size = bytes;
+        self
+    }
+
+    /// Sets the maximum number of headers a request may have before the server
+    /// responds with a `431 Request Header Fields Too Large`, defaults to 100.
+    ///
+    /// The HTTP/1 parser never accepts more than 100 headers, so larger values
+    /// only apply to HTTP/2 connections.
+    pub fn with_max_headers(&mut self, count: usize) -> &mut Self {
+        self.config_mut().max_headers = count;
+        self
+    }
+
+    /// Sets the maximum total size in bytes of the request headers before the
+    /// server responds with a `431 Request Header Fields Too Large`, defaults
+    /// to 16KiB.
+    ///
+    /// The limit also bounds how much an HTTP/1 connection buffers while
+    /// reading a request head, to no less than 8KiB, so oversized heads are
+    /// rejected before they are fully read.
+    pub fn with_max_header_bytes(&mut self, size: usize) -> &mut Self {
+        self.config_mut().max_header_bytes = size;
+        self
+    }
+
+    /// Sets the networks of the proxies in front of the server, in CIDR
+    /// notation such as `10.0.0.0/8` or as single addresses. Requests from
+    /// these peers have their `X-Forwarded-For`, `X-Forwarded-Host` and
+    /// `X-Forwarded-Proto` headers honored by [Request::remote_addr],
+    /// [Request::host] and [Request::scheme]; the headers of any other peer
+    /// are ignored so direct clients cannot spoof them.
+    ///
+    /// ## Panics
+    /// Panics if any of the networks is invalid.
+    pub fn with_trusted_proxies(&mut self, cidrs: &[&str]) -> &mut Self {
+        let proxies = TrustedProxies::parse(cidrs)
+            .unwrap_or_else(|cidr| panic!("Invalid trusted proxy network: {}", cidr));
+        self.config_mut().trusted_proxies = Arc::new(proxies);
+        self
+    }
+
+    /// Sets a function that rewrites each request before it is routed, such as
+    /// mapping legacy URLs to new ones or stripping a language prefix. Unlike a
+    /// redirect the client never sees the new URL; handlers are routed by and
+    /// see the rewritten path while [Request::original_path] keeps the path
+    /// the client requested.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// let mut app = sidemount::new();
+    /// app.with_rewriter(|parts| {
+    ///     if let Some(path) = parts.uri.path().strip_prefix("/en/") {
+    ///         parts.uri = format!("/{}", path).parse().unwrap();
+    ///     }
+    /// });
+    /// ```
+    pub fn with_rewriter(
+        &mut self,
+        rewriter: impl Fn(&mut http::RequestParts) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config_mut().rewriter = Some(Box::new(rewriter));
+        self
+    }
+
+    /// Profiles the middleware chain, calling the profiler after each request
+    /// with the time spent in every middleware and the handler. The time of a
+    /// layer is split into its total, which includes the layers inside it, and
+    /// its own time spent before calling `next.run` and after it returned, so
+    /// a slow middleware stands out even in a deep async chain.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// let mut app = sidemount::new();
+    /// app.with_profiler(|profile| {
+    ///     for layer in &profile.layers {
+    ///         println!("{} {}: {:?}", profile.path, layer.name, layer.own);
+    ///     }
+    /// });
+    /// ```
+    pub fn with_profiler(
+        &mut self,
+        profiler: impl Fn(&MiddlewareProfile) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config_mut().profiler = Some(Box::new(profiler));
+        self
+    }
+
+    /// Sets the body and content type of the `404 Not Found` responses for
+    /// unmatched paths, which are empty by default. Use [Server::fallback] to
+    /// handle unmatched paths with a handler instead.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// let mut app = sidemount::new();
+    /// app.not_found_body(r#"{"error":"not_found"}"#, "application/json");
+    /// ```
+    pub fn not_found_body(
+        &mut self,
+        body: impl Into<Bytes>,
+        content_type: &'static str,
+    ) -> &mut Self {
+        self.config_mut().not_found = Some(ErrorBody {
+            body: body.into(),
+            content_type: HeaderValue::from_static(content_type),
+        });
+        self
+    }
+
+    /// Sets the body and content type of the `405 Method Not Allowed`
+    /// responses for routes without a handler for the method, which are empty
+    /// by default.
+    pub fn method_not_allowed_body(
+        &mut self,
+        body: impl Into<Bytes>,
+        content_type: &'static str,
+    ) -> &mut Self {
+        self.config_mut().method_not_allowed = Some(ErrorBody {
+            body: body.into(),
+            content_type: HeaderValue::from_static(content_type),
+        });
+        self
+    }
+
+    /// Routes requests for the given hostname to a router, falling back to the
+    /// default router for unmatched hosts. Hostnames may start with a `*.`
+    /// wildcard to match any subdomain, e.g. `*.example.com`.
+    ///
+    /// ## Panics
+    /// Panics if a hostname contains a `*` anywhere but a leading `*.`, or if
+    /// called after binding to a listener.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response, Router};
+    ///
+    /// async fn tenant(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// let mut tenants = Router::new();
+    /// tenants.at("/").get(tenant);
+    ///
+    /// let mut app = sidemount::new();
+    /// app.host("*.example.com", tenants);
+    /// ```
+    pub fn host(&mut self, hostname: &str, router: impl Into<Router>) {
+        let hosts =
+            Arc::get_mut(&mut self.hosts).expect("Cannot mount router after binding to listener");
+        hosts.insert(hostname, router.into());
+    }
+
     /// Executes a listener on a given listener type.
     pub async fn listen<T: ToSocketAddrs>(self, addr: T) -> Result<()> {
+        self.bind(addr).await?.serve().await
+    }
+
+    /// Binds a listener to the address without accepting connections yet, so
+    /// the address actually bound, such as the port picked for port 0, is
+    /// known before serving.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # #[tokio::main]
+    /// # async fn main() -> sidemount::Result<()> {
+    /// let app = sidemount::new();
+    /// let bound = app.bind("127.0.0.1:0").await?;
+    /// println!("listening on {}", bound.local_addr());
+    /// # drop(bound);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn bind<T: ToSocketAddrs>(self, addr: T) -> Result<BoundServer> {
         let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        Ok(BoundServer {
+            server: self,
+            listener,
+            local_addr,
+        })
+    }
+
+    /// Listens like [Server::listen] until the signal completes, then shuts
+    /// down gracefully: the listener is closed, idle connections are closed,
+    /// requests already running finish, and it returns once every connection
+    /// is done or the drain timeout passes (see [Server::with_drain_timeout]).
+    ///
+    /// Requests arriving meanwhile are rejected as after
+    /// [ShutdownHandle::shutdown].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # #[tokio::main]
+    /// # async fn main() -> sidemount::Result<()> {
+    /// let app = sidemount::new();
+    /// let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    /// let server = tokio::spawn(app.listen_with_shutdown("127.0.0.1:0", async {
+    ///     stopped.await.ok();
+    /// }));
+    /// // On SIGTERM:
+    /// stop.send(()).ok();
+    /// server.await.unwrap()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn listen_with_shutdown<T, F>(self, addr: T, signal: F) -> Result<()>
+    where
+        T: ToSocketAddrs,
+        F: Future<Output = ()>,
+    {
+        self.bind(addr).await?.serve_with_shutdown(signal).await
+    }
+
+    /// Listens like [Server::listen] over TLS, completing the handshake of
+    /// each connection on its own task before serving it. The SNI server
+    /// name, ALPN protocol and TLS version are available through
+    /// [Request::connection_info]. A failed handshake, or one not completed
+    /// within 10 seconds, is logged at debug level and drops the connection.
+    ///
+    /// To serve HTTP/2, the config must offer `h2` through ALPN, as configs
+    /// loaded with [crate::TlsConfig::from_pem] do.
+    ///
+    /// ## Examples
+    /// ```rust,no_run
+    /// use sidemount::TlsConfig;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> sidemount::Result<()> {
+    /// let app = sidemount::new();
+    /// let config = TlsConfig::from_pem("cert.pem", "key.pem")?;
+    /// app.listen_tls("0.0.0.0:443", config).await
+    /// # }
+    /// ```
+    #[cfg(feature = "tls")]
+    pub async fn listen_tls<T: ToSocketAddrs>(
+        self,
+        addr: T,
+        config: rustls::ServerConfig,
+    ) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        self.serve_tls(listener, config).await
+    }
+
+    /// Accepts TLS connections on the listener, see [Server::listen_tls].
+    #[cfg(feature = "tls")]
+    pub(crate) async fn serve_tls(
+        self,
+        listener: TcpListener,
+        config: rustls::ServerConfig,
+    ) -> Result<()> {
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        let handshake = move |stream, info| {
+            crate::tls::accept(
+                acceptor.clone(),
+                crate::tls::HANDSHAKE_TIMEOUT,
+                stream,
+                info,
+            )
+        };
+        self.serve_with(listener, future::pending(), handshake)
+            .await
+    }
+
+    /// Listens on the first port in the range that is free on the host, which
+    /// avoids port collisions in test harnesses and dev servers. The server
+    /// runs on a spawned task; the bound address is returned so callers know
+    /// where to connect, along with the task handle to await or abort.
+    ///
+    /// Ports already in use are skipped while any other bind error is returned
+    /// immediately.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # #[tokio::main]
+    /// # async fn main() -> sidemount::Result<()> {
+    /// let app = sidemount::new();
+    /// let (addr, server) = app.listen_available("127.0.0.1", 8000..9000).await?;
+    /// println!("listening on {}", addr);
+    /// server.abort();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn listen_available(
+        self,
+        host: &str,
+        ports: impl IntoIterator<Item = u16>,
+    ) -> Result<(SocketAddr, JoinHandle<Result<()>>)> {
+        for port in ports {
+            match TcpListener::bind((host, port)).await {
+                Ok(listener) => {
+                    let addr = listener.local_addr()?;
+                    return Ok((addr, tokio::spawn(self.serve(listener, future::pending()))));
+                }
+                Err(err) if err.kind() == io::ErrorKind::AddrInUse => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::AddrInUse, "No available port in range").into())
+    }
+
+    /// Gets a handle to shut down the server, which stays valid after the
+    /// server is moved into [Server::listen].
+    ///
+    /// Once shut down, new requests get a `503 Service Unavailable` with a
+    /// `Retry-After` (see [Server::with_shutdown_retry_after]) and
+    /// `Connection: close`, so clients on keep-alive connections reconnect to
+    /// another instance while requests already running finish normally.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let app = sidemount::new();
+    /// let shutdown = app.shutdown_handle();
+    /// tokio::spawn(app.listen("127.0.0.1:0"));
+    /// // On SIGTERM, before the load balancer stops routing to the instance:
+    /// shutdown.shutdown();
+    /// # }
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Accepts connections on the listener until the signal completes, serving
+    /// each on its own task.
+    ///
+    /// The tasks are tracked in a set that reaps them as they complete, and
+    /// while [Server::with_max_connections] of them are running no further
+    /// connections are accepted, leaving them queued in the listen backlog.
+    /// Transient errors accepting a connection are logged and accepting
+    /// resumes after a short pause; only an unusable listener stops the loop.
+    /// Once the signal completes, the connections are closed gracefully and
+    /// waited for, see [Server::listen_with_shutdown].
+    async fn serve(self, listener: TcpListener, signal: impl Future<Output = ()>) -> Result<()> {
+        let plain = |stream, info| future::ready(Ok((stream, info)));
+        self.serve_with(listener, signal, plain).await
+    }
+
+    /// Accepts connections like [Server::serve], running the handshake on
+    /// each connection's task before serving the stream it returns, such as a
+    /// TLS stream along with its details. A failed handshake drops the
+    /// connection without stopping the loop.
+    async fn serve_with<H, F, S>(
+        self,
+        listener: TcpListener,
+        signal: impl Future<Output = ()>,
+        handshake: H,
+    ) -> Result<()>
+    where
+        H: Fn(TcpStream, ConnectionInfo) -> F,
+        F: Future<Output = io::Result<(S, ConnectionInfo)>> + Send + 'static,
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let max_connections = self.config.max_connections;
+        let mut connections = JoinSet::new();
+        let closing = CancellationToken::new();
+        let mut paused = None;
+        tokio::pin!(signal);
         loop {
-            let (stream, _) = listener.accept().await?;
+            let accepting =
+                paused.is_none() && max_connections.is_none_or(|max| connections.len() < max);
+            tokio::select! {
+                _ = &mut signal => break,
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+                _ = tokio::time::sleep_until(paused.unwrap_or_else(tokio::time::Instant::now)),
+                    if paused.is_some() => paused = None,
+                accepted = listener.accept(), if accepting => {
+                    let (stream, remote_addr) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(err) if is_connection_error(&err) => {
+                            log::debug!("Failed to accept connection: {}", err);
+                            continue;
+                        }
+                        Err(err) if is_fatal_accept_error(&err) => return Err(err.into()),
+                        Err(err) => {
+                            log::error!("Failed to accept connection, pausing: {}", err);
+                            paused = Some(tokio::time::Instant::now() + ACCEPT_BACKOFF);
+                            continue;
+                        }
+                    };
+
+                    let info = ConnectionInfo {
+                        remote_addr: Some(remote_addr),
+                        local_addr: stream.local_addr().ok(),
+                        ..ConnectionInfo::default()
+                    };
+                    let handshake = handshake(stream, info);
+                    let mut server = self.clone();
+                    let closing = closing.clone();
+                    let connection = async move {
+                        let (stream, info) = match handshake.await {
+                            Ok(accepted) => accepted,
+                            Err(err) => {
+                                log::debug!("Handshake with {} failed: {}", remote_addr, err);
+                                return;
+                            }
+                        };
+                        server.connection = Arc::new(info);
+                        if let Err(err) = server.serve_connection_until(stream, closing.cancelled()).await {
+                            log::error!("Failed to serve connection: {:?}", err);
+                        }
+                    };
+                    #[cfg(feature = "metrics")]
+                    let connection = match &self.config.task_monitors {
+                        Some(monitors) => Either::Left(monitors.connections().instrument(connection)),
+                        None => Either::Right(connection),
+                    };
+                    connections.spawn(connection);
+                }
+            }
+        }
 
-            let server = Server {
-                middleware: self.middleware.clone(),
-                router: self.router.clone(),
+        drop(listener);
+        self.shutdown.shutdown();
+        closing.cancel();
+        let drained = async { while connections.join_next().await.is_some() {} };
+        match self.config.drain_timeout {
+            Some(timeout) => {
+                if tokio::time::timeout(timeout, drained).await.is_err() {
+                    connections.shutdown().await;
+                }
+            }
+            None => drained.await,
+        }
+        Ok(())
+    }
+
+    /// Serves a single connection over any transport through the router and
+    /// middleware, for custom accept loops, TLS terminators or in-process
+    /// streams such as [tokio::io::duplex].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn index(req: Request) -> Response {
+    ///     Response::default()
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> sidemount::Result<()> {
+    /// let mut app = sidemount::new();
+    /// app.at("/").get(index);
+    ///
+    /// let (client, server) = tokio::io::duplex(1024);
+    /// tokio::spawn(async move { app.serve_connection(server).await });
+    /// # drop(client);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn serve_connection<S>(&self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        self.serve_connection_until(stream, future::pending()).await
+    }
+
+    /// Serves a connection until the closing future completes, then closes it
+    /// gracefully: hyper finishes the requests already running and sends an
+    /// HTTP/2 `GOAWAY`.
+    async fn serve_connection_until<S>(
+        &self,
+        stream: S,
+        closing: impl Future<Output = ()>,
+    ) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let _open = OpenConnection::new(&self.open_connections);
+        let max_header_bytes = self.config.max_header_bytes;
+        let conn = Http::new()
+            .max_buf_size(max_header_bytes.max(MIN_BUF_SIZE))
+            .http2_max_header_list_size(max_header_bytes.try_into().unwrap_or(u32::MAX))
+            .serve_connection(
+                stream,
+                service_fn(|req| {
+                    let server = self.clone();
+                    async move {
+                        let res = server.handle_request(req).await;
+                        Ok::<_, hyper::Error>(TrailersBody::wrap(res))
+                    }
+                }),
+            );
+        tokio::pin!(conn);
+        tokio::select! {
+            served = conn.as_mut() => return Ok(served?),
+            _ = closing => {}
+        }
+        conn.as_mut().graceful_shutdown();
+        conn.await?;
+        Ok(())
+    }
+}
+
+impl Server {
+    /// Runs a request through the router and middleware to produce a response,
+    /// exactly as for a request received on a connection but without binding a
+    /// socket, such as to test an app or drive it from another server.
+    ///
+    /// The request carries no [ConnectionInfo], so [Request::remote_addr] is
+    /// `None` unless the headers of a trusted proxy provide it.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// use hyper::{Body, StatusCode};
+    /// use sidemount::{Request, Response};
+    ///
+    /// async fn index(req: Request) -> Response {
+    ///     Response::text("hello")
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut app = sidemount::new();
+    /// app.at("/").get(index);
+    ///
+    /// let req = hyper::Request::get("/").body(Body::empty()).unwrap();
+    /// let res = app.handle_request(req).await;
+    /// assert_eq!(res.status(), StatusCode::OK);
+    /// # }
+    /// ```
+    pub async fn handle_request(&self, mut req: http::Request) -> http::Response {
+        if self.shutdown.is_shutting_down() {
+            let res = hyper::Response::builder()
+                .status(hyper::StatusCode::SERVICE_UNAVAILABLE)
+                .header(
+                    header::RETRY_AFTER,
+                    self.config.shutdown_retry_after.as_secs(),
+                )
+                .header(header::CONNECTION, "close")
+                .body(Body::empty())
+                .unwrap();
+            return self.config.process(res);
+        }
+        req.extensions_mut().insert(self.connection.clone());
+        req.extensions_mut().insert(self.state.clone());
+        req.extensions_mut().insert(self.config.unread_body);
+        req.extensions_mut()
+            .insert(MaxBodySize(self.config.max_body_size));
+        req.extensions_mut()
+            .insert(self.config.trusted_proxies.clone());
+        if self.config.headers_too_large(req.headers()) {
+            let res = hyper::Response::builder()
+                .status(hyper::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+                .body(Body::empty())
+                .unwrap();
+            return self.config.process(res);
+        }
+        if is_ambiguously_framed(req.headers()) {
+            let res = hyper::Response::builder()
+                .status(hyper::StatusCode::BAD_REQUEST)
+                .header(header::CONNECTION, "close")
+                .body(Body::empty())
+                .unwrap();
+            return self.config.process(res);
+        }
+        if let Some(rewriter) = &self.config.rewriter {
+            let (mut parts, body) = req.into_parts();
+            let path = parts.uri.path().to_owned();
+            rewriter(&mut parts);
+            if parts.uri.path() != path {
+                parts.extensions.insert(OriginalPath(path));
+            }
+            req = hyper::Request::from_parts(parts, body);
+        }
+        let version = req.version();
+        let head = req.method() == hyper::Method::HEAD;
+        let options = req.method() == hyper::Method::OPTIONS;
+        let router = request_host(&req)
+            .and_then(|host| self.hosts.select(host))
+            .unwrap_or(&self.router);
+        if let Some(path) = router.redirect_path(req.uri().path()) {
+            let location = match req.uri().query() {
+                Some(query) => format!("{}?{}", path, query),
+                None => String::from(path),
             };
-            tokio::task::spawn(async move {
-                if let Err(err) = Http::new().serve_connection(stream, server).await {
-                    eprintln!("Failed to serve connection: {:?}", err);
+            let res = hyper::Response::builder()
+                .status(hyper::StatusCode::MOVED_PERMANENTLY)
+                .header(header::LOCATION, location)
+                .body(Body::empty())
+                .unwrap();
+            return self.config.process(res);
+        }
+
+        let mut res = match router.find(req.uri().path(), req.method().into()) {
+            RouteResult::Found(r) => {
+                let (route, handler, params) = r;
+                req.extensions_mut()
+                    .insert(MatchedPattern(route.shared_pattern()));
+                if !route.accepts_body(req.headers()) {
+                    let res = hyper::Response::builder()
+                        .status(hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                        .body(Body::empty())
+                        .unwrap();
+                    return self.config.process(res);
                 }
-            });
+                let middleware = router.chain(route, &self.middleware);
+                let run = async {
+                    match route.acquire().await {
+                        Ok(_permit) => {
+                            let mut next = Next::new(middleware.clone(), handler);
+                            let profile = self.config.profiler.as_ref().map(|profiler| {
+                                let timings = Arc::new(Timings::new(middleware.len() + 1));
+                                let request = (req.method().clone(), req.uri().path().to_owned());
+                                (profiler, timings, request)
+                            });
+                            if let Some((_, timings, _)) = &profile {
+                                next = next.with_timings(timings.clone());
+                            }
+
+                            let req = Request::new(req, params);
+                            let res = match AssertUnwindSafe(next.run(req)).catch_unwind().await {
+                                Ok(res) => res.into(),
+                                Err(panic) => self.config.panic_response(panic),
+                            };
+                            if let Some((profiler, timings, (method, path))) = profile {
+                                profiler(&timings.profile(method, path, &middleware));
+                            }
+                            res
+                        }
+                        Err(()) => hyper::Response::builder()
+                            .status(hyper::StatusCode::SERVICE_UNAVAILABLE)
+                            .body(Body::empty())
+                            .unwrap(),
+                    }
+                };
+                #[cfg(feature = "metrics")]
+                let run = match &self.config.task_monitors {
+                    Some(monitors) => Either::Left(
+                        monitors
+                            .route_monitor(&route.shared_pattern())
+                            .instrument(run),
+                    ),
+                    None => Either::Right(run),
+                };
+                match self.config.request_timeout {
+                    Some(timeout) => {
+                        tokio::time::timeout(timeout, run)
+                            .await
+                            .unwrap_or_else(|_| {
+                                hyper::Response::builder()
+                                    .status(hyper::StatusCode::SERVICE_UNAVAILABLE)
+                                    .body(Body::empty())
+                                    .unwrap()
+                            })
+                    }
+                    None => run.await,
+                }
+            }
+            RouteResult::NotFound => {
+                error_response(hyper::StatusCode::NOT_FOUND, &self.config.not_found)
+            }
+            RouteResult::MethodNotAllowed(mut allowed) => {
+                if !allowed.contains(&hyper::Method::OPTIONS) {
+                    allowed.push(hyper::Method::OPTIONS);
+                }
+                let allow = allowed
+                    .iter()
+                    .map(hyper::Method::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let allow = HeaderValue::from_str(&allow).ok();
+                if options {
+                    // Answered through the middleware, so it can handle
+                    // preflight requests such as CORS.
+                    let handler: Arc<dyn Handler> = Arc::new(move |_req: Request| {
+                        let allow = allow.clone();
+                        async move {
+                            let mut res = crate::Response::from(hyper::StatusCode::NO_CONTENT);
+                            if let Some(allow) = allow {
+                                res.headers_mut().insert(header::ALLOW, allow);
+                            }
+                            res
+                        }
+                    });
+                    let req = Request::new(req, HashMap::new());
+                    Next::new(self.middleware.clone(), handler)
+                        .run(req)
+                        .await
+                        .into()
+                } else {
+                    let mut res = error_response(
+                        hyper::StatusCode::METHOD_NOT_ALLOWED,
+                        &self.config.method_not_allowed,
+                    );
+                    if let Some(allow) = allow {
+                        res.headers_mut().insert(header::ALLOW, allow);
+                    }
+                    res
+                }
+            }
+        };
+        correct_content_length(&mut res);
+        if version < hyper::Version::HTTP_2 && self.shedding_load() {
+            res.headers_mut()
+                .insert(header::CONNECTION, HeaderValue::from_static("close"));
         }
+        let mut res = self.config.process(res);
+        if head {
+            strip_body(&mut res);
+        }
+        res
     }
 }
 
@@ -79,26 +1466,1371 @@ impl Service<http::Request> for Server {
     }
 
     fn call(&mut self, req: http::Request) -> Self::Future {
-        let router = self.router.clone();
-        let middleware = self.middleware.clone();
-        Box::pin(async move {
-            let res = match router.find(req.uri().path(), req.method().into()) {
-                RouteResult::Found(r) => {
-                    let (handler, params) = r;
-                    let req = Request::new(req, params);
-                    let next = Next::new(middleware, handler);
-                    next.run(req).await.into()
-                }
-                RouteResult::NotFound => hyper::Response::builder()
-                    .status(hyper::StatusCode::NOT_FOUND)
-                    .body(Body::empty())
-                    .unwrap(),
-                RouteResult::MethodNotAllowed => hyper::Response::builder()
-                    .status(hyper::StatusCode::METHOD_NOT_ALLOWED)
-                    .body(Body::empty())
-                    .unwrap(),
-            };
-            Ok(res)
-        })
+        let server = self.clone();
+        Box::pin(async move { Ok(server.handle_request(req).await) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, AtomicUsize};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::{Response, StreamBody, SyncHandler};
+
+    async fn connection(req: Request) -> Response {
+        let info = req.connection_info().unwrap();
+        assert_eq!(info.remote_addr, Some(([127, 0, 0, 1], 4000).into()));
+        assert_eq!(req.tls_server_name(), None);
+        assert_eq!(req.alpn_protocol(), None);
+        Response::default()
+    }
+
+    #[tokio::test]
+    async fn test_connection_info() {
+        let mut server = Server::new();
+        server.at("/").get(connection);
+        server.connection = Arc::new(ConnectionInfo {
+            remote_addr: Some(([127, 0, 0, 1], 4000).into()),
+            ..ConnectionInfo::default()
+        });
+
+        let req = hyper::Request::get("/").body(Body::empty()).unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+    }
+
+    async fn index(_req: Request) -> Response {
+        Response::default()
+    }
+
+    #[tokio::test]
+    async fn test_virtual_hosts() {
+        let mut api = Router::new();
+        api.at("/api").get(index);
+        let mut tenants = Router::new();
+        tenants.at("/tenant").get(index);
+
+        let mut server = Server::new();
+        server.at("/").get(index);
+        server.host("api.example.com", api);
+        server.host("*.example.com", tenants);
+
+        let cases = [
+            ("api.example.com", "/api", hyper::StatusCode::OK),
+            ("API.example.com:8080", "/api", hyper::StatusCode::OK),
+            ("api.example.com", "/tenant", hyper::StatusCode::NOT_FOUND),
+            ("acme.example.com", "/tenant", hyper::StatusCode::OK),
+            ("acme.example.com.:443", "/tenant", hyper::StatusCode::OK),
+            ("a.b.example.com", "/tenant", hyper::StatusCode::OK),
+            ("example.com", "/tenant", hyper::StatusCode::NOT_FOUND),
+            ("badexample.com", "/tenant", hyper::StatusCode::NOT_FOUND),
+            ("example.com", "/", hyper::StatusCode::OK),
+            ("[::1]:7000", "/", hyper::StatusCode::OK),
+        ];
+        for (host, path, status) in cases {
+            let req = hyper::Request::get(path)
+                .header(header::HOST, host)
+                .body(Body::empty())
+                .unwrap();
+            let res = server.call(req).await.unwrap();
+            assert_eq!(res.status(), status, "{} {}", host, path);
+        }
+
+        let req = hyper::Request::get("http://api.example.com./api")
+            .header(header::HOST, "other.test")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+    }
+
+    #[test]
+    #[should_panic(expected = "must start with `*.`")]
+    fn test_partial_wildcard_host() {
+        Server::new().host("*example.com", Router::new());
+    }
+
+    #[test]
+    fn test_default_content_type() {
+        let mut server = Server::new();
+        server.with_default_content_type("text/plain");
+        let config = &server.config;
+
+        let res = config.process(hyper::Response::new(Body::from("hello")));
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain");
+
+        let res = config.process(hyper::Response::new(Body::empty()));
+        assert!(!res.headers().contains_key(header::CONTENT_TYPE));
+
+        let res = hyper::Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        let res = config.process(res);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+    }
+
+    async fn blocked(req: Request) -> Response {
+        let notify = req.extensions().get::<Arc<tokio::sync::Notify>>().unwrap();
+        notify.notified().await;
+        Response::default()
+    }
+
+    fn blocked_request(notify: &Arc<tokio::sync::Notify>) -> http::Request {
+        let mut req = hyper::Request::get("/slow").body(Body::empty()).unwrap();
+        req.extensions_mut().insert(notify.clone());
+        req
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_fails_fast() {
+        let mut server = Server::new();
+        server.at("/slow").concurrency_limit(1).get(blocked);
+
+        let notify = Arc::new(tokio::sync::Notify::new());
+        let first = tokio::spawn(server.clone().call(blocked_request(&notify)));
+        tokio::task::yield_now().await;
+
+        let res = server.call(blocked_request(&notify)).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+
+        notify.notify_one();
+        let res = first.await.unwrap().unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let mut server = Server::new();
+        server
+            .with_shutdown_retry_after(Duration::from_secs(30))
+            .at("/slow")
+            .get(blocked);
+        let shutdown = server.shutdown_handle();
+
+        let notify = Arc::new(tokio::sync::Notify::new());
+        let running = tokio::spawn(server.clone().call(blocked_request(&notify)));
+        tokio::task::yield_now().await;
+        shutdown.shutdown();
+
+        let res = server.call(blocked_request(&notify)).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "30");
+        assert_eq!(res.headers()[header::CONNECTION], "close");
+
+        notify.notify_one();
+        let res = running.await.unwrap().unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+    }
+
+    async fn sleepy(_req: Request) -> Response {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Response::text("done")
+    }
+
+    #[tokio::test]
+    async fn test_bind() {
+        let mut server = Server::new();
+        server.at("/").get(text);
+        let bound = server.bind("127.0.0.1:0").await.unwrap();
+        let addr = bound.local_addr();
+        assert_ne!(addr.port(), 0);
+        let serving = tokio::spawn(bound.serve());
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).await.unwrap();
+        assert!(res.ends_with("\r\n\r\nhello"), "{}", res);
+        serving.abort();
+    }
+
+    #[tokio::test]
+    async fn test_listen_with_shutdown() {
+        let server = Server::new();
+        let res = server.listen_with_shutdown("127.0.0.1:0", async {}).await;
+        assert!(res.is_ok());
+
+        let mut server = Server::new();
+        server.at("/").get(text);
+        server.at("/slow").get(sleepy);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(listener, async {
+            stopped.await.ok();
+        }));
+
+        let mut idle = tokio::net::TcpStream::connect(addr).await.unwrap();
+        idle.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut buf = [0; 1024];
+        assert!(idle.read(&mut buf).await.unwrap() > 0);
+        let mut busy = tokio::net::TcpStream::connect(addr).await.unwrap();
+        busy.write_all(b"GET /slow HTTP/1.1\r\n\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        stop.send(()).unwrap();
+        let mut res = String::new();
+        busy.read_to_string(&mut res).await.unwrap();
+        assert!(res.ends_with("\r\n\r\ndone"), "{}", res);
+        assert_eq!(idle.read(&mut buf).await.unwrap(), 0);
+        let served = tokio::time::timeout(Duration::from_secs(5), serving).await;
+        assert!(served.unwrap().unwrap().is_ok());
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+
+    async fn hang(_req: Request) -> Response {
+        future::pending().await
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout() {
+        let mut server = Server::new();
+        server
+            .with_drain_timeout(Duration::from_millis(10))
+            .at("/hang")
+            .get(hang);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(listener, async {
+            stopped.await.ok();
+        }));
+
+        let mut stuck = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stuck
+            .write_all(b"GET /hang HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        stop.send(()).unwrap();
+        let served = tokio::time::timeout(Duration::from_secs(5), serving).await;
+        assert!(served.unwrap().unwrap().is_ok());
+        let mut rest = Vec::new();
+        stuck.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_shedding() {
+        let mut server = Server::new();
+        server.with_load_shedding(2).at("/").get(text);
+        let request = b"GET / HTTP/1.1\r\n\r\n";
+
+        let (mut first, stream) = tokio::io::duplex(4096);
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.serve_connection(stream).await }
+        });
+        first.write_all(request).await.unwrap();
+        let mut buf = [0; 1024];
+        let n = first.read(&mut buf).await.unwrap();
+        let res = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+        assert!(!res.contains("connection: close"), "{}", res);
+
+        let res = send(&server, "GET / HTTP/1.1\r\n\r\n").await;
+        assert!(res.to_lowercase().contains("connection: close"), "{}", res);
+
+        server.shutdown_handle().shutdown();
+        first.write_all(request).await.unwrap();
+        let mut res = String::new();
+        first.read_to_string(&mut res).await.unwrap();
+        assert!(res.starts_with("HTTP/1.1 503"), "{}", res);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_waits() {
+        let mut server = Server::new();
+        server
+            .at("/slow")
+            .concurrency_limit_with_timeout(1, std::time::Duration::from_secs(5))
+            .get(blocked);
+
+        let notify = Arc::new(tokio::sync::Notify::new());
+        let first = tokio::spawn(server.clone().call(blocked_request(&notify)));
+        tokio::task::yield_now().await;
+        let second = tokio::spawn(server.clone().call(blocked_request(&notify)));
+        tokio::task::yield_now().await;
+
+        notify.notify_one();
+        assert_eq!(
+            first.await.unwrap().unwrap().status(),
+            hyper::StatusCode::OK
+        );
+        notify.notify_one();
+        assert_eq!(
+            second.await.unwrap().unwrap().status(),
+            hyper::StatusCode::OK
+        );
+    }
+
+    async fn panics(_req: Request) -> Response {
+        panic!("something went wrong");
+    }
+
+    #[tokio::test]
+    async fn test_panic_response() {
+        let mut server = Server::new();
+        server.at("/").get(panics);
+
+        let req = hyper::Request::get("/").body(Body::empty()).unwrap();
+        let res = server.clone().call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        server.with_dev_mode(true);
+        let req = hyper::Request::get("/").body(Body::empty()).unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with("handler panicked: something went wrong"));
+    }
+
+    async fn fails(_req: Request) -> Response {
+        Response::from_error("database unavailable")
+    }
+
+    #[tokio::test]
+    async fn test_error_detail_in_dev_mode() {
+        let mut server = Server::new();
+        server.at("/").get(fails);
+
+        let res = send(&server, "GET / HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.starts_with("HTTP/1.1 500 Internal Server Error"));
+        assert!(res.ends_with("\r\n\r\nInternal Server Error"));
+
+        server.with_dev_mode(true);
+        let res = send(&server, "GET / HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.contains("content-length: 20\r\n"));
+        assert!(res.ends_with("\r\n\r\ndatabase unavailable"));
+    }
+
+    struct Resource(Arc<AtomicBool>);
+
+    impl Drop for Resource {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    async fn holds_resource(req: Request) -> Response {
+        let released = req.extensions().get::<Arc<AtomicBool>>();
+        let _resource = Resource(released.unwrap().clone());
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Response::default()
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_cancels_handler() {
+        let mut server = Server::new();
+        server.at("/").get(holds_resource);
+        server.with_request_timeout(Duration::from_millis(10));
+
+        let released = Arc::new(AtomicBool::new(false));
+        let mut req = hyper::Request::get("/").body(Body::empty()).unwrap();
+        req.extensions_mut().insert(released.clone());
+
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+        assert!(released.load(Ordering::SeqCst));
+    }
+
+    async fn send(server: &Server, request: &str) -> String {
+        let (mut client, stream) = tokio::io::duplex(4096);
+        let server = server.clone();
+        let conn = tokio::spawn(async move { server.serve_connection(stream).await });
+
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        conn.await.unwrap().unwrap();
+        response
+    }
+
+    async fn trailers(_req: Request) -> Response {
+        let (mut sender, mut res) = Response::channel();
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+        res.trailers(trailers);
+        tokio::spawn(async move { sender.send_data("hello".into()).await });
+        res
+    }
+
+    #[tokio::test]
+    async fn test_trailers_over_http2() {
+        let mut server = Server::new();
+        server.at("/").get(trailers);
+        let (client, stream) = tokio::io::duplex(1024);
+        tokio::spawn(async move { server.serve_connection(stream).await });
+
+        let (mut sender, conn) = hyper::client::conn::Builder::new()
+            .http2_only(true)
+            .handshake::<_, Body>(client)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let req = hyper::Request::get("http://localhost/")
+            .body(Body::empty())
+            .unwrap();
+        let mut body = sender.send_request(req).await.unwrap().into_body();
+        assert_eq!(&body.data().await.unwrap().unwrap()[..], b"hello");
+        assert!(body.data().await.is_none());
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["x-checksum"], "abc");
+    }
+
+    #[tokio::test]
+    async fn test_header_limits() {
+        let mut server = Server::new();
+        server.at("/").get(index);
+        server.with_max_headers(3).with_max_header_bytes(64);
+
+        let res = send(
+            &server,
+            "GET / HTTP/1.1\r\nhost: a\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(res.starts_with("HTTP/1.1 200 OK"));
+
+        let res = send(
+            &server,
+            "GET / HTTP/1.1\r\nhost: a\r\na: 1\r\nb: 2\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(res.starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+
+        let big = "x".repeat(64);
+        let req = format!(
+            "GET / HTTP/1.1\r\nhost: a\r\nx: {}\r\nconnection: close\r\n\r\n",
+            big
+        );
+        let res = send(&server, &req).await;
+        assert!(res.starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+    }
+
+    async fn text(_req: Request) -> Response {
+        Response::text("hello")
+    }
+
+    #[cfg(feature = "json")]
+    async fn create_user(mut req: Request) -> Response {
+        #[derive(serde::Deserialize)]
+        struct NewUser {
+            name: String,
+        }
+        match req.json::<NewUser>().await {
+            Ok(user) => Response::text(format!("created {}", user.name)),
+            Err(err) => Response::text(err.to_string()).status(hyper::StatusCode::BAD_REQUEST),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn test_chunked_json() {
+        let mut server = Server::new();
+        server.at("/users").post(create_user);
+
+        let res = send(
+            &server,
+            "POST /users HTTP/1.1\r\ncontent-type: application/json\r\n\
+             transfer-encoding: chunked\r\nconnection: close\r\n\r\n\
+             5\r\n{\"nam\r\n6\r\ne\":\"ad\r\n2\r\na\"\r\n1\r\n}\r\n0\r\n\r\n",
+        )
+        .await;
+        assert!(res.starts_with("HTTP/1.1 200 OK"), "{}", res);
+        assert!(res.ends_with("\r\n\r\ncreated ada"), "{}", res);
+    }
+
+    async fn stream(_req: Request) -> Response {
+        let (mut sender, res) = Response::channel();
+        tokio::spawn(async move { sender.send_data("hello".into()).await });
+        res
+    }
+
+    #[tokio::test]
+    async fn test_content_length_for_buffered_bodies() {
+        let mut server = Server::new();
+        server.at("/text").get(text);
+        server.at("/stream").get(stream);
+
+        let res = send(&server, "GET /text HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.contains("content-length: 5\r\n"));
+        assert!(!res.contains("transfer-encoding"));
+
+        let res = send(&server, "GET /stream HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.contains("transfer-encoding: chunked\r\n"));
+        assert!(!res.contains("content-length"));
+    }
+
+    async fn mislabeled(_req: Request) -> Response {
+        let mut res = Response::text("hello");
+        res.headers_mut()
+            .insert(header::CONTENT_LENGTH, "50".parse().unwrap());
+        res
+    }
+
+    async fn head(_req: Request) -> Response {
+        let mut res = Response::default();
+        res.headers_mut()
+            .insert(header::CONTENT_LENGTH, "50".parse().unwrap());
+        res
+    }
+
+    #[tokio::test]
+    async fn test_allow_header() {
+        let mut server = Server::new();
+        server.at("/users").get(text);
+        server.at("/users").post(text);
+        server.at("/custom").method(Method::OPTIONS, text);
+
+        let req = hyper::Request::delete("/users")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, POST, OPTIONS");
+
+        let req = hyper::Request::options("/users")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, POST, OPTIONS");
+
+        let req = hyper::Request::options("/custom")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        assert!(res.headers().get(header::ALLOW).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_head_uses_get_handler() {
+        let mut server = Server::new();
+        server.at("/").get(text);
+
+        let req = hyper::Request::head("/").body(Body::empty()).unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "5");
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        let res = send(&server, "HEAD / HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.contains("content-length: 5\r\n"), "{}", res);
+        assert!(res.ends_with("\r\n\r\n"), "{}", res);
+    }
+
+    #[tokio::test]
+    async fn test_corrects_content_length() {
+        let mut server = Server::new();
+        server.at("/").get(mislabeled);
+        server.at("/head").method(Method::HEAD, head);
+
+        let res = send(&server, "GET / HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.contains("content-length: 5\r\n"));
+        assert!(res.ends_with("\r\n\r\nhello"));
+
+        let res = send(&server, "HEAD /head HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.contains("content-length: 50\r\n"));
+    }
+
+    async fn paths(req: Request) -> Response {
+        Response::text(format!("{} {}", req.path(), req.original_path()))
+    }
+
+    #[tokio::test]
+    async fn test_rewriter() {
+        let mut server = Server::new();
+        server.at("/about").get(paths);
+        server.with_rewriter(|parts| {
+            if let Some(path) = parts.uri.path().strip_prefix("/en/") {
+                parts.uri = format!("/{}", path).parse().unwrap();
+            }
+        });
+
+        let res = send(
+            &server,
+            "GET /en/about HTTP/1.1\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(res.ends_with("\r\n\r\n/about /en/about"));
+        let res = send(&server, "GET /about HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.ends_with("\r\n\r\n/about /about"));
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slash_redirect() {
+        let mut server = Server::new();
+        server
+            .with_trailing_slash(TrailingSlash::RedirectToNoSlash)
+            .at("/foo")
+            .get(text);
+        server.at("/").get(text);
+
+        let req = hyper::Request::get("/foo/?page=2")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers()[header::LOCATION], "/foo?page=2");
+
+        for path in ["/foo", "/"] {
+            let req = hyper::Request::get(path).body(Body::empty()).unwrap();
+            let res = server.call(req).await.unwrap();
+            assert_eq!(res.status(), hyper::StatusCode::OK, "{}", path);
+        }
+
+        server.at("/{page}").get(text);
+        let req = hyper::Request::get("//evil.com/")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_ne!(res.status(), hyper::StatusCode::MOVED_PERMANENTLY);
+        assert!(res.headers().get(header::LOCATION).is_none());
+    }
+
+    #[test]
+    fn test_accept_errors() {
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        assert!(is_connection_error(&reset));
+        assert!(!is_fatal_accept_error(&reset));
+
+        let too_many_files = io::Error::from_raw_os_error(24);
+        assert!(!is_connection_error(&too_many_files));
+        assert!(!is_fatal_accept_error(&too_many_files));
+
+        let not_listening = io::Error::from(io::ErrorKind::InvalidInput);
+        assert!(is_fatal_accept_error(&not_listening));
+    }
+
+    #[tokio::test]
+    async fn test_listen_available() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let ports = [port, 0];
+
+        let mut server = Server::new();
+        server.at("/").get(text);
+        let (addr, task) = server.listen_available("127.0.0.1", ports).await.unwrap();
+        assert_ne!(addr.port(), port);
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.ends_with("hello"));
+        task.abort();
+
+        let result = Server::new().listen_available("127.0.0.1", [port]).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_max_connections() {
+        let mut server = Server::new();
+        server.with_max_connections(1).at("/").get(text);
+        let (addr, task) = server.listen_available("127.0.0.1", [0]).await.unwrap();
+
+        let request = b"GET / HTTP/1.1\r\n\r\n";
+        let mut first = tokio::net::TcpStream::connect(addr).await.unwrap();
+        first.write_all(request).await.unwrap();
+        let mut buf = [0; 1024];
+        assert!(first.read(&mut buf).await.unwrap() > 0);
+
+        let mut second = tokio::net::TcpStream::connect(addr).await.unwrap();
+        second.write_all(request).await.unwrap();
+        let waiting = tokio::time::timeout(Duration::from_millis(100), second.read(&mut buf)).await;
+        assert!(waiting.is_err());
+
+        drop(first);
+        let read = tokio::time::timeout(Duration::from_secs(5), second.read(&mut buf)).await;
+        assert!(read.unwrap().unwrap() > 0);
+        task.abort();
+    }
+
+    static FLOOD_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+    static FLOOD_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+    async fn counted(_req: Request) -> Response {
+        let in_flight = FLOOD_IN_FLIGHT.fetch_add(1, Ordering::SeqCst) + 1;
+        FLOOD_PEAK.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        FLOOD_IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+        Response::text("hello")
+    }
+
+    #[tokio::test]
+    async fn test_max_connections_under_flood() {
+        let mut server = Server::new();
+        server.with_max_connections(3).at("/").get(counted);
+        let (addr, task) = server.listen_available("127.0.0.1", [0]).await.unwrap();
+
+        let clients = (0..20).map(|_| {
+            tokio::spawn(async move {
+                let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+                client
+                    .write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+                let mut res = String::new();
+                client.read_to_string(&mut res).await.unwrap();
+                res
+            })
+        });
+        for res in futures::future::join_all(clients).await {
+            assert!(res.unwrap().ends_with("hello"));
+        }
+        assert_eq!(FLOOD_PEAK.load(Ordering::SeqCst), 3);
+        task.abort();
+    }
+
+    async fn reject(_req: Request) -> Response {
+        hyper::StatusCode::PAYLOAD_TOO_LARGE.into()
+    }
+
+    /// Starts a slow chunked upload that the handler rejects without reading,
+    /// returning the connection once the rejection arrives.
+    async fn rejected_upload(server: Server) -> tokio::io::DuplexStream {
+        let (mut client, stream) = tokio::io::duplex(4096);
+        tokio::spawn(async move { server.serve_connection(stream).await });
+        client
+            .write_all(b"POST /upload HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 1024];
+        let read = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buf)).await;
+        let res = String::from_utf8_lossy(&buf[..read.unwrap().unwrap()]).into_owned();
+        assert!(res.starts_with("HTTP/1.1 413 Payload Too Large"));
+        client
+    }
+
+    #[tokio::test]
+    async fn test_unread_body() {
+        let mut server = Server::new();
+        server.at("/upload").post(reject);
+        server.at("/").get(text);
+
+        let mut client = rejected_upload(server.clone()).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        client
+            .write_all(b"5\r\nworld\r\n0\r\n\r\nGET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut res = String::new();
+        client.read_to_string(&mut res).await.unwrap();
+        assert!(res.ends_with("\r\n\r\nhello"));
+
+        server.with_unread_body(UnreadBody::Close);
+        let mut client = rejected_upload(server).await;
+        let mut rest = Vec::new();
+        let read =
+            tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut rest)).await;
+        assert!(read.unwrap().is_ok());
+        assert!(rest.is_empty());
+    }
+
+    async fn upload(mut req: Request) -> std::result::Result<Response, crate::BodyError> {
+        let body = req.body_bytes().await?;
+        Ok(Response::text(format!("received {} bytes", body.len())))
+    }
+
+    #[tokio::test]
+    async fn test_max_body_size() {
+        let mut server = Server::new();
+        server.with_max_body_size(8).at("/upload").post(upload);
+
+        let res = send(
+            &server,
+            "POST /upload HTTP/1.1\r\ncontent-length: 5\r\nconnection: close\r\n\r\nhello",
+        )
+        .await;
+        assert!(res.ends_with("received 5 bytes"), "{}", res);
+
+        let res = send(
+            &server,
+            "POST /upload HTTP/1.1\r\ncontent-length: 10\r\nconnection: close\r\n\r\nhelloworld",
+        )
+        .await;
+        assert!(res.starts_with("HTTP/1.1 413 Payload Too Large"), "{}", res);
+        assert!(res.ends_with("Request body exceeds 8 bytes"));
+
+        let res = send(
+            &server,
+            "POST /upload HTTP/1.1\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n5\r\nhello\r\n5\r\nworld\r\n0\r\n\r\n",
+        )
+        .await;
+        assert!(res.starts_with("HTTP/1.1 413 Payload Too Large"), "{}", res);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_transfer_encoding_with_content_length() {
+        let mut server = Server::new();
+        server.at("/").post(text);
+
+        let res = send(
+            &server,
+            "POST / HTTP/1.1\r\ncontent-length: 4\r\ntransfer-encoding: chunked\r\n\r\n0\r\n\r\n",
+        )
+        .await;
+        assert!(res.starts_with("HTTP/1.1 400 Bad Request"));
+        assert!(res.contains("connection: close\r\n"));
+
+        // hyper drops a Content-Length that follows Transfer-Encoding while
+        // parsing, so it cannot be rejected; the handler only ever sees the
+        // request framed as chunked
+        server.at("/length").post(|req: Request| async move {
+            Response::text(format!("{:?}", req.headers().get(header::CONTENT_LENGTH)))
+        });
+        let res = send(
+            &server,
+            "POST /length HTTP/1.1\r\ntransfer-encoding: chunked\r\ncontent-length: 4\r\nconnection: close\r\n\r\n0\r\n\r\n",
+        )
+        .await;
+        assert!(res.ends_with("None"), "{}", res);
+    }
+
+    static POOLS_CREATED: AtomicUsize = AtomicUsize::new(0);
+
+    /// A resource that is not [Clone] and counts its constructions.
+    struct Pool {
+        queries: AtomicUsize,
+    }
+
+    async fn connect() -> Pool {
+        POOLS_CREATED.fetch_add(1, Ordering::SeqCst);
+        Pool {
+            queries: AtomicUsize::new(0),
+        }
+    }
+
+    async fn query(req: Request) -> Response {
+        let pool = req.state::<Pool>().unwrap();
+        let queries = pool.queries.fetch_add(1, Ordering::SeqCst) + 1;
+        Response::text(queries.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_state_shared_across_connections() {
+        let mut server = Server::new();
+        server.at("/").get(query);
+        server.with_state_async(connect()).await;
+
+        let req = "GET / HTTP/1.1\r\nconnection: close\r\n\r\n";
+        assert!(send(&server, req).await.ends_with("\r\n\r\n1"));
+        assert!(send(&server, req).await.ends_with("\r\n\r\n2"));
+        assert_eq!(POOLS_CREATED.load(Ordering::SeqCst), 1);
+    }
+
+    async fn post(req: Request) -> Response {
+        let mut params: Vec<_> = req.params().iter().collect();
+        params.sort();
+        Response::text(format!("{} {:?}", req.params_len(), params))
+    }
+
+    #[tokio::test]
+    async fn test_params() {
+        let mut server = Server::new();
+        server.at("/users/{id}/posts/{pid}").get(post);
+
+        let req = hyper::Request::get("/users/7/posts/42")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], br#"2 [("id", "7"), ("pid", "42")]"#);
+    }
+
+    struct Counter(AtomicUsize);
+
+    struct Greeting(&'static str);
+
+    async fn count(req: Request) -> Response {
+        assert!(req.state::<Pool>().is_none());
+        let counter = req.state::<Counter>().unwrap();
+        let count = counter.0.fetch_add(1, Ordering::SeqCst) + 1;
+        Response::text(format!("{} {}", req.state::<Greeting>().unwrap().0, count))
+    }
+
+    #[tokio::test]
+    async fn test_state_types() {
+        let mut server = Server::new();
+        server
+            .with_state(Counter(AtomicUsize::new(0)))
+            .with_state(Greeting("hits"))
+            .at("/")
+            .get(count);
+
+        for expected in ["hits 1", "hits 2"] {
+            let req = hyper::Request::get("/").body(Body::empty()).unwrap();
+            let res = server.call(req).await.unwrap();
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(&body[..], expected.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_request() {
+        let mut server = Server::new();
+        server.at("/users/{id}").get(SyncHandler(greet_user));
+
+        let req = hyper::Request::get("/users/7").body(Body::empty()).unwrap();
+        let res = server.handle_request(req).await;
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"user 7");
+
+        let req = hyper::Request::get("/missing").body(Body::empty()).unwrap();
+        let res = server.handle_request(req).await;
+        assert_eq!(res.status(), hyper::StatusCode::NOT_FOUND);
+
+        let req = hyper::Request::delete("/users/7")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.handle_request(req).await;
+        assert_eq!(res.status(), hyper::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+    }
+
+    fn greet_user(req: Request) -> String {
+        format!("user {}", req.param("id").unwrap())
+    }
+
+    fn greet(req: Request) -> String {
+        format!("hello {}", req.param("name").unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_sync_handler() {
+        let mut server = Server::new();
+        server.at("/greet/{name}").get(SyncHandler(greet));
+        server
+            .at("/status")
+            .get(SyncHandler(|_req| hyper::StatusCode::ACCEPTED));
+
+        let req = hyper::Request::get("/greet/ada")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello ada");
+
+        let req = hyper::Request::get("/status").body(Body::empty()).unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::ACCEPTED);
+    }
+
+    struct Visits(AtomicU64);
+
+    async fn visit(_req: Request, visits: Arc<Visits>) -> Response {
+        let count = visits.0.fetch_add(1, Ordering::SeqCst) + 1;
+        Response::text(count.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_route_with_state() {
+        let mut server = Server::new();
+        server.with_state(Visits(AtomicU64::new(0)));
+        server.route_with_state(Method::GET, "/visits", visit);
+        server.route_with_state(Method::GET, "/missing", |_req, _: Arc<Greeting>| async {
+            Response::default()
+        });
+
+        for expected in ["1", "2"] {
+            let req = hyper::Request::get("/visits").body(Body::empty()).unwrap();
+            let res = server.call(req).await.unwrap();
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(&body[..], expected.as_bytes());
+        }
+        let visits = server.state.get::<Visits>().unwrap();
+        assert_eq!(visits.0.load(Ordering::SeqCst), 2);
+
+        let req = hyper::Request::get("/missing").body(Body::empty()).unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    struct Slow;
+
+    #[async_trait::async_trait]
+    impl Middleware for Slow {
+        async fn handle(&self, req: Request, next: Next) -> Response {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            next.run(req).await
+        }
+
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+    }
+
+    struct Passthrough;
+
+    #[async_trait::async_trait]
+    impl Middleware for Passthrough {
+        async fn handle(&self, req: Request, next: Next) -> Response {
+            next.run(req).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_profiler() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut server = Server::new();
+        server.mount(Passthrough);
+        server.mount(Slow);
+        server.at("/").get(text);
+        server.with_profiler(move |profile| tx.send(profile.clone()).unwrap());
+
+        send(&server, "GET / HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        let profile = rx.recv().unwrap();
+        assert_eq!(profile.path, "/");
+        let names = profile
+            .layers
+            .iter()
+            .map(|layer| layer.name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![std::any::type_name::<Passthrough>(), "slow", "handler"]
+        );
+
+        let (outer, slow) = (&profile.layers[0], &profile.layers[1]);
+        assert!(slow.own >= Duration::from_millis(30));
+        assert!(outer.total >= slow.total);
+        assert!(outer.own < Duration::from_millis(30));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_task_monitors() {
+        let mut server = Server::new();
+        server.at("/users/{id}").get(index);
+        let monitors = server.with_task_monitors();
+
+        for path in ["/users/1", "/users/2"] {
+            let req = hyper::Request::get(path).body(Body::empty()).unwrap();
+            server.clone().call(req).await.unwrap();
+        }
+        let monitor = monitors.route("/users/{id}").unwrap();
+        assert_eq!(monitor.cumulative().dropped_count, 2);
+        assert_eq!(monitors.routes().len(), 1);
+        assert!(monitors.route("/missing").is_none());
+    }
+
+    struct Named(&'static str);
+
+    #[async_trait::async_trait]
+    impl Middleware for Named {
+        async fn handle(&self, req: Request, next: Next) -> Response {
+            next.run(req).await
+        }
+
+        fn name(&self) -> &'static str {
+            self.0
+        }
+    }
+
+    struct Trace(&'static str);
+
+    #[async_trait::async_trait]
+    impl Middleware for Trace {
+        async fn handle(&self, req: Request, next: Next) -> Response {
+            let mut res = next.run(req).await;
+            res.headers_mut()
+                .append("x-trace", HeaderValue::from_static(self.0));
+            res
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route_middleware() {
+        let mut server = Server::new();
+        server.mount(Trace("global"));
+        server
+            .at("/admin")
+            .with(Trace("auth"))
+            .with(Trace("audit"))
+            .get(text);
+        server.at("/public").get(text);
+
+        let trace = |res: http::Response| {
+            res.headers()
+                .get_all("x-trace")
+                .iter()
+                .map(|value| String::from(value.to_str().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let req = hyper::Request::get("/admin").body(Body::empty()).unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(trace(res), ["audit", "auth", "global"]);
+        let req = hyper::Request::get("/public").body(Body::empty()).unwrap();
+        let res = server.call(req).await.unwrap();
+        assert_eq!(trace(res), ["global"]);
+    }
+
+    #[tokio::test]
+    async fn test_router_middleware() {
+        let mut admin = Router::new();
+        admin.mount(Trace("auth"));
+        admin.at("/settings").with(Trace("route")).get(text);
+        let mut site = Router::new();
+        site.mount(Trace("site"));
+        site.at("/home").get(text);
+        site.route("/admin", admin);
+
+        let mut server = Server::new();
+        server.mount(Trace("global"));
+        server.at("/health").get(text);
+        server.route("/site", site);
+
+        let cases: [(&str, &[&str]); 3] = [
+            ("/site/admin/settings", &["route", "auth", "site", "global"]),
+            ("/site/home", &["site", "global"]),
+            ("/health", &["global"]),
+        ];
+        for (path, expected) in cases {
+            let req = hyper::Request::get(path).body(Body::empty()).unwrap();
+            let res = server.call(req).await.unwrap();
+            let trace = res
+                .headers()
+                .get_all("x-trace")
+                .iter()
+                .map(|value| value.to_str().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(trace, expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_mount_with_priority() {
+        let mut server = Server::new();
+        server.mount(Named("auth"));
+        server.mount_with_priority(Named("compression"), 100);
+        server.mount_with_priority(Named("cors"), -10);
+        server.mount(Named("session"));
+        server.mount_with_priority(Named("log"), -100);
+        let names = server
+            .middleware
+            .iter()
+            .map(|mid| mid.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["log", "cors", "auth", "session", "compression"]);
+    }
+
+    #[tokio::test]
+    async fn test_error_bodies() {
+        let mut server = Server::new();
+        server.at("/").get(text);
+        let res = send(
+            &server,
+            "GET /missing HTTP/1.1\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(res.contains("content-length: 0\r\n"));
+
+        server
+            .not_found_body(r#"{"error":"not_found"}"#, "application/json")
+            .method_not_allowed_body("not allowed", "text/plain");
+        let res = send(
+            &server,
+            "GET /missing HTTP/1.1\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(res.starts_with("HTTP/1.1 404 Not Found"));
+        assert!(res.contains("content-type: application/json\r\n"));
+        assert!(res.ends_with("\r\n\r\n{\"error\":\"not_found\"}"));
+
+        let res = send(&server, "POST / HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.starts_with("HTTP/1.1 405 Method Not Allowed"));
+        assert!(res.ends_with("\r\n\r\nnot allowed"));
+    }
+
+    #[tokio::test]
+    async fn test_accepts() {
+        let mut server = Server::new();
+        server
+            .at("/users")
+            .accepts("application/json")
+            .accepts("text/*")
+            .post(index);
+
+        let cases = [
+            (
+                Some("application/json; charset=utf-8"),
+                "{}",
+                hyper::StatusCode::OK,
+            ),
+            (Some("Text/Plain"), "hi", hyper::StatusCode::OK),
+            (
+                Some("application/xml"),
+                "<a/>",
+                hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ),
+            (None, "{}", hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            (None, "", hyper::StatusCode::OK),
+        ];
+        for (content_type, body, status) in cases {
+            let mut req = hyper::Request::post("/users").header(header::CONTENT_LENGTH, body.len());
+            if let Some(content_type) = content_type {
+                req = req.header(header::CONTENT_TYPE, content_type);
+            }
+            let res = server
+                .call(req.body(Body::from(body)).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), status, "{:?}", content_type);
+        }
+    }
+
+    async fn greeting(_req: Request) -> &'static str {
+        "hello"
+    }
+
+    async fn chunks(
+        _req: Request,
+    ) -> StreamBody<impl futures::Stream<Item = std::io::Result<&'static str>>> {
+        let chunks = ["id,name\n", "1,ada\n"].map(Ok);
+        StreamBody::new(futures::stream::iter(chunks)).content_type("text/csv")
+    }
+
+    async fn created(req: Request) -> (hyper::StatusCode, String) {
+        (
+            hyper::StatusCode::CREATED,
+            format!("created {}", req.path()),
+        )
+    }
+
+    async fn nothing(_req: Request) {}
+
+    async fn fallible(req: Request) -> crate::Result<&'static str> {
+        let page: u32 = req.query_param("page").unwrap_or_default().parse()?;
+        Ok(if page > 1 { "more" } else { "first" })
+    }
+
+    async fn item(req: Request) -> crate::Result<String> {
+        let id: u32 = req.param_parse("id")?;
+        Ok(format!("item {}", id))
+    }
+
+    async fn conflict(_req: Request) -> std::result::Result<String, hyper::StatusCode> {
+        Err(hyper::StatusCode::CONFLICT)
+    }
+
+    #[tokio::test]
+    async fn test_into_response_handlers() {
+        let mut server = Server::new();
+        server.at("/greeting").get(greeting);
+        server.at("/users").post(created);
+        server.at("/nothing").get(nothing);
+        server.at("/export").get(chunks);
+        server
+            .at("/piped")
+            .get((|req: Request| async move { req }, created));
+
+        let cases = [
+            (Method::GET, "/greeting", hyper::StatusCode::OK, "hello"),
+            (
+                Method::POST,
+                "/users",
+                hyper::StatusCode::CREATED,
+                "created /users",
+            ),
+            (Method::GET, "/nothing", hyper::StatusCode::OK, ""),
+            (
+                Method::GET,
+                "/export",
+                hyper::StatusCode::OK,
+                "id,name\n1,ada\n",
+            ),
+            (
+                Method::GET,
+                "/piped",
+                hyper::StatusCode::CREATED,
+                "created /piped",
+            ),
+        ];
+        for (method, path, status, body) in cases {
+            let req = hyper::Request::builder()
+                .method(method)
+                .uri(path)
+                .body(Body::empty())
+                .unwrap();
+            let res = server.call(req).await.unwrap();
+            assert_eq!(res.status(), status, "{}", path);
+            if path == "/export" {
+                assert_eq!(res.headers()[header::CONTENT_TYPE], "text/csv");
+            }
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(&bytes[..], body.as_bytes(), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_result_handlers() {
+        let mut server = Server::new();
+        server.at("/items").get(fallible);
+        server.at("/items/{id}").get(item);
+        server.at("/conflict").get(conflict);
+
+        let cases = [
+            ("/items?page=2", hyper::StatusCode::OK, "more"),
+            (
+                "/items?page=two",
+                hyper::StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal Server Error",
+            ),
+            ("/items/7", hyper::StatusCode::OK, "item 7"),
+            (
+                "/items/seven",
+                hyper::StatusCode::BAD_REQUEST,
+                "Invalid path param id: invalid digit found in string",
+            ),
+            ("/conflict", hyper::StatusCode::CONFLICT, ""),
+        ];
+        for (path, status, body) in cases {
+            let req = hyper::Request::get(path).body(Body::empty()).unwrap();
+            let res = server.call(req).await.unwrap();
+            assert_eq!(res.status(), status, "{}", path);
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(&bytes[..], body.as_bytes(), "{}", path);
+        }
+
+        server.with_dev_mode(true);
+        let req = hyper::Request::get("/items?page=two")
+            .body(Body::empty())
+            .unwrap();
+        let res = server.call(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&bytes[..], b"invalid digit found in string");
+    }
+
+    #[tokio::test]
+    async fn test_serve_connection() {
+        let mut server = Server::new();
+        server.at("/foo").get(index);
+
+        let res = send(&server, "GET /foo HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+
+        let res = send(&server, "GET /bar HTTP/1.1\r\nconnection: close\r\n\r\n").await;
+        assert!(res.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", res);
     }
 }
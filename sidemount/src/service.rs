@@ -0,0 +1,91 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{http, Server};
+
+/// The app as a [tower::Service], so tower middleware can be layered around
+/// the routing and middleware of sidemount or the app can be embedded in
+/// another tower-based server, see [Server::into_service].
+///
+/// The service is always ready and never fails: errors are answered with a
+/// response like on a connection, see [Server::handle_request].
+///
+/// ## Examples
+/// ```rust
+/// use hyper::Body;
+/// use sidemount::{Request, Response};
+/// use tower::ServiceExt;
+///
+/// async fn index(req: Request) -> Response {
+///     Response::text("hello")
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mut app = sidemount::new();
+/// app.at("/").get(index);
+///
+/// let req = hyper::Request::get("/").body(Body::empty()).unwrap();
+/// let res = app.into_service().oneshot(req).await.unwrap();
+/// assert!(res.status().is_success());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SidemountService {
+    server: Server,
+}
+
+impl Server {
+    /// Converts the app into a [tower::Service], see [SidemountService].
+    pub fn into_service(self) -> SidemountService {
+        SidemountService { server: self }
+    }
+}
+
+impl tower::Service<http::Request> for SidemountService {
+    type Response = http::Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request) -> Self::Future {
+        let server = self.server.clone();
+        Box::pin(async move { Ok(server.handle_request(req).await) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Body, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::{Request, Response};
+
+    async fn user(req: Request) -> Response {
+        Response::text(format!("user {}", req.param("id").unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_oneshot() {
+        let mut app = crate::new();
+        app.at("/users/{id}").get(user);
+        let service = app.into_service();
+
+        let req = hyper::Request::get("/users/7").body(Body::empty()).unwrap();
+        let res = service.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"user 7");
+
+        let req = hyper::Request::get("/missing").body(Body::empty()).unwrap();
+        let res = service.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
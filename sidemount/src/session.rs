@@ -0,0 +1,464 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use cookie::{Cookie, CookieJar, Key, SameSite};
+use hyper::header::{self, HeaderValue};
+use rand::RngCore;
+
+use crate::{Middleware, Next, Request, Response, Result};
+
+/// The key/value data stored in a session.
+pub type SessionData = HashMap<String, String>;
+
+/// A backing store used by [SessionMiddleware] to load and save sessions.
+///
+/// The store is handed the raw value of the session cookie and returns the
+/// value to send back to the client, which lets a store keep data server-side
+/// behind an id (see [MemoryStore]) or in the cookie itself (see [CookieStore]).
+#[async_trait]
+pub trait SessionStore: Send + Sync + 'static {
+    /// Loads the session data associated with the cookie value, if any.
+    async fn load(&self, cookie: &str) -> Result<Option<SessionData>>;
+
+    /// Saves the session data, returning the new cookie value. The existing
+    /// cookie value is given when the session was previously loaded.
+    async fn save(&self, cookie: Option<&str>, data: &SessionData) -> Result<String>;
+
+    /// Destroys the session associated with the cookie value.
+    async fn destroy(&self, cookie: &str) -> Result<()>;
+}
+
+/// An in-memory [SessionStore] keyed by random session ids.
+///
+/// Sessions are lost when the process restarts and are not shared across
+/// processes, so this is best suited to development and single-instance apps.
+/// A session expires once it has not been loaded or saved for its time to
+/// live, and expired sessions are purged whenever a session is saved.
+pub struct MemoryStore {
+    ttl: Duration,
+    sessions: Mutex<HashMap<String, (SessionData, Instant)>>,
+}
+
+/// How long an unused session is kept by default, see [MemoryStore::ttl].
+const SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore {
+            ttl: SESSION_TTL,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl MemoryStore {
+    /// Creates a new empty in-memory store keeping unused sessions for 24
+    /// hours.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Sets how long a session is kept since it was last loaded or saved.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+/// Generates a random 256-bit session id encoded as hex.
+fn generate_id() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn load(&self, cookie: &str) -> Result<Option<SessionData>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get_mut(cookie) {
+            Some((data, used)) if used.elapsed() < self.ttl => {
+                *used = Instant::now();
+                Ok(Some(data.clone()))
+            }
+            Some(_) => {
+                sessions.remove(cookie);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, cookie: Option<&str>, data: &SessionData) -> Result<String> {
+        let id = cookie.map(String::from).unwrap_or_else(generate_id);
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, (_, used)| used.elapsed() < self.ttl);
+        sessions.insert(id.clone(), (data.clone(), Instant::now()));
+        Ok(id)
+    }
+
+    async fn destroy(&self, cookie: &str) -> Result<()> {
+        self.sessions.lock().unwrap().remove(cookie);
+        Ok(())
+    }
+}
+
+/// A [SessionStore] that keeps the session data in the cookie itself, signed
+/// with an HMAC key so clients cannot tamper with it.
+///
+/// The data is readable by the client and cookies are limited to around 4KB,
+/// so only store small, non-secret values this way.
+pub struct CookieStore {
+    key: Key,
+}
+
+/// Name the session data is signed under, independent of the cookie name.
+const SIGNED_NAME: &str = "sidemount.session";
+
+impl CookieStore {
+    /// Creates a new signed cookie store from a key of at least 64 bytes.
+    ///
+    /// ## Panics
+    /// Panics if the key is shorter than 64 bytes.
+    pub fn new(key: &[u8]) -> Self {
+        CookieStore {
+            key: Key::from(key),
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for CookieStore {
+    async fn load(&self, cookie: &str) -> Result<Option<SessionData>> {
+        let mut jar = CookieJar::new();
+        jar.add_original(Cookie::new(SIGNED_NAME, cookie.to_owned()));
+        Ok(jar.signed(&self.key).get(SIGNED_NAME).map(|cookie| {
+            form_urlencoded::parse(cookie.value().as_bytes())
+                .into_owned()
+                .collect()
+        }))
+    }
+
+    async fn save(&self, _cookie: Option<&str>, data: &SessionData) -> Result<String> {
+        let value = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(data)
+            .finish();
+        let mut jar = CookieJar::new();
+        jar.signed_mut(&self.key)
+            .add(Cookie::new(SIGNED_NAME, value));
+        Ok(jar.get(SIGNED_NAME).unwrap().value().to_owned())
+    }
+
+    async fn destroy(&self, _cookie: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SessionState {
+    data: SessionData,
+    modified: bool,
+    destroyed: bool,
+    regenerated: bool,
+}
+
+/// A handle to the session of the current request.
+///
+/// The handle is inserted into the request extensions by [SessionMiddleware]
+/// and any changes made through it are saved once the handler returns.
+#[derive(Clone, Default)]
+pub struct Session {
+    state: Arc<Mutex<SessionState>>,
+}
+
+impl Session {
+    fn new(data: SessionData) -> Self {
+        Session {
+            state: Arc::new(Mutex::new(SessionState {
+                data,
+                ..SessionState::default()
+            })),
+        }
+    }
+
+    /// Gets the value stored under the key.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.state.lock().unwrap().data.get(key).cloned()
+    }
+
+    /// Stores the value under the key.
+    pub fn set(&self, key: impl Into<String>, value: impl Into<String>) {
+        let mut state = self.state.lock().unwrap();
+        state.data.insert(key.into(), value.into());
+        state.modified = true;
+    }
+
+    /// Removes the value stored under the key, returning it if present.
+    pub fn remove(&self, key: &str) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let value = state.data.remove(key);
+        state.modified |= value.is_some();
+        value
+    }
+
+    /// Saves the session under a new id once the handler returns, destroying
+    /// the old one. Call this when the privileges of the session change, such
+    /// as on login, so an id planted in the browser beforehand, or seen by
+    /// anyone else, does not carry over.
+    pub fn regenerate(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.regenerated = true;
+        state.modified = true;
+    }
+
+    /// Removes all session data and expires the session cookie.
+    pub fn destroy(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.data.clear();
+        state.destroyed = true;
+    }
+}
+
+/// Middleware that loads the session from a cookie, exposes it to handlers as
+/// a [Session] in the request extensions, and saves it when modified.
+///
+/// ## Examples
+/// ```rust
+/// use sidemount::session::{MemoryStore, Session, SessionMiddleware};
+/// use sidemount::{Request, Response};
+///
+/// async fn login(req: Request) -> Response {
+///     if let Some(session) = req.extensions().get::<Session>() {
+///         session.regenerate();
+///         session.set("user", "42");
+///     }
+///     Response::default()
+/// }
+///
+/// let mut app = sidemount::new();
+/// app.mount(SessionMiddleware::new(MemoryStore::new()));
+/// app.at("/login").post(login);
+/// ```
+pub struct SessionMiddleware {
+    store: Box<dyn SessionStore>,
+    cookie_name: String,
+    secure: bool,
+}
+
+impl SessionMiddleware {
+    /// Creates new session middleware backed by the given store.
+    pub fn new(store: impl SessionStore) -> Self {
+        SessionMiddleware {
+            store: Box::new(store),
+            cookie_name: String::from("sid"),
+            secure: false,
+        }
+    }
+
+    /// Sets the name of the session cookie, defaults to `sid`.
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    /// Sets whether the session cookie is only sent over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    fn cookie(&self, value: String) -> Cookie<'static> {
+        Cookie::build((self.cookie_name.clone(), value))
+            .path("/")
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(SameSite::Lax)
+            .build()
+    }
+}
+
+#[async_trait]
+impl Middleware for SessionMiddleware {
+    async fn handle(&self, mut req: Request, next: Next) -> Response {
+        let mut existing = req.cookies().get(&self.cookie_name).cloned();
+        let data = match &existing {
+            Some(cookie) => self.store.load(cookie).await.unwrap_or_else(|err| {
+                log::error!("Failed to load session: {:?}", err);
+                None
+            }),
+            None => None,
+        };
+        if data.is_none() {
+            existing = None;
+        }
+
+        let session = Session::new(data.unwrap_or_default());
+        req.extensions_mut().insert(session.clone());
+        let mut res = next.run(req).await;
+
+        let state = std::mem::take(&mut *session.state.lock().unwrap());
+        if state.destroyed || state.regenerated {
+            if let Some(cookie) = existing.take() {
+                if let Err(err) = self.store.destroy(&cookie).await {
+                    log::error!("Failed to destroy session: {:?}", err);
+                }
+            }
+        }
+        let cookie = if state.destroyed {
+            let mut cookie = self.cookie(String::new());
+            cookie.make_removal();
+            Some(cookie)
+        } else if state.modified {
+            match self.store.save(existing.as_deref(), &state.data).await {
+                Ok(value) => Some(self.cookie(value)),
+                Err(err) => {
+                    log::error!("Failed to save session: {:?}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(cookie) = cookie {
+            if let Ok(value) = HeaderValue::from_str(&cookie.encoded().to_string()) {
+                res.headers_mut().append(header::SET_COOKIE, value);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn login(req: Request) -> Response {
+        let session = req.extensions().get::<Session>().unwrap();
+        assert_eq!(session.get("user"), None);
+        session.set("user", "42");
+        Response::default()
+    }
+
+    async fn profile(req: Request) -> Response {
+        let session = req.extensions().get::<Session>().unwrap();
+        assert_eq!(session.get("user").as_deref(), Some("42"));
+        Response::default()
+    }
+
+    async fn run(
+        middleware: &Arc<Vec<Arc<dyn Middleware>>>,
+        handler: Arc<dyn crate::Handler>,
+        cookie: Option<&str>,
+    ) -> Response {
+        let mut req = hyper::Request::builder();
+        if let Some(cookie) = cookie {
+            req = req.header(header::COOKIE, cookie);
+        }
+        let req = Request::new(req.body(hyper::Body::empty()).unwrap(), HashMap::new());
+        Next::new(middleware.clone(), handler).run(req).await
+    }
+
+    async fn round_trip(store: impl SessionStore) {
+        let middleware: Arc<Vec<Arc<dyn Middleware>>> =
+            Arc::new(vec![Arc::new(SessionMiddleware::new(store))]);
+
+        let res = run(&middleware, Arc::new(login), None).await;
+        let set_cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        let cookie = Cookie::parse(set_cookie.to_owned()).unwrap();
+        assert_eq!(cookie.name(), "sid");
+        assert_eq!(cookie.http_only(), Some(true));
+
+        let cookie = format!("sid={}", cookie.value());
+        let res = run(&middleware, Arc::new(profile), Some(&cookie)).await;
+        assert!(!res.headers().contains_key(header::SET_COOKIE));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        round_trip(MemoryStore::new()).await;
+    }
+
+    async fn elevate(req: Request) -> Response {
+        let session = req.extensions().get::<Session>().unwrap();
+        session.regenerate();
+        session.set("role", "admin");
+        Response::default()
+    }
+
+    async fn whoami(req: Request) -> Response {
+        let session = req.extensions().get::<Session>().unwrap();
+        Response::text(format!(
+            "{:?} {:?}",
+            session.get("user"),
+            session.get("role")
+        ))
+    }
+
+    fn session_cookie(res: &Response) -> String {
+        let set_cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        let cookie = Cookie::parse(set_cookie.to_owned()).unwrap();
+        format!("sid={}", cookie.value())
+    }
+
+    async fn body(res: Response) -> String {
+        let res: crate::http::Response = res.into();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_regenerate() {
+        let middleware: Arc<Vec<Arc<dyn Middleware>>> =
+            Arc::new(vec![Arc::new(SessionMiddleware::new(MemoryStore::new()))]);
+
+        let res = run(&middleware, Arc::new(login), None).await;
+        let planted = session_cookie(&res);
+        let res = run(&middleware, Arc::new(elevate), Some(&planted)).await;
+        let fresh = session_cookie(&res);
+        assert_ne!(fresh, planted);
+
+        let res = run(&middleware, Arc::new(whoami), Some(&fresh)).await;
+        assert_eq!(body(res).await, r#"Some("42") Some("admin")"#);
+        let res = run(&middleware, Arc::new(whoami), Some(&planted)).await;
+        assert_eq!(body(res).await, "None None");
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_ttl() {
+        let mut data = SessionData::new();
+        data.insert(String::from("user"), String::from("42"));
+
+        let store = MemoryStore::new();
+        let id = store.save(None, &data).await.unwrap();
+        assert_eq!(store.load(&id).await.unwrap(), Some(data.clone()));
+
+        let store = MemoryStore::new().ttl(Duration::ZERO);
+        let id = store.save(None, &data).await.unwrap();
+        assert_eq!(store.load(&id).await.unwrap(), None);
+        store.save(None, &data).await.unwrap();
+        store.save(None, &data).await.unwrap();
+        assert_eq!(store.sessions.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cookie_store() {
+        round_trip(CookieStore::new(&[7; 64])).await;
+    }
+
+    #[tokio::test]
+    async fn test_cookie_store_rejects_tampering() {
+        let store = CookieStore::new(&[7; 64]);
+        let mut data = SessionData::new();
+        data.insert(String::from("user"), String::from("42"));
+        let value = store.save(None, &data).await.unwrap();
+        assert_eq!(store.load(&value).await.unwrap(), Some(data));
+
+        let tampered = value.replace("42", "43");
+        assert_eq!(store.load(&tampered).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    convert::Infallible,
+    fmt::Write,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::Stream;
+use hyper::body::Bytes;
+use hyper::header;
+use tokio::time::{Instant, Sleep};
+
+use crate::{IntoResponse, Response};
+
+/// The interval between keep-alive comments by default.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// A server-sent event, serialized with the `event:`, `id:`, `retry:` and
+/// `data:` fields that are set.
+///
+/// ## Examples
+/// ```rust
+/// use std::time::Duration;
+/// use sidemount::sse::Event;
+///
+/// let event = Event::default()
+///     .event("price")
+///     .id("42")
+///     .retry(Duration::from_secs(5))
+///     .data("{\"symbol\":\"ACME\",\"price\":12.5}");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    data: Option<String>,
+    event: Option<String>,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// Sets the data of the event. Each line is sent as its own `data:` field,
+    /// which the browser joins back with newlines.
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Sets the name of the event, dispatched to listeners of that name
+    /// rather than to `onmessage`. Line breaks are removed.
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(single_line(event.into()));
+        self
+    }
+
+    /// Sets the id of the event, sent back by the browser in `Last-Event-ID`
+    /// when reconnecting. Line breaks are removed.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(single_line(id.into()));
+        self
+    }
+
+    /// Sets how long the browser waits before reconnecting after the stream
+    /// is closed.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Serializes the event, ending with the blank line that dispatches it.
+    fn to_bytes(&self) -> Bytes {
+        let mut out = String::new();
+        if let Some(event) = &self.event {
+            let _ = writeln!(out, "event: {}", event);
+        }
+        if let Some(id) = &self.id {
+            let _ = writeln!(out, "id: {}", id);
+        }
+        if let Some(retry) = self.retry {
+            let _ = writeln!(out, "retry: {}", retry.as_millis());
+        }
+        if let Some(data) = &self.data {
+            // `\r\n`, `\r` and `\n` all end a line in an event stream
+            for line in data.replace("\r\n", "\n").split(['\r', '\n']) {
+                let _ = writeln!(out, "data: {}", line);
+            }
+        }
+        out.push('\n');
+        Bytes::from(out)
+    }
+}
+
+/// Removes the line breaks that would end a field early.
+fn single_line(value: String) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+/// A stream of [Event]s answered as a `text/event-stream` response, sending a
+/// keep-alive comment whenever no event was sent for a while so proxies do
+/// not close the idle connection.
+///
+/// ## Examples
+/// ```rust
+/// use std::time::Duration;
+/// use futures::StreamExt;
+/// use sidemount::sse::{Event, EventStream};
+/// use sidemount::Request;
+///
+/// async fn ticks(req: Request) -> EventStream {
+///     let ticks = futures::stream::iter(0..3).map(|n| Event::default().data(n.to_string()));
+///     EventStream::new(ticks).keep_alive(Duration::from_secs(30))
+/// }
+///
+/// let mut app = sidemount::new();
+/// app.at("/ticks").get(ticks);
+/// ```
+pub struct EventStream {
+    events: Pin<Box<dyn Stream<Item = Event> + Send>>,
+    keep_alive: Duration,
+}
+
+impl EventStream {
+    /// Creates a stream of the events, sending a keep-alive comment after 15
+    /// seconds without an event.
+    pub fn new(events: impl Stream<Item = Event> + Send + 'static) -> Self {
+        EventStream {
+            events: Box::pin(events),
+            keep_alive: KEEP_ALIVE,
+        }
+    }
+
+    /// Sets the time without an event after which a keep-alive comment is
+    /// sent.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+}
+
+impl IntoResponse for EventStream {
+    fn into_response(self) -> Response {
+        let body = Framed {
+            timer: Box::pin(tokio::time::sleep(self.keep_alive)),
+            events: self.events,
+            keep_alive: self.keep_alive,
+        };
+        Response::stream(body)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+    }
+}
+
+/// The serialized events, interleaved with keep-alive comments.
+struct Framed {
+    events: Pin<Box<dyn Stream<Item = Event> + Send>>,
+    timer: Pin<Box<Sleep>>,
+    keep_alive: Duration,
+}
+
+impl Stream for Framed {
+    type Item = Result<Bytes, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let next = match self.events.as_mut().poll_next(cx) {
+            Poll::Ready(Some(event)) => event.to_bytes(),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => match self.timer.as_mut().poll(cx) {
+                Poll::Ready(()) => Bytes::from_static(b":\n\n"),
+                Poll::Pending => return Poll::Pending,
+            },
+        };
+        let deadline = Instant::now() + self.keep_alive;
+        self.timer.as_mut().reset(deadline);
+        Poll::Ready(Some(Ok(next)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::http;
+
+    async fn collect(stream: EventStream) -> (http::Response, String) {
+        let res: http::Response = stream.into_response().into();
+        let (parts, body) = res.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        let res = hyper::Response::from_parts(parts, hyper::Body::empty());
+        (res, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_event_stream() {
+        let events = futures::stream::iter(vec![
+            Event::default().data("hello"),
+            Event::default()
+                .event("update")
+                .id("7\n")
+                .retry(Duration::from_secs(3))
+                .data("line one\nline two"),
+        ]);
+        let (res, body) = collect(EventStream::new(events)).await;
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/event-stream");
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+        assert_eq!(
+            body,
+            "data: hello\n\n\
+             event: update\nid: 7\nretry: 3000\ndata: line one\ndata: line two\n\n"
+        );
+    }
+
+    #[test]
+    fn test_event_data_line_breaks() {
+        let event = Event::default().data("a\rid: forged\r\nb\nc\r");
+        assert_eq!(
+            event.to_bytes(),
+            "data: a\ndata: id: forged\ndata: b\ndata: c\ndata: \n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive() {
+        let late = futures::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Event::default().data("late")
+        });
+        let events = futures::stream::iter(vec![Event::default().data("early")]).chain(late);
+        let stream = EventStream::new(events).keep_alive(Duration::from_millis(20));
+        let (_, body) = collect(stream).await;
+        assert!(body.starts_with("data: early\n\n:\n\n"));
+        assert!(body.ends_with(":\n\ndata: late\n\n"));
+    }
+}
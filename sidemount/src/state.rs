@@ -0,0 +1,22 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::Arc,
+};
+
+/// Application state shared by every request, keyed by type.
+#[derive(Default)]
+pub(crate) struct StateMap(HashMap<TypeId, Arc<dyn Any + Send + Sync>>);
+
+impl StateMap {
+    /// Inserts the state, replacing any previous state of the same type.
+    pub fn insert<S: Send + Sync + 'static>(&mut self, state: S) {
+        self.0.insert(TypeId::of::<S>(), Arc::new(state));
+    }
+
+    /// Gets a shared reference to the state of the given type.
+    pub fn get<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        let state = self.0.get(&TypeId::of::<S>())?.clone();
+        state.downcast().ok()
+    }
+}
@@ -0,0 +1,176 @@
+use std::{fs::File, io, io::BufReader, path::Path, time::Duration};
+
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio::net::TcpStream;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+use crate::ConnectionInfo;
+
+/// Loads the TLS configuration for [crate::Server::listen_tls].
+pub struct TlsConfig;
+
+impl TlsConfig {
+    /// Loads a server config from a PEM file with the certificate chain,
+    /// leaf first, and a PEM file with its PKCS#8, RSA or SEC1 private key.
+    /// The config offers HTTP/2 and HTTP/1.1 through ALPN.
+    ///
+    /// ## Examples
+    /// ```rust,no_run
+    /// use sidemount::TlsConfig;
+    ///
+    /// # fn main() -> sidemount::Result<()> {
+    /// let config = TlsConfig::from_pem("cert.pem", "key.pem")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_pem(
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> crate::Result<ServerConfig> {
+        let cert_path = cert_path.as_ref();
+        let mut certs = BufReader::new(File::open(cert_path)?);
+        let certs = rustls_pemfile::certs(&mut certs)?;
+        if certs.is_empty() {
+            return Err(format!("No certificates found in {}", cert_path.display()).into());
+        }
+
+        let key_path = key_path.as_ref();
+        let mut keys = BufReader::new(File::open(key_path)?);
+        let key = loop {
+            match rustls_pemfile::read_one(&mut keys)? {
+                Some(rustls_pemfile::Item::PKCS8Key(key))
+                | Some(rustls_pemfile::Item::RSAKey(key))
+                | Some(rustls_pemfile::Item::ECKey(key)) => break key,
+                Some(_) => continue,
+                None => {
+                    return Err(format!("No private key found in {}", key_path.display()).into())
+                }
+            }
+        };
+
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                certs.into_iter().map(Certificate).collect(),
+                PrivateKey(key),
+            )?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+/// How long a client has to complete the TLS handshake, so stalled clients
+/// cannot hold connections open.
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Completes the TLS handshake of an accepted connection within the timeout,
+/// adding the TLS details to its connection info.
+pub(crate) async fn accept(
+    acceptor: TlsAcceptor,
+    timeout: Duration,
+    stream: TcpStream,
+    mut info: ConnectionInfo,
+) -> io::Result<(TlsStream<TcpStream>, ConnectionInfo)> {
+    let stream = tokio::time::timeout(timeout, acceptor.accept(stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))??;
+    let (_, conn) = stream.get_ref();
+    info.server_name = conn.server_name().map(String::from);
+    info.alpn_protocol = conn.alpn_protocol().map(<[u8]>::to_vec);
+    info.tls_version = conn.protocol_version().and_then(|version| version.as_str());
+    Ok((stream, info))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hyper::Body;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{Request, Response, Server};
+
+    async fn server_name(req: Request) -> Response {
+        let info = req.connection_info().unwrap();
+        Response::text(format!(
+            "{} {}",
+            info.server_name.as_deref().unwrap_or("-"),
+            info.tls_version.unwrap_or("-")
+        ))
+    }
+
+    /// Writes a self-signed certificate for `localhost` and its key to PEM
+    /// files, returning their paths along with the DER certificate.
+    fn self_signed() -> (std::path::PathBuf, std::path::PathBuf, Vec<u8>) {
+        let cert = rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+        let dir = std::env::temp_dir().join(format!("sidemount-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+        (cert_path, key_path, cert.serialize_der().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_https_request() {
+        let (cert_path, key_path, der) = self_signed();
+        let config = TlsConfig::from_pem(&cert_path, &key_path).unwrap();
+        assert!(TlsConfig::from_pem(&key_path, &key_path).is_err());
+        assert!(TlsConfig::from_pem(&cert_path, &cert_path).is_err());
+
+        let mut server = Server::new();
+        server.at("/").get(server_name);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let serving = tokio::spawn(server.serve_tls(listener, config));
+
+        // A failed handshake drops the connection without stopping the server.
+        let mut garbage = TcpStream::connect(addr).await.unwrap();
+        garbage.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&Certificate(der)).unwrap();
+        let client = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client));
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let name = rustls::ServerName::try_from("localhost").unwrap();
+        let stream = connector.connect(name, stream).await.unwrap();
+
+        let (mut sender, conn) = hyper::client::conn::handshake(stream).await.unwrap();
+        tokio::spawn(conn);
+        let req = hyper::Request::get("/").body(Body::empty()).unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"localhost TLSv1_3");
+        assert!(!serving.is_finished());
+        serving.abort();
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout() {
+        let (cert_path, key_path, _) = self_signed();
+        let config = TlsConfig::from_pem(&cert_path, &key_path).unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _stalled = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let accepted = accept(
+            acceptor,
+            Duration::from_millis(10),
+            stream,
+            ConnectionInfo::default(),
+        )
+        .await;
+        assert_eq!(accepted.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,73 @@
+//! Counts the heap allocations made while running a request through the
+//! middleware chain, as handlers and middleware are called through trait
+//! objects whose futures `async_trait` boxes.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sidemount::{Middleware, Next, Request, Response};
+
+/// Counts the allocations made on the current thread.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+struct Pass;
+
+#[async_trait]
+impl Middleware for Pass {
+    async fn handle(&self, req: Request, next: Next) -> Response {
+        next.run(req).await
+    }
+}
+
+async fn ok(_req: Request) -> Response {
+    Response::default()
+}
+
+/// Runs a request through the given number of middleware, returning the
+/// allocations made by [Next::run] alone.
+fn allocations(layers: usize) -> usize {
+    let middleware: Vec<Arc<dyn Middleware>> = (0..layers)
+        .map(|_| Arc::new(Pass) as Arc<dyn Middleware>)
+        .collect();
+    let next = Next::new(Arc::new(middleware), Arc::new(ok));
+    let req = hyper::Request::get("/").body(hyper::Body::empty()).unwrap();
+    let req = Request::new(req, HashMap::new());
+
+    let before = ALLOCATIONS.with(Cell::get);
+    let res = futures::executor::block_on(next.run(req));
+    let after = ALLOCATIONS.with(Cell::get);
+    drop(res);
+    after - before
+}
+
+#[test]
+fn test_one_boxed_future_per_layer() {
+    // The first run sets up the thread-local state of the executor.
+    allocations(0);
+
+    let handler = allocations(0);
+    assert_eq!(handler, 1, "the handler future is boxed once");
+    assert_eq!(allocations(1), handler + 1);
+    assert_eq!(allocations(3), handler + 3);
+}